@@ -0,0 +1,29 @@
+//! Minimal offline server for manual testing with a real client, echoing chat back to the sender.
+//!
+//! Run with `cargo run --example echo --features echo`, then join `localhost`.
+
+use soulflame::cfg::SoulflameConfiguration;
+use soulflame::network::echo::EchoHandler;
+use soulflame::network::NetworkListener;
+use soulflame::DEFAULT_REGISTRY_CODEC;
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        ..Default::default()
+    };
+    if let Some(dir) = config.registry_codec.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    if !config.registry_codec.exists() {
+        tokio::fs::write(&config.registry_codec, DEFAULT_REGISTRY_CODEC).await?;
+    }
+
+    let mut listener = NetworkListener::bind("127.0.0.1".into(), 25565, config).await?;
+    listener.set_handler(Arc::new(EchoHandler));
+    println!("Echo server listening on {}", listener.local_addr()?);
+    listener.run().await;
+    Ok(())
+}
@@ -1,13 +1,179 @@
+use crate::network::auth::ServerKeys;
+use crate::network::encode::MAX_PACKET_SIZE;
+use crate::protocol::client::handshake::{
+    FORWARDING_MAX_HOSTNAME_LENGTH, MAX_SANE_PROTOCOL_VERSION, VANILLA_MAX_HOSTNAME_LENGTH,
+};
+use crate::protocol::server::play::{GameMode, ServerLink, ServerLinkLabel, ServerLinkType};
+use crate::{DEFAULT_FAVICON, SERVER_BRAND, SERVER_VERSION_NAME};
+use anyhow::bail;
+use log::{error, info, warn};
+use nbt::Blob;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use uuid::Uuid;
 
+/// Server configuration, read from a TOML file where missing keys take their default value.
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SoulflameConfiguration {
     pub max_players: u32,
     pub motd: String,
     pub favicon: PathBuf,
+    /// NBT file with the registry codec sent to joining players, logins are refused without it.
+    pub registry_codec: PathBuf,
+    /// Upper bound for the handshake protocol version, `None` disables the check.
+    pub max_protocol_version: Option<u32>,
+    /// Only allow logins from clients transferred here by another server.
+    pub accept_transfers_only: bool,
+    /// Brand reported to clients instead of the real one.
+    pub spoof_brand: Option<String>,
+    /// Version name shown in the server list instead of the real one.
+    pub spoof_version_name: Option<String>,
+    /// Game mode new players join in, e.g. `"creative"`.
+    #[serde_as(as = "DisplayFromStr")]
+    pub default_gamemode: GameMode,
+    /// If not empty, only addresses in one of these ranges may connect, e.g. `"10.0.0.0/8"`.
+    pub allow_cidrs: Vec<String>,
+    /// Addresses in these ranges are refused, even if they are allowed.
+    pub deny_cidrs: Vec<String>,
+    /// What happens when an account logs in while it is already online.
+    pub duplicate_login: DuplicateLogin,
+    /// Packet handlers running longer than this many milliseconds are logged, `None` disables it.
+    pub slow_handler_threshold_ms: Option<u64>,
+    /// Packets of at least this many bytes are compressed, a negative value disables compression.
+    pub compression_threshold: i32,
+    /// How the inbound and outgoing packet channels of a connection are scheduled.
+    pub channel_scheduling: ChannelScheduling,
+    /// Show the default favicon instead of an animated PNG, rather than only warning about it.
+    pub reject_animated_favicon: bool,
+    /// OS level keep-alive probing of accepted connections, to notice dead peers early.
+    pub tcp_keepalive: TcpKeepaliveConfig,
+    /// Log status pings and clean disconnects of each connection, errors are always logged.
+    pub log_connections: bool,
+    /// Trust player data forwarded by a BungeeCord proxy in the handshake instead of authenticating.
+    pub bungeecord_forwarding: bool,
+    /// Longest accepted handshake server address, by default depending on whether forwarding is enabled.
+    pub max_hostname_length: Option<usize>,
+    /// Links shown in the pause menu of 1.21+ clients.
+    pub server_links: Vec<ServerLinkConfig>,
+    /// Authenticate players with Mojang, offline mode derives their UUID from the username.
+    pub online_mode: bool,
+    /// Connections per address that may be in the handshake or status state at the same time.
+    pub max_pending_per_ip: usize,
+    /// Retries of the session server request authenticating online mode logins.
+    pub session_server: SessionServerConfig,
+    /// Keep answering status requests, but refuse logins not on the bypass list.
+    pub maintenance: bool,
+    /// MOTD shown in the server list during maintenance.
+    pub maintenance_motd: String,
+    /// Reason players are disconnected with during maintenance.
+    pub maintenance_kick_message: String,
+    /// Usernames or UUIDs that may still log in during maintenance.
+    pub maintenance_bypass: Vec<String>,
+    /// Reason players are disconnected with when the server shuts down.
+    pub shutdown_message: String,
+    /// Largest packet in bytes accepted from clients, before and after decompression.
+    pub max_packet_size: usize,
+    /// Milliseconds a client may stay silent before it is disconnected, `0` disables it.
+    pub read_timeout_ms: u64,
+    /// Online players listed when hovering the player count in the server list.
+    pub sample_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TcpKeepaliveConfig {
+    pub enabled: bool,
+    /// Seconds a connection stays idle before the first probe.
+    pub idle_secs: u64,
+    /// Seconds between unanswered probes.
+    pub interval_secs: u64,
+    /// Unanswered probes before the connection is dropped.
+    pub retries: u32,
+}
+
+impl Default for TcpKeepaliveConfig {
+    fn default() -> Self {
+        TcpKeepaliveConfig {
+            enabled: true,
+            idle_secs: 30,
+            interval_secs: 10,
+            retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionServerConfig {
+    /// Requests sent at most, including the first one.
+    pub attempts: u32,
+    /// Milliseconds a single request may take before it is retried.
+    pub request_timeout_ms: u64,
+    /// Milliseconds to wait before the first retry, doubled after every retry.
+    pub initial_backoff_ms: u64,
+    /// Milliseconds all attempts together may take, so a player is not stuck logging in.
+    pub total_timeout_ms: u64,
+}
+
+impl Default for SessionServerConfig {
+    fn default() -> Self {
+        SessionServerConfig {
+            attempts: 3,
+            request_timeout_ms: 5000,
+            initial_backoff_ms: 250,
+            total_timeout_ms: 10000,
+        }
+    }
+}
+
+impl SessionServerConfig {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+
+    pub fn initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.initial_backoff_ms)
+    }
+
+    pub fn total_timeout(&self) -> Duration {
+        Duration::from_millis(self.total_timeout_ms)
+    }
+}
+
+/// Scheduling of the two packet channels of a connection.
+///
+/// `Shared` polls both directions from one task, which is cheaper but lets a busy writer
+/// delay reads between packets. `Split` runs each direction as its own task, so the runtime
+/// can read and write in parallel at the cost of an extra task per connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelScheduling {
+    Shared,
+    Split,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateLogin {
+    /// Disconnect the session that is already online.
+    Kick,
+    /// Refuse the new login.
+    Deny,
+}
+
+/// Server link shown in the pause menu.
+///
+/// Labels naming a built-in link type, like `"bug_report"`, are translated by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerLinkConfig {
+    pub label: String,
+    pub url: String,
 }
 
 impl Default for SoulflameConfiguration {
@@ -16,28 +182,263 @@ impl Default for SoulflameConfiguration {
             max_players: 20,
             motd: "<dark_gray>A Soulflame server.".to_string(),
             favicon: Path::new("./soulflame/favicon.png").to_path_buf(),
+            registry_codec: Path::new("./soulflame/registry_codec.nbt").to_path_buf(),
+            max_protocol_version: Some(MAX_SANE_PROTOCOL_VERSION),
+            accept_transfers_only: false,
+            spoof_brand: None,
+            spoof_version_name: None,
+            default_gamemode: GameMode::Survival,
+            allow_cidrs: vec![],
+            deny_cidrs: vec![],
+            duplicate_login: DuplicateLogin::Kick,
+            slow_handler_threshold_ms: None,
+            compression_threshold: 256,
+            channel_scheduling: ChannelScheduling::Shared,
+            reject_animated_favicon: false,
+            tcp_keepalive: TcpKeepaliveConfig::default(),
+            log_connections: true,
+            bungeecord_forwarding: false,
+            max_hostname_length: None,
+            server_links: vec![],
+            online_mode: true,
+            max_pending_per_ip: 5,
+            session_server: SessionServerConfig::default(),
+            maintenance: false,
+            maintenance_motd: "<gold>The server is under maintenance.".to_string(),
+            maintenance_kick_message: "<gold>The server is under maintenance, try again later!"
+                .to_string(),
+            maintenance_bypass: vec![],
+            shutdown_message: "<red>Server closing".to_string(),
+            max_packet_size: MAX_PACKET_SIZE,
+            read_timeout_ms: 30_000,
+            sample_size: 12,
+        }
+    }
+}
+
+impl SoulflameConfiguration {
+    /// Reads the configuration file, writing the default configuration to it if it is missing.
+    ///
+    /// A malformed file is logged and left as it is, the default configuration is used instead.
+    pub async fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let config = SoulflameConfiguration::default();
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(path, config.to_toml()?).await?;
+                info!("Created default configuration {:?}", path);
+                return Ok(config);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => Ok(config),
+            Err(e) => {
+                error!(
+                    "Could not parse configuration {:?}, using the default one: {}",
+                    path, e
+                );
+                Ok(SoulflameConfiguration::default())
+            }
+        }
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        // going through a value writes plain keys before tables, as TOML requires
+        Ok(toml::to_string_pretty(&toml::Value::try_from(self)?)?)
+    }
+
+    /// Brand advertised to clients.
+    pub fn brand(&self) -> &str {
+        self.spoof_brand.as_deref().unwrap_or(SERVER_BRAND)
+    }
+
+    /// Timeout of reads from clients, `None` if it is disabled.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        match self.read_timeout_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Compression threshold for the packet channels, `None` if compression is disabled.
+    pub fn compression(&self) -> Option<usize> {
+        usize::try_from(self.compression_threshold).ok()
+    }
+
+    pub fn server_links(&self) -> Vec<ServerLink> {
+        self.server_links
+            .iter()
+            .map(|link| {
+                let label = match ServerLinkType::from_name(&link.label) {
+                    Some(link_type) => ServerLinkLabel::BuiltIn(link_type),
+                    None => ServerLinkLabel::Custom(link.label.clone()),
+                };
+                ServerLink::new(label, link.url.clone())
+            })
+            .collect()
+    }
+
+    /// Longest accepted handshake server address, raised by default for proxy forwarding.
+    pub fn max_hostname_length(&self) -> usize {
+        match self.max_hostname_length {
+            Some(max) => max,
+            None if self.bungeecord_forwarding => FORWARDING_MAX_HOSTNAME_LENGTH,
+            None => VANILLA_MAX_HOSTNAME_LENGTH,
         }
     }
+
+    /// Whether the player is on the maintenance bypass list, usernames are case insensitive.
+    pub fn bypasses_maintenance(&self, uuid: &Uuid, name: &str) -> bool {
+        self.maintenance_bypass.iter().any(|entry| {
+            entry.eq_ignore_ascii_case(name)
+                || Uuid::parse_str(entry).map_or(false, |bypass| &bypass == uuid)
+        })
+    }
+
+    /// How long a packet handler may run before it is logged as slow, `None` if disabled.
+    pub fn slow_handler_threshold(&self) -> Option<Duration> {
+        self.slow_handler_threshold_ms.map(Duration::from_millis)
+    }
+
+    /// Version name advertised in status responses.
+    pub fn version_name(&self) -> &str {
+        self.spoof_version_name
+            .as_deref()
+            .unwrap_or(SERVER_VERSION_NAME)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RuntimeConfiguration {
-    pub favicon: String,
+    pub favicon: Option<String>,
+    /// Key pair for online mode logins, only generated if online mode is enabled.
+    pub keys: Option<Arc<ServerKeys>>,
+    /// Registry codec for the join game packet, `None` if it could not be loaded.
+    pub registry_codec: Option<Arc<Blob>>,
+    /// How long reads from a client may take, `None` if they never time out.
+    pub read_timeout: Option<Duration>,
 }
 
 impl RuntimeConfiguration {
     pub async fn from_cfg(cfg: &SoulflameConfiguration) -> anyhow::Result<Self> {
-        let mut favicon = File::open(&cfg.favicon).await?;
-        let mut buf = vec![];
-        favicon.read_to_end(&mut buf).await?;
+        let favicon = match favicon_from_cfg(cfg).await {
+            Ok(favicon) => Some(favicon),
+            Err(e) => {
+                warn!("Skipping favicon {:?}: {}", cfg.favicon, e);
+                None
+            }
+        };
+
+        let keys = if cfg.online_mode {
+            Some(Arc::new(ServerKeys::generate()?))
+        } else {
+            None
+        };
+
+        let registry_codec = match load_registry_codec(&cfg.registry_codec).await {
+            Ok(codec) => Some(Arc::new(codec)),
+            Err(e) => {
+                error!(
+                    "Could not load registry codec {:?}, players will not be able to join: {}",
+                    cfg.registry_codec, e
+                );
+                None
+            }
+        };
 
         Ok(RuntimeConfiguration {
-            favicon: build_favicon(&buf[..]),
+            favicon,
+            keys,
+            registry_codec,
+            read_timeout: cfg.read_timeout(),
         })
     }
 }
 
-fn build_favicon(bytes: &[u8]) -> String {
+async fn load_registry_codec(path: &Path) -> anyhow::Result<Blob> {
+    let mut file = File::open(path).await?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf).await?;
+    Ok(Blob::from_reader(&mut Cursor::new(buf))?)
+}
+
+/// Loads and checks the configured favicon, returning it as the data URL sent in status responses.
+pub async fn favicon_from_cfg(cfg: &SoulflameConfiguration) -> anyhow::Result<String> {
+    let buf = load_favicon(&cfg.favicon).await?;
+    if buf.is_empty() {
+        bail!("Favicon file is empty")
+    }
+    if !buf.starts_with(&PNG_SIGNATURE) {
+        bail!("Favicon is not a PNG image")
+    }
+    if is_animated_png(&buf) {
+        if cfg.reject_animated_favicon {
+            warn!(
+                "Favicon {:?} is animated, showing the default favicon instead",
+                cfg.favicon
+            );
+            return Ok(build_favicon(DEFAULT_FAVICON));
+        }
+        warn!(
+            "Favicon {:?} is animated, clients only show its first frame",
+            cfg.favicon
+        );
+    }
+    Ok(build_favicon(&buf))
+}
+
+pub(crate) async fn load_favicon(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut favicon = File::open(path).await?;
+    let mut buf = vec![];
+    favicon.read_to_end(&mut buf).await?;
+    Ok(buf)
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Width and height of favicons, clients drop favicons of any other size.
+pub const FAVICON_SIZE: u32 = 64;
+
+/// Width and height of the PNG image, read from its `IHDR` chunk which always comes first.
+pub(crate) fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let header = bytes.get(PNG_SIGNATURE.len()..PNG_SIGNATURE.len() + 16)?;
+    if &header[4..8] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let height = u32::from_be_bytes([header[12], header[13], header[14], header[15]]);
+    Some((width, height))
+}
+
+/// Whether the image is an APNG, which declares its animation in an `acTL` chunk before any image data.
+pub(crate) fn is_animated_png(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return false;
+    }
+
+    let mut offset = PNG_SIGNATURE.len();
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        match &header[4..] {
+            b"acTL" => return true,
+            b"IDAT" | b"IEND" => return false,
+            _ => {}
+        }
+        // chunk header, data and crc
+        offset += 12 + length;
+    }
+    false
+}
+
+pub(crate) fn build_favicon(bytes: &[u8]) -> String {
     let b = base64::encode(bytes);
     format!("data:image/png;base64,{}", b)
 }
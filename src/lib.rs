@@ -0,0 +1,23 @@
+#![feature(fn_traits)]
+
+pub mod cfg;
+pub mod chat;
+pub mod net_io;
+pub mod network;
+pub mod protocol;
+#[cfg(test)]
+pub mod tests;
+pub mod util;
+pub mod world;
+
+pub static LATEST_PROTOCOL_VERSION: u32 = 759;
+/// Protocol versions clients can join with.
+pub static SUPPORTED_PROTOCOLS: &[u32] = &[759];
+pub static SERVER_BRAND: &str = "soulflame";
+pub static SERVER_VERSION_NAME: &str = "Soulflame 1.19";
+pub static CONFIG_PATH: &str = "./soulflame.toml";
+
+/// Favicon extracted on first start, and shown instead of a configured favicon that is refused.
+pub static DEFAULT_FAVICON: &[u8] = include_bytes!("../res/favicon.png");
+/// Registry codec sent to joining clients, holding the overworld, plains and the 1.19 chat types.
+pub static DEFAULT_REGISTRY_CODEC: &[u8] = include_bytes!("../res/registry_codec.nbt");
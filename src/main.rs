@@ -1,23 +1,8 @@
-#![feature(fn_traits)]
-
-pub mod cfg;
-pub mod chat;
-pub mod net_io;
-pub mod network;
-pub mod protocol;
-#[cfg(test)]
-pub mod tests;
-pub mod util;
-pub mod world;
-
 use std::io::Cursor;
 use std::path::Path;
 
 use log::{error, info, LevelFilter};
 use log4rs::append::console::ConsoleAppender;
-
-use crate::cfg::SoulflameConfiguration;
-use crate::network::NetworkListener;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
@@ -25,48 +10,73 @@ use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::{init_config, Config};
+use soulflame::cfg::SoulflameConfiguration;
+use soulflame::network::NetworkListener;
+use soulflame::{
+    CONFIG_PATH, DEFAULT_FAVICON, DEFAULT_REGISTRY_CODEC, SERVER_BRAND, SERVER_VERSION_NAME,
+};
 use tokio::fs::create_dir_all;
 
-pub static LATEST_PROTOCOL_VERSION: u32 = 759;
-
 #[tokio::main]
 async fn main() {
     extract_resources().await;
 
     configure_logging().await;
 
-    info!("Starting SoulFlame server...");
-
-    let config = SoulflameConfiguration {
-        max_players: 100,
-        motd: "<aqua>The best server ever".to_string(),
-        favicon: Path::new("./soulflame/favicon.png").to_path_buf(),
+    info!(
+        "Starting SoulFlame server ({}, {})...",
+        SERVER_BRAND, SERVER_VERSION_NAME
+    );
+
+    let config = match SoulflameConfiguration::load_or_create(Path::new(CONFIG_PATH)).await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Could not load configuration! Error: {}", e);
+            return;
+        }
     };
-    let result = NetworkListener::init("127.0.0.1".into(), 25565, config).await;
-    if let Err(e) = result {
-        error!("Failed starting network listener! Error: {}", e)
+    match NetworkListener::bind("127.0.0.1".into(), 25565, config).await {
+        Ok(listener) => {
+            let shutdown = listener.shutdown_handle();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    info!("Received Ctrl-C, shutting down...");
+                    shutdown.shutdown();
+                }
+            });
+            listener.run().await;
+        }
+        Err(e) => error!("Failed starting network listener! Error: {}", e),
     }
 
     info!("Closing server...");
 }
 
 async fn extract_resources() {
-    let path = Path::new("./soulflame/favicon.png");
-    if path.exists() {
-        return;
-    }
     create_dir_all("./soulflame")
         .await
         .expect("Could not create directories");
-    let mut bytes = Cursor::new(include_bytes!("../res/favicon.png"));
+    extract_resource(Path::new("./soulflame/favicon.png"), DEFAULT_FAVICON).await;
+    extract_resource(
+        Path::new("./soulflame/registry_codec.nbt"),
+        DEFAULT_REGISTRY_CODEC,
+    )
+    .await;
+}
+
+async fn extract_resource(path: &Path, contents: &[u8]) {
+    if path.exists() {
+        return;
+    }
+    let mut bytes = Cursor::new(contents);
     tokio::io::copy(
         &mut bytes,
         &mut tokio::fs::File::create(path)
             .await
-            .expect("Could not create favicon file!"),
+            .unwrap_or_else(|e| panic!("Could not create {:?}: {}", path, e)),
     )
     .await
-    .expect("Could not copy default favicon!");
+    .unwrap_or_else(|e| panic!("Could not copy default {:?}: {}", path, e));
 }
 
 async fn configure_logging() {
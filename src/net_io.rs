@@ -1,12 +1,18 @@
 #![allow(unused_variables)]
 
+pub mod hexdump;
+pub mod nbt;
 pub mod packet;
 
 use crate::util::Identifier;
+use ::nbt::Blob;
 use anyhow::bail;
 use async_trait::async_trait;
+use bytes::BufMut;
 use log::error;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::io::Cursor;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::{Bytes, Uuid};
@@ -16,6 +22,28 @@ pub trait PacketWrite: Sized {
     async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()>;
 }
 
+/// Writes packets into any [`BufMut`], e.g. a pooled `BytesMut`, instead of a `Vec<u8>`.
+///
+/// Implemented for every [`PacketWrite`] type.
+#[async_trait]
+pub trait PacketWriteBuf: PacketWrite + Sync {
+    /// Writes the packet through `staging`, which is cleared first and can be reused for every
+    /// packet written into the buffer.
+    async fn pack_write_buf<B: BufMut + Send>(
+        &self,
+        buffer: &mut B,
+        staging: &mut Vec<u8>,
+        target_version: u32,
+    ) -> anyhow::Result<()> {
+        staging.clear();
+        self.pack_write(staging, target_version).await?;
+        buffer.put_slice(staging);
+        Ok(())
+    }
+}
+
+impl<T: PacketWrite + Sync> PacketWriteBuf for T {}
+
 #[async_trait]
 pub trait PacketRead: Sized {
     async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self>;
@@ -51,10 +79,10 @@ __primitive_impl!(
     read_f32, f64, write_f64, read_f64
 );
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct VarInt(pub i32);
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct VarLong(pub i64);
 
 impl From<i32> for VarInt {
@@ -105,27 +133,24 @@ impl PacketWrite for VarInt {
 #[async_trait]
 impl PacketRead for VarInt {
     async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
-        let mut size = 0;
-        let mut v = 0;
+        let mut v = 0i32;
 
-        loop {
+        for i in 0..5 {
             let r = buffer.read_u8().await?;
-            let value = i32::from(r & 0b0111_1111);
-            v = v | value.overflowing_shl(7 * size).0;
-
-            size += 1;
+            v |= i32::from(r & 0b0111_1111) << (7 * i);
 
-            if size > 5 {
+            // the fifth byte only holds the top four bits and may not continue
+            if i == 4 && r & 0b1111_0000 != 0 {
                 error!("VarInt too long (max size: 5, read: {}", v);
                 bail!("VarInt too long (max size: 5, read: {}", v);
             }
 
             if r & 0b1000_0000 == 0 {
-                break;
+                return Ok(VarInt(v));
             }
         }
 
-        Ok(VarInt(v))
+        unreachable!("the fifth byte always ends the VarInt")
     }
 }
 
@@ -177,6 +202,121 @@ impl PacketRead for VarLong {
     }
 }
 
+/// Registry reference that is either an id, or an inline value.
+///
+/// Ids are sent shifted by one, with `0` followed by the inline value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IdOr<T> {
+    Id(i32),
+    Inline(T),
+}
+
+#[async_trait]
+impl<T> PacketWrite for IdOr<T>
+where
+    T: PacketWrite + Send + Sync,
+{
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match self {
+            IdOr::Id(id) => VarInt(id + 1).pack_write(buffer, target_version).await,
+            IdOr::Inline(value) => {
+                VarInt(0).pack_write(buffer, target_version).await?;
+                value.pack_write(buffer, target_version).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T> PacketRead for IdOr<T>
+where
+    T: PacketRead + Send,
+{
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let id = VarInt::pack_read(buffer, target_version).await?.0;
+        if id != 0 {
+            return Ok(IdOr::Id(id - 1));
+        }
+        Ok(IdOr::Inline(T::pack_read(buffer, target_version).await?))
+    }
+}
+
+/// Block position, packed into 26 bits for x and z and 12 bits for y.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Packs the coordinates into a single long, bits outside of each coordinate's range are lost.
+    pub fn packed(&self) -> i64 {
+        ((self.x as i64 & 0x3FFFFFF) << 38)
+            | ((self.z as i64 & 0x3FFFFFF) << 12)
+            | (self.y as i64 & 0xFFF)
+    }
+
+    /// Unpacks a long written by [`Position::packed`], sign extending every coordinate.
+    pub fn unpack(packed: i64) -> Self {
+        Self {
+            x: (packed >> 38) as i32,
+            y: (packed << 52 >> 52) as i32,
+            z: (packed << 26 >> 38) as i32,
+        }
+    }
+}
+
+#[async_trait]
+impl PacketWrite for Position {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        self.packed().pack_write(buffer, target_version).await
+    }
+}
+
+#[async_trait]
+impl PacketRead for Position {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        Ok(Position::unpack(
+            i64::pack_read(buffer, target_version).await?,
+        ))
+    }
+}
+
+/// Rotation angle, stored in steps of 1/256 of a full turn.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Angle(pub u8);
+
+impl Angle {
+    /// Rounds down to the next step, wrapping around so that e.g. 360° and -360° are 0.
+    pub fn from_degrees(degrees: f32) -> Self {
+        // wrapping the steps instead of the degrees, as 360° minus a tiny bit rounds to 360.0
+        Angle(((degrees * 256.0 / 360.0).floor() as i64).rem_euclid(256) as u8)
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.0 as f32 * 360.0 / 256.0
+    }
+}
+
+#[async_trait]
+impl PacketWrite for Angle {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        self.0.pack_write(buffer, target_version).await
+    }
+}
+
+#[async_trait]
+impl PacketRead for Angle {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        Ok(Angle(u8::pack_read(buffer, target_version).await?))
+    }
+}
+
 #[async_trait]
 impl<T> PacketWrite for Option<T>
 where
@@ -312,8 +452,53 @@ impl PacketWrite for Uuid {
     }
 }
 
+#[async_trait]
+impl PacketRead for Blob {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        Blob::from_reader(buffer).map_err(anyhow::Error::from)
+    }
+}
+
+#[async_trait]
+impl PacketWrite for Blob {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        self.to_writer(buffer).map_err(anyhow::Error::from)
+    }
+}
+
 const MAX_ARRAY_SIZE: usize = 1024 * 1024; // 2^20
 
+/// Max amount of bytes allocated up front for a length-prefixed array.
+///
+/// The declared length comes from the client, so arrays above this only grow as their elements
+/// are actually read, which keeps a bogus length from reserving memory it never fills.
+pub const MAX_PREALLOCATION_BYTES: usize = 64 * 1024;
+
+fn check_array_size(size: usize, action: &str) -> anyhow::Result<()> {
+    if size > MAX_ARRAY_SIZE {
+        error!(
+            "Tried to {} array of size {}, which is larger than max size ({})",
+            action, size, MAX_ARRAY_SIZE
+        );
+        bail!(
+            "Tried to {} array of size {}, which is larger than max size ({})",
+            action,
+            size,
+            MAX_ARRAY_SIZE
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn preallocate<T>(declared: usize) -> Vec<T> {
+    Vec::with_capacity(preallocation::<T>(declared))
+}
+
+fn preallocation<T>(declared: usize) -> usize {
+    let cap = MAX_PREALLOCATION_BYTES / std::mem::size_of::<T>().max(1);
+    declared.min(cap)
+}
+
 #[async_trait]
 impl<T> PacketRead for Vec<T>
 where
@@ -321,20 +506,9 @@ where
 {
     async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
         let size = VarInt::pack_read(buffer, target_version).await?.0 as usize;
+        check_array_size(size, "read")?;
 
-        if size > MAX_ARRAY_SIZE {
-            error!(
-                "Tried to read array of size {}, which is larger than max size ({})",
-                size, MAX_ARRAY_SIZE
-            );
-            bail!(
-                "Tried to read array of size {}, which is larger than max size ({})",
-                size,
-                MAX_ARRAY_SIZE
-            );
-        }
-
-        let mut vals = vec![];
+        let mut vals = preallocate(size);
 
         for _ in 0..size {
             vals.push(T::pack_read(buffer, target_version).await?);
@@ -351,18 +525,7 @@ where
 {
     async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
         let size = self.len();
-
-        if size > MAX_ARRAY_SIZE {
-            error!(
-                "Tried to write array of size {}, which is larger than max size ({})",
-                size, MAX_ARRAY_SIZE
-            );
-            bail!(
-                "Tried to write array of size {}, which is larger than max size ({})",
-                size,
-                MAX_ARRAY_SIZE
-            );
-        }
+        check_array_size(size, "write")?;
 
         VarInt(size as i32)
             .pack_write(buffer, target_version)
@@ -376,6 +539,114 @@ where
     }
 }
 
+/// Maps are sent as a VarInt entry count followed by each key and its value.
+#[async_trait]
+impl<K, V> PacketRead for HashMap<K, V>
+where
+    K: PacketRead + Eq + Hash + Send,
+    V: PacketRead + Send,
+{
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let size = VarInt::pack_read(buffer, target_version).await?.0 as usize;
+        check_array_size(size, "read")?;
+
+        let mut map = HashMap::with_capacity(preallocation::<(K, V)>(size));
+        for _ in 0..size {
+            let key = K::pack_read(buffer, target_version).await?;
+            let value = V::pack_read(buffer, target_version).await?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+/// Entries are written in key order, so equal maps are always written the same.
+#[async_trait]
+impl<K, V> PacketWrite for HashMap<K, V>
+where
+    K: PacketWrite + Ord + Send + Sync,
+    V: PacketWrite + Send + Sync,
+{
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        check_array_size(self.len(), "write")?;
+        VarInt(self.len() as i32)
+            .pack_write(buffer, target_version)
+            .await?;
+
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (key, value) in entries {
+            key.pack_write(buffer, target_version).await?;
+            value.pack_write(buffer, target_version).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Arrays are exactly `N` elements without a length prefix.
+#[async_trait]
+impl<T, const N: usize> PacketRead for [T; N]
+where
+    T: PacketRead + Send,
+{
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let mut vals = preallocate(N);
+        for _ in 0..N {
+            vals.push(T::pack_read(buffer, target_version).await?);
+        }
+
+        match vals.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N elements were read"),
+        }
+    }
+}
+
+#[async_trait]
+impl<T, const N: usize> PacketWrite for [T; N]
+where
+    T: PacketWrite + Send + Sync,
+{
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        for v in self {
+            v.pack_write(buffer, target_version).await?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! __tuple_impl {
+    ($(
+    ($($t:ident $idx:tt),+)
+    ),* $(,)?) => {
+        $(
+            /// Tuple members are written in order, without a delimiter.
+            #[async_trait]
+            impl<$($t),+> PacketRead for ($($t,)+)
+            where
+                $($t: PacketRead + Send),+
+            {
+                async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+                    Ok(($($t::pack_read(buffer, target_version).await?,)+))
+                }
+            }
+
+            #[async_trait]
+            impl<$($t),+> PacketWrite for ($($t,)+)
+            where
+                $($t: PacketWrite + Send + Sync),+
+            {
+                async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+                    $(self.$idx.pack_write(buffer, target_version).await?;)+
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+__tuple_impl!((A 0, B 1), (A 0, B 1, C 2));
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ByteArray(pub Vec<u8>);
 
@@ -397,3 +668,204 @@ impl PacketRead for ByteArray {
         Ok(ByteArray(buf))
     }
 }
+
+/// Exactly `N` bytes without a length prefix, for buffers of a size known up front.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FixedByteArray<const N: usize>(pub [u8; N]);
+
+#[async_trait]
+impl<const N: usize> PacketWrite for FixedByteArray<N> {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        buffer.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<const N: usize> PacketRead for FixedByteArray<N> {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let remaining = buffer.get_ref().len() - buffer.position() as usize;
+        if remaining < N {
+            error!(
+                "Expected {} bytes for fixed size array, only {} are left",
+                N, remaining
+            );
+            bail!(
+                "Expected {} bytes for fixed size array, only {} are left",
+                N,
+                remaining
+            )
+        }
+
+        let mut array = [0u8; N];
+        buffer.read_exact(&mut array).await?;
+        Ok(FixedByteArray(array))
+    }
+}
+
+/// Growable set of bits, sent as a length-prefixed array of longs like Java's `BitSet`.
+///
+/// Bit `i` is bit `i % 64` of long `i / 64`, trailing empty longs are never stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_words(mut words: Vec<u64>) -> Self {
+        while words.last() == Some(&0) {
+            words.pop();
+        }
+        Self { words }
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        match self.words.get(idx / 64) {
+            Some(word) => word & (1 << (idx % 64)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn set(&mut self, idx: usize, value: bool) {
+        let word = idx / 64;
+        if value {
+            if word >= self.words.len() {
+                self.words.resize(word + 1, 0);
+            }
+            self.words[word] |= 1 << (idx % 64);
+        } else if word < self.words.len() {
+            self.words[word] &= !(1 << (idx % 64));
+            while self.words.last() == Some(&0) {
+                self.words.pop();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PacketWrite for BitSet {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        self.words.pack_write(buffer, target_version).await
+    }
+}
+
+#[async_trait]
+impl PacketRead for BitSet {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        Ok(BitSet::from_words(
+            Vec::<u64>::pack_read(buffer, target_version).await?,
+        ))
+    }
+}
+
+/// Set of exactly `N` bits, sent as `N / 8` bytes rounded up without a length prefix.
+///
+/// Bit `i` is bit `i % 8` of byte `i / 8`, like Java's `BitSet.toByteArray`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FixedBitSet<const N: usize> {
+    bytes: Vec<u8>,
+}
+
+impl<const N: usize> Default for FixedBitSet<N> {
+    fn default() -> Self {
+        Self {
+            bytes: vec![0; (N + 7) / 8],
+        }
+    }
+}
+
+impl<const N: usize> FixedBitSet<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        idx < N && self.bytes[idx / 8] & (1 << (idx % 8)) != 0
+    }
+
+    /// Sets the bit, panics if it is not below `N`.
+    pub fn set(&mut self, idx: usize, value: bool) {
+        assert!(idx < N, "Bit {} is out of bounds for {} bits", idx, N);
+        if value {
+            self.bytes[idx / 8] |= 1 << (idx % 8);
+        } else {
+            self.bytes[idx / 8] &= !(1 << (idx % 8));
+        }
+    }
+}
+
+#[async_trait]
+impl<const N: usize> PacketWrite for FixedBitSet<N> {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        buffer.extend_from_slice(&self.bytes);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<const N: usize> PacketRead for FixedBitSet<N> {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let mut set = Self::default();
+        buffer.read_exact(&mut set.bytes).await?;
+        // bits past N are only padding
+        if N % 8 != 0 {
+            if let Some(last) = set.bytes.last_mut() {
+                *last &= (1u8 << (N % 8)) - 1;
+            }
+        }
+        Ok(set)
+    }
+}
+
+/// Bit flag sets that are sent as a single byte.
+pub trait BitFlags8: Sized + Copy + Send + Sync {
+    /// Whether unknown bits are rejected on read instead of being dropped.
+    const STRICT: bool = true;
+
+    fn bits(&self) -> u8;
+    fn from_bits(bits: u8) -> Option<Self>;
+    fn from_bits_truncate(bits: u8) -> Self;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flags8<T>(pub T);
+
+#[async_trait]
+impl<T> PacketWrite for Flags8<T>
+where
+    T: BitFlags8,
+{
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        buffer.write_u8(self.0.bits()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T> PacketRead for Flags8<T>
+where
+    T: BitFlags8,
+{
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let bits = buffer.read_u8().await?;
+        if !T::STRICT {
+            return Ok(Flags8(T::from_bits_truncate(bits)));
+        }
+
+        match T::from_bits(bits) {
+            Some(flags) => Ok(Flags8(flags)),
+            None => {
+                error!("Read unknown bits in flags: {:#010b}", bits);
+                bail!("Read unknown bits in flags: {:#010b}", bits)
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Formats the bytes as `offset  hex  |ascii|` lines, 16 bytes per line.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(out, "{:08x}  ", line * BYTES_PER_LINE);
+        for i in 0..BYTES_PER_LINE {
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(out, "{:02x} ", byte);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        out.extend(chunk.iter().map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
+    }
+    out
+}
+
+/// Like [`hex_dump`], preceded by the decoded VarInt length prefix of a framed packet.
+pub fn annotated_hex_dump(bytes: &[u8], length_prefixed: bool) -> String {
+    let mut out = String::new();
+    if length_prefixed {
+        match peek_varint(bytes) {
+            Some((length, size)) => {
+                let _ = writeln!(
+                    out,
+                    "length prefix: {} ({} byte{}, {} bytes follow)",
+                    length,
+                    size,
+                    if size == 1 { "" } else { "s" },
+                    bytes.len() - size
+                );
+            }
+            None => out.push_str("length prefix: invalid\n"),
+        }
+    }
+    out.push_str(&hex_dump(bytes));
+    out
+}
+
+/// Decodes the leading VarInt without a cursor, returning its value and size in bytes.
+fn peek_varint(bytes: &[u8]) -> Option<(i32, usize)> {
+    let mut value = 0u32;
+    for (i, byte) in bytes.iter().take(5).enumerate() {
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value as i32, i + 1));
+        }
+    }
+    None
+}
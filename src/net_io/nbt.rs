@@ -0,0 +1,258 @@
+use crate::net_io::{preallocate, PacketRead, PacketWrite, MAX_ARRAY_SIZE};
+use anyhow::bail;
+use async_trait::async_trait;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::warn;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+/// First protocol version (1.20.2) that leaves out the name of the root tag on the network.
+pub const NAMELESS_ROOT_PROTOCOL_VERSION: u32 = 764;
+
+/// Deepest nesting of lists and compounds accepted when reading, the same limit vanilla uses.
+pub const MAX_NBT_DEPTH: usize = 512;
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// NBT tag, written as the root of a network NBT structure.
+///
+/// Strings are read and written as plain UTF-8, instead of the modified UTF-8 Java uses, which
+/// only differs for null characters and characters outside of the basic multilingual plane.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// Tags of a list all have the same type.
+    List(Vec<Nbt>),
+    Compound(BTreeMap<String, Nbt>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Nbt {
+    pub fn tag_id(&self) -> u8 {
+        match self {
+            Nbt::Byte(_) => TAG_BYTE,
+            Nbt::Short(_) => TAG_SHORT,
+            Nbt::Int(_) => TAG_INT,
+            Nbt::Long(_) => TAG_LONG,
+            Nbt::Float(_) => TAG_FLOAT,
+            Nbt::Double(_) => TAG_DOUBLE,
+            Nbt::ByteArray(_) => TAG_BYTE_ARRAY,
+            Nbt::String(_) => TAG_STRING,
+            Nbt::List(_) => TAG_LIST,
+            Nbt::Compound(_) => TAG_COMPOUND,
+            Nbt::IntArray(_) => TAG_INT_ARRAY,
+            Nbt::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    /// Tag of a compound with the name, `None` for other tags.
+    pub fn get(&self, name: &str) -> Option<&Nbt> {
+        match self {
+            Nbt::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    fn write_payload(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        match self {
+            Nbt::Byte(value) => buffer.write_i8(*value)?,
+            Nbt::Short(value) => buffer.write_i16::<BigEndian>(*value)?,
+            Nbt::Int(value) => buffer.write_i32::<BigEndian>(*value)?,
+            Nbt::Long(value) => buffer.write_i64::<BigEndian>(*value)?,
+            Nbt::Float(value) => buffer.write_f32::<BigEndian>(*value)?,
+            Nbt::Double(value) => buffer.write_f64::<BigEndian>(*value)?,
+            Nbt::ByteArray(values) => {
+                write_length(buffer, values.len())?;
+                for value in values {
+                    buffer.write_i8(*value)?;
+                }
+            }
+            Nbt::String(value) => write_string(buffer, value)?,
+            Nbt::List(tags) => {
+                let tag_id = tags.first().map(Nbt::tag_id).unwrap_or(TAG_END);
+                if let Some(other) = tags.iter().find(|tag| tag.tag_id() != tag_id) {
+                    warn!(
+                        "NBT list of tag type {} contains a tag of type {}",
+                        tag_id,
+                        other.tag_id()
+                    );
+                    bail!(
+                        "NBT list of tag type {} contains a tag of type {}",
+                        tag_id,
+                        other.tag_id()
+                    )
+                }
+                buffer.write_u8(tag_id)?;
+                write_length(buffer, tags.len())?;
+                for tag in tags {
+                    tag.write_payload(buffer)?;
+                }
+            }
+            Nbt::Compound(tags) => {
+                for (name, tag) in tags {
+                    buffer.write_u8(tag.tag_id())?;
+                    write_string(buffer, name)?;
+                    tag.write_payload(buffer)?;
+                }
+                buffer.write_u8(TAG_END)?;
+            }
+            Nbt::IntArray(values) => {
+                write_length(buffer, values.len())?;
+                for value in values {
+                    buffer.write_i32::<BigEndian>(*value)?;
+                }
+            }
+            Nbt::LongArray(values) => {
+                write_length(buffer, values.len())?;
+                for value in values {
+                    buffer.write_i64::<BigEndian>(*value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_payload(buffer: &mut Cursor<&[u8]>, tag_id: u8, depth: usize) -> anyhow::Result<Self> {
+        if depth > MAX_NBT_DEPTH {
+            warn!("NBT is nested deeper than {} tags", MAX_NBT_DEPTH);
+            bail!("NBT is nested deeper than {} tags", MAX_NBT_DEPTH)
+        }
+
+        Ok(match tag_id {
+            TAG_BYTE => Nbt::Byte(buffer.read_i8()?),
+            TAG_SHORT => Nbt::Short(buffer.read_i16::<BigEndian>()?),
+            TAG_INT => Nbt::Int(buffer.read_i32::<BigEndian>()?),
+            TAG_LONG => Nbt::Long(buffer.read_i64::<BigEndian>()?),
+            TAG_FLOAT => Nbt::Float(buffer.read_f32::<BigEndian>()?),
+            TAG_DOUBLE => Nbt::Double(buffer.read_f64::<BigEndian>()?),
+            TAG_BYTE_ARRAY => {
+                let len = read_length(buffer)?;
+                let mut values = preallocate(len);
+                for _ in 0..len {
+                    values.push(buffer.read_i8()?);
+                }
+                Nbt::ByteArray(values)
+            }
+            TAG_STRING => Nbt::String(read_string(buffer)?),
+            TAG_LIST => {
+                let element_id = buffer.read_u8()?;
+                let len = read_length(buffer)?;
+                if element_id == TAG_END && len > 0 {
+                    warn!("NBT list of end tags has {} elements", len);
+                    bail!("NBT list of end tags has {} elements", len)
+                }
+                let mut tags = preallocate(len);
+                for _ in 0..len {
+                    tags.push(Nbt::read_payload(buffer, element_id, depth + 1)?);
+                }
+                Nbt::List(tags)
+            }
+            TAG_COMPOUND => {
+                let mut tags = BTreeMap::new();
+                loop {
+                    let tag_id = buffer.read_u8()?;
+                    if tag_id == TAG_END {
+                        break;
+                    }
+                    let name = read_string(buffer)?;
+                    tags.insert(name, Nbt::read_payload(buffer, tag_id, depth + 1)?);
+                }
+                Nbt::Compound(tags)
+            }
+            TAG_INT_ARRAY => {
+                let len = read_length(buffer)?;
+                let mut values = preallocate(len);
+                for _ in 0..len {
+                    values.push(buffer.read_i32::<BigEndian>()?);
+                }
+                Nbt::IntArray(values)
+            }
+            TAG_LONG_ARRAY => {
+                let len = read_length(buffer)?;
+                let mut values = preallocate(len);
+                for _ in 0..len {
+                    values.push(buffer.read_i64::<BigEndian>()?);
+                }
+                Nbt::LongArray(values)
+            }
+            other => {
+                warn!("Invalid NBT tag type {}", other);
+                bail!("Invalid NBT tag type {}", other)
+            }
+        })
+    }
+}
+
+fn write_length(buffer: &mut Vec<u8>, len: usize) -> anyhow::Result<()> {
+    buffer.write_i32::<BigEndian>(i32::try_from(len)?)?;
+    Ok(())
+}
+
+fn read_length(buffer: &mut Cursor<&[u8]>) -> anyhow::Result<usize> {
+    let len = buffer.read_i32::<BigEndian>()?;
+    if len < 0 || len as usize > MAX_ARRAY_SIZE {
+        warn!("Invalid NBT array length: {}", len);
+        bail!("Invalid NBT array length: {}", len)
+    }
+    Ok(len as usize)
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) -> anyhow::Result<()> {
+    buffer.write_u16::<BigEndian>(u16::try_from(value.len())?)?;
+    buffer.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn read_string(buffer: &mut Cursor<&[u8]>) -> anyhow::Result<String> {
+    let len = buffer.read_u16::<BigEndian>()? as usize;
+    let mut bytes = vec![0u8; len];
+    buffer.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[async_trait]
+impl PacketWrite for Nbt {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        buffer.write_u8(self.tag_id())?;
+        if target_version < NAMELESS_ROOT_PROTOCOL_VERSION {
+            write_string(buffer, "")?;
+        }
+        self.write_payload(buffer)
+    }
+}
+
+#[async_trait]
+impl PacketRead for Nbt {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let tag_id = buffer.read_u8()?;
+        if tag_id == TAG_END {
+            warn!("Expected NBT, got an end tag");
+            bail!("Expected NBT, got an end tag")
+        }
+        if target_version < NAMELESS_ROOT_PROTOCOL_VERSION {
+            // the root name is always empty in practice
+            read_string(buffer)?;
+        }
+        Nbt::read_payload(buffer, tag_id, 0)
+    }
+}
@@ -12,11 +12,23 @@ pub trait PacketStage {
 }
 
 pub trait Packet<S> {
-    fn packet_id() -> u32;
+    /// Id of the packet in the protocol version, `None` if the version does not have the packet.
+    fn packet_id(target_version: u32) -> Option<i32>;
     fn side() -> ProtocolSide;
     fn into_stage(self) -> S;
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! base_packet_id {
+    (_) => {
+        None
+    };
+    ($id:literal) => {
+        Some($id)
+    };
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! simplify {
@@ -29,7 +41,10 @@ macro_rules! simplify {
     (ByteArray) => {
         Vec<u8>
     };
-    ($typ:ident $(<$generic:ident>)?) => {
+    (FixedByteArray<$n:tt>) => {
+        [u8; $n]
+    };
+    ($typ:ident $(<$generic:tt>)?) => {
         $typ $(<$generic>)?
     }
 }
@@ -39,6 +54,7 @@ macro_rules! json_packet_struct {
     ($(
     $name:ident {
         $(
+        $(#[$meta:meta])*
         $field:ident: $ty:ident $(<$generic:ident>)?
         ),* $(,)?
     }
@@ -47,6 +63,7 @@ macro_rules! json_packet_struct {
         #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
         pub struct $name {
              $(
+             $(#[$meta])*
              $field: $ty$(<$generic>)?
              ),*
         }
@@ -57,6 +74,12 @@ macro_rules! json_packet_struct {
                     $($field),*
                 }
             }
+
+            $(
+            pub fn $field(&self) -> &$ty$(<$generic>)? {
+                &self.$field
+            }
+            )*
         }
 
         #[async_trait::async_trait]
@@ -88,7 +111,10 @@ macro_rules! writeable {
     (ByteArray, $e:expr) => {
         ByteArray($e.clone())
     };
-    ($typ:ident $(<$generic:ident>)?, $e:expr) => {
+    (FixedByteArray $(<$n:tt>)?, $e:expr) => {
+        FixedByteArray($e)
+    };
+    ($typ:ident $(<$generic:tt>)?, $e:expr) => {
         $e
     };
 }
@@ -105,22 +131,55 @@ macro_rules! storage {
     (ByteArray, $e:expr) => {
         $e.0
     };
-    ($typ:ident $(<$generic:ident>)?, $e:expr) => {
+    (FixedByteArray $(<$n:tt>)?, $e:expr) => {
+        $e.0
+    };
+    ($typ:ident $(<$generic:tt>)?, $e:expr) => {
         $e
     };
 }
 
 #[macro_export]
 macro_rules! define_enum {
+    (@io $name:ident) => {
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketRead for $name {
+            async fn pack_read(buffer: &mut std::io::Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+                $name::from_id($crate::net_io::VarInt::pack_read(buffer, target_version).await?.0)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketWrite for $name {
+            async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+                self.id().pack_write(buffer, target_version).await
+            }
+        }
+    };
+    (@io $name:ident $repr:ident) => {
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketRead for $name {
+            async fn pack_read(buffer: &mut std::io::Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+                $name::from_id(<$repr>::pack_read(buffer, target_version).await? as i32)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketWrite for $name {
+            async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+                (self.id().0 as $repr).pack_write(buffer, target_version).await
+            }
+        }
+    };
     ($(
-    $name:ident {
+    $name:ident $(: $repr:ident)? {
         $(
         $field:ident = $value:literal
         ),* $(,)*
     }
     );* $(;)*) => {
         $(
-        #[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
         pub enum $name {
             $(
             $field
@@ -151,17 +210,63 @@ macro_rules! define_enum {
             }
         }
 
+        $crate::define_enum!(@io $name $($repr)?);
+        )*
+    };
+}
+
+#[macro_export]
+macro_rules! define_string_enum {
+    ($(
+    $name:ident {
+        $(
+        $field:ident = $value:literal
+        ),* $(,)*
+    }
+    );* $(;)*) => {
+        $(
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        pub enum $name {
+            $(
+            $field
+            ),*
+        }
+
+        impl $name {
+            fn id(&self) -> &'static str {
+                use $name::*;
+                match self {
+                    $(
+                    $field => $value,
+                    )*
+                }
+            }
+
+            fn from_id(id: &str) -> anyhow::Result<$name> {
+                use $name::*;
+                Ok(match id {
+                    $(
+                    $value => $field,
+                    )*
+                    _ => {
+                        log::warn!("Invalid {} ID provided: {}!", stringify!($name), id);
+                        anyhow::bail!("Invalid {} ID provided: {}!", stringify!($name), id)
+                    }
+                })
+            }
+        }
+
         #[async_trait::async_trait]
         impl $crate::net_io::PacketRead for $name {
             async fn pack_read(buffer: &mut std::io::Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
-                $name::from_id($crate::net_io::VarInt::pack_read(buffer, target_version).await?.0)
+                $name::from_id(&String::pack_read(buffer, target_version).await?)
             }
         }
 
         #[async_trait::async_trait]
         impl $crate::net_io::PacketWrite for $name {
             async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
-                self.id().pack_write(buffer, target_version).await
+                self.id().to_string().pack_write(buffer, target_version).await
             }
         }
         )*
@@ -173,7 +278,7 @@ macro_rules! packet_struct {
     ($(
         $name:ident {
             $(
-                $field_name:ident: $field_ty:ident $(<$generic:ident>)?
+                $field_name:ident: $field_ty:ident $(<$generic:tt>)?
             ),* $(,)?
         }
     );* $(;)?) => {
@@ -230,14 +335,89 @@ macro_rules! packet_struct {
     };
 }
 
+/// Defines a field whose layout depends on a leading discriminant, e.g. the mode of a teams packet.
+#[macro_export]
+macro_rules! action_packet {
+    ($(
+    $(#[$meta:meta])*
+    $name:ident: $repr:ident {
+        $(
+        $variant:ident ($disc:literal) {
+            $(
+            $field_name:ident: $field_ty:ident $(<$generic:tt>)?
+            ),* $(,)?
+        }
+        ),* $(,)?
+    }
+    );* $(;)?) => {
+        $(
+        $(#[$meta])*
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+        pub enum $name {
+            $(
+            $variant {
+                $(
+                $field_name: $crate::simplify!($field_ty $(<$generic>)?)
+                ),*
+            }
+            ),*
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketRead for $name {
+            #[allow(unused_variables)]
+            async fn pack_read(buffer: &mut std::io::Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+                let action = $crate::storage!($repr, <$repr>::pack_read(buffer, target_version).await?);
+                Ok(match action {
+                    $(
+                    $disc => {
+                        $(
+                        let $field_name = <$field_ty$(<$generic>)?>::pack_read(buffer, target_version).await?;
+                        )*
+                        $name::$variant {
+                            $(
+                            $field_name: $crate::storage!($field_ty $(<$generic>)?, $field_name),
+                            )*
+                        }
+                    }
+                    )*
+                    _ => {
+                        log::warn!("Invalid {} action provided: {}!", stringify!($name), action);
+                        anyhow::bail!("Invalid {} action provided: {}!", stringify!($name), action)
+                    }
+                })
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::net_io::PacketWrite for $name {
+            async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+                match self {
+                    $(
+                    $name::$variant { $($field_name),* } => {
+                        $crate::writeable!($repr, ($disc as $crate::simplify!($repr))).pack_write(buffer, target_version).await?;
+                        $(
+                        $crate::writeable!($field_ty, (*$field_name)).pack_write(buffer, target_version).await?;
+                        )*
+                    }
+                    )*
+                }
+
+                Ok(())
+            }
+        }
+        )*
+    };
+}
+
 #[macro_export]
 macro_rules! staged_packets {
     (
         $stage:ident ($stage_name:literal, $side:ident) {
             $(
-            $name:ident ($id:literal) {
+            $name:ident ($id:tt $(, since $since:expr => $since_id:literal)*) {
                 $(
-                $field_name:ident: $field_ty:ident $(<$generic:ident>)?
+                $field_name:ident: $field_ty:ident $(<$generic:tt>)?
                 ),* $(,)?
             }
             );* $(;)?
@@ -251,6 +431,17 @@ macro_rules! staged_packets {
             ),*
         }
 
+        impl $stage {
+            /// Name of the packet type held by this variant.
+            pub fn packet_name(&self) -> &'static str {
+                match self {
+                    $(
+                    $stage::$name(_) => stringify!($name)
+                    ),*
+                }
+            }
+        }
+
         impl $crate::net_io::packet::PacketStage for $stage {
             fn name() -> String {
                 $stage_name.to_string()
@@ -264,17 +455,16 @@ macro_rules! staged_packets {
         #[async_trait::async_trait]
         impl $crate::net_io::PacketRead for $stage {
             async fn pack_read(buffer: &mut std::io::Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+                use $crate::net_io::packet::{Packet, PacketStage};
+
                 let id = $crate::net_io::VarInt::pack_read(buffer, target_version).await?.0;
-                Ok(match id {
-                    $(
-                    $id => $stage::$name(<$name>::pack_read(buffer, target_version).await?),
-                    )*
-                    _ => {
-                        use $crate::net_io::packet::PacketStage;
-                        log::warn!("Received invalid packet type: {:#01x} in stage {}", id, $stage::name());
-                        anyhow::bail!("Received invalid packet type: {:#01x} in stage {}", id, $stage::name());
-                    }
-                })
+                $(
+                if <$name as Packet<$stage>>::packet_id(target_version) == Some(id) {
+                    return Ok($stage::$name(<$name>::pack_read(buffer, target_version).await?));
+                }
+                )*
+                log::warn!("Received invalid packet type: {:#01x} in stage {}", id, $stage::name());
+                anyhow::bail!("Received invalid packet type: {:#01x} in stage {}", id, $stage::name())
             }
         }
 
@@ -317,8 +507,13 @@ macro_rules! staged_packets {
             }
 
             impl $crate::net_io::packet::Packet<$stage> for $name {
-                fn packet_id() -> u32 {
-                    $id
+                fn packet_id(target_version: u32) -> Option<i32> {
+                    // ids since a version replace the ones of earlier versions, `_` if they have none
+                    let ids: &[(u32, i32)] = &[$(($since, $since_id)),*];
+                    match ids.iter().rev().find(|(since, _)| target_version >= *since) {
+                        Some((_, id)) => Some(*id),
+                        None => $crate::base_packet_id!($id),
+                    }
                 }
 
                 fn side() -> $crate::net_io::packet::ProtocolSide {
@@ -348,7 +543,16 @@ macro_rules! staged_packets {
             #[async_trait::async_trait]
             impl $crate::net_io::PacketWrite for $name {
                 async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
-                    $crate::net_io::VarInt($id).pack_write(buffer, target_version).await?;
+                    use $crate::net_io::packet::Packet;
+
+                    let id = match <$name as Packet<$stage>>::packet_id(target_version) {
+                        Some(id) => id,
+                        None => {
+                            log::warn!("{} does not exist in protocol version {}", stringify!($name), target_version);
+                            anyhow::bail!("{} does not exist in protocol version {}", stringify!($name), target_version)
+                        }
+                    };
+                    $crate::net_io::VarInt(id).pack_write(buffer, target_version).await?;
                     $(
                     $crate::writeable!($field_ty, self.$field_name).pack_write(buffer, target_version).await?;
                     )*
@@ -1,20 +1,49 @@
+pub mod auth;
 pub mod client;
+#[cfg(feature = "echo")]
+pub mod echo;
 pub mod encode;
+pub mod handler;
+pub mod keepalive;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod player;
+pub mod query;
+pub mod rcon;
+pub mod registry;
+pub mod status;
 
-use crate::cfg::{RuntimeConfiguration, SoulflameConfiguration};
+use crate::cfg::{RuntimeConfiguration, SoulflameConfiguration, TcpKeepaliveConfig};
 use crate::network::client::ClientConnection;
+use crate::network::handler::{NoopHandler, PacketHandler};
+use crate::network::registry::PlayerRegistry;
+use crate::network::status::{DefaultStatusProvider, StatusProvider};
 use anyhow::{bail, Context};
-use log::{info, warn};
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use ipnet::IpNet;
+use log::{error, info, warn};
+use socket2::{SockRef, TcpKeepalive};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+
+/// How long a shutdown waits for connections to close before giving up on them.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct NetworkListener {
     inner: TcpListener,
-    players: PlayerCount,
+    state: SharedState,
+    pending: PendingConnections,
     config: SoulflameConfiguration,
     runtime: RuntimeConfiguration,
+    access: AccessList,
+    shutdown: Shutdown,
+    trigger: ShutdownHandle,
+    closed: mpsc::Receiver<()>,
 }
 
 impl NetworkListener {
@@ -23,45 +52,293 @@ impl NetworkListener {
         port: u16,
         configuration: SoulflameConfiguration,
     ) -> anyhow::Result<()> {
+        Self::bind(addr, port, configuration).await?.run().await;
+        Ok(())
+    }
+
+    /// Binds the listener without accepting connections yet, see [`NetworkListener::run`].
+    pub async fn bind(
+        addr: String,
+        port: u16,
+        configuration: SoulflameConfiguration,
+    ) -> anyhow::Result<Self> {
         let listener = TcpListener::bind(format!("{}:{}", addr, port))
             .await
             .context("Failed to bind to port! Is it already in use?")?;
 
         info!("Started network listener successfully!");
+        if configuration.spoof_brand.is_some() || configuration.spoof_version_name.is_some() {
+            info!(
+                "Advertising as brand '{}' with version '{}'",
+                configuration.brand(),
+                configuration.version_name()
+            );
+        }
+        if configuration.online_mode && !cfg!(feature = "online") {
+            error!("Online mode is enabled, but the `online` feature is not!");
+            error!("Every login will be refused, disable online mode or enable the feature");
+        }
+
+        let runtime = RuntimeConfiguration::from_cfg(&configuration).await?;
+        let (state, status) = SharedState::with_default_status(&configuration, &runtime);
+        #[cfg(unix)]
+        tokio::spawn(reload_on_hangup(status));
+        #[cfg(not(unix))]
+        drop(status);
 
-        let this = NetworkListener {
+        let (trigger, shutdown, closed) = Shutdown::new();
+        Ok(NetworkListener {
             inner: listener,
-            players: PlayerCount::new(configuration.max_players),
+            state,
+            pending: PendingConnections::new(configuration.max_pending_per_ip),
             config: configuration.clone(),
-            runtime: RuntimeConfiguration::from_cfg(&configuration).await?,
-        };
+            runtime,
+            access: AccessList::from_cfg(&configuration)?,
+            shutdown,
+            trigger,
+            closed,
+        })
+    }
 
-        this.network_loop().await;
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.inner.local_addr()?)
+    }
 
-        Ok(())
+    /// Players logged in through this listener.
+    pub fn registry(&self) -> PlayerRegistry {
+        self.state.registry.clone()
+    }
+
+    /// Count of the players logged in through this listener.
+    pub fn players(&self) -> PlayerCount {
+        self.state.players.clone()
+    }
+
+    /// Provider answering status requests, by default following reloads and the maintenance mode.
+    pub fn status_provider(&self) -> Arc<dyn StatusProvider> {
+        self.state.status.clone()
+    }
+
+    /// Replaces the provider answering status requests of every connection accepted afterwards.
+    ///
+    /// `SIGHUP` only reloads the branding of the default provider.
+    pub fn set_status_provider(&mut self, provider: Arc<dyn StatusProvider>) {
+        self.state.status = provider;
+    }
+
+    /// Replaces the handler play packets of every connection accepted afterwards are dispatched to.
+    pub fn set_handler(&mut self, handler: Arc<dyn PacketHandler>) {
+        self.state.handler = handler;
+    }
+
+    /// Switch refusing logins and changing the MOTD, which can be toggled while the listener runs.
+    pub fn maintenance(&self) -> MaintenanceMode {
+        self.state.maintenance.clone()
+    }
+
+    /// Handle stopping the listener, which can be used while it runs.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.trigger.clone()
+    }
+
+    /// Accepts connections until the listener is shut down.
+    ///
+    /// Players are disconnected on shutdown, this returns once every connection closed.
+    pub async fn run(self) {
+        self.network_loop().await;
     }
 
     async fn network_loop(mut self) {
+        let mut shutdown = self.shutdown.clone();
         loop {
-            if let Ok((stream, addr)) = self.inner.accept().await {
-                self.proceed(stream, addr).await;
+            tokio::select! {
+                accepted = self.inner.accept() => {
+                    if let Ok((stream, addr)) = accepted {
+                        self.proceed(stream, addr).await;
+                    }
+                }
+                _ = shutdown.wait() => break,
             }
         }
+
+        info!("Stopped accepting connections, waiting for them to close");
+        // only the connections hold the shutdown now, the channel closes once they are gone
+        drop(shutdown);
+        drop(self.shutdown);
+        if timeout(SHUTDOWN_TIMEOUT, self.closed.recv()).await.is_err() {
+            warn!(
+                "Connections did not close within {}s",
+                SHUTDOWN_TIMEOUT.as_secs()
+            );
+        }
     }
 
     async fn proceed(&mut self, stream: TcpStream, addr: SocketAddr) {
-        let connection = ClientConnection::new(
+        if !self.access.permits(addr.ip()) {
+            info!("Refused connection from {}", addr.ip());
+            return;
+        }
+
+        let slot = match self.pending.try_acquire(addr.ip()) {
+            Some(slot) => slot,
+            None => {
+                info!(
+                    "Refused connection from {}, too many connections are pending",
+                    addr.ip()
+                );
+                return;
+            }
+        };
+
+        if let Err(e) = configure_keepalive(&stream, &self.config.tcp_keepalive) {
+            warn!("Could not enable TCP keep-alive for {}: {}", addr.ip(), e);
+        }
+
+        let mut connection = ClientConnection::new(
             stream,
             addr,
-            self.players.clone(),
+            self.state.clone(),
             self.config.clone(),
             self.runtime.clone(),
         )
         .await;
+        connection.set_pending_slot(slot);
+        connection.set_shutdown(self.shutdown.clone());
         connection.start();
     }
 }
 
+/// Reloads the MOTD and favicon from the configuration file whenever the process receives `SIGHUP`.
+#[cfg(unix)]
+async fn reload_on_hangup(status: Arc<DefaultStatusProvider>) {
+    use crate::CONFIG_PATH;
+    use std::path::Path;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!(
+                "Could not listen for SIGHUP, branding can not be reloaded: {}",
+                e
+            );
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match SoulflameConfiguration::load_or_create(Path::new(CONFIG_PATH)).await {
+            Ok(config) => status.reload(&config).await,
+            Err(e) => warn!(
+                "Could not reload the configuration, keeping the branding: {}",
+                e
+            ),
+        }
+    }
+}
+
+/// Enables OS level keep-alive probes on the stream, if configured.
+pub(crate) fn configure_keepalive(
+    stream: &TcpStream,
+    cfg: &TcpKeepaliveConfig,
+) -> anyhow::Result<()> {
+    let socket = SockRef::from(stream);
+    if !cfg.enabled {
+        socket.set_keepalive(false)?;
+        return Ok(());
+    }
+
+    let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(cfg.idle_secs));
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_vendor = "apple"
+    ))]
+    let keepalive = keepalive
+        .with_interval(Duration::from_secs(cfg.interval_secs))
+        .with_retries(cfg.retries);
+    socket.set_tcp_keepalive(&keepalive)?;
+    Ok(())
+}
+
+/// Address ranges allowed or denied to connect, a denied range always wins.
+#[derive(Debug, Clone, Default)]
+pub struct AccessList {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl AccessList {
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>) -> Self {
+        Self { allow, deny }
+    }
+
+    pub fn from_cfg(cfg: &SoulflameConfiguration) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            parse_cidrs(&cfg.allow_cidrs)?,
+            parse_cidrs(&cfg.deny_cidrs)?,
+        ))
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+fn parse_cidrs(cidrs: &[String]) -> anyhow::Result<Vec<IpNet>> {
+    cidrs
+        .iter()
+        .map(|cidr| {
+            cidr.parse::<IpNet>()
+                .with_context(|| format!("Invalid address range in configuration: {}", cidr))
+        })
+        .collect()
+}
+
+/// State a listener shares with each of its connections.
+#[derive(Clone)]
+pub struct SharedState {
+    pub players: PlayerCount,
+    pub registry: PlayerRegistry,
+    pub maintenance: MaintenanceMode,
+    pub status: Arc<dyn StatusProvider>,
+    pub handler: Arc<dyn PacketHandler>,
+}
+
+impl SharedState {
+    pub fn new(config: &SoulflameConfiguration, runtime: &RuntimeConfiguration) -> Self {
+        Self::with_default_status(config, runtime).0
+    }
+
+    /// Creates the state along with the default status provider it answers status requests with,
+    /// which follows the player count and the maintenance switch of the state.
+    pub fn with_default_status(
+        config: &SoulflameConfiguration,
+        runtime: &RuntimeConfiguration,
+    ) -> (Self, Arc<DefaultStatusProvider>) {
+        let players = PlayerCount::new(config.max_players);
+        let registry = PlayerRegistry::new();
+        let maintenance = MaintenanceMode::new(config.maintenance);
+        let mut status = DefaultStatusProvider::new(config.clone(), runtime.clone());
+        status.set_maintenance(maintenance.clone());
+        status.set_players(players.clone(), registry.clone());
+        let status = Arc::new(status);
+        (
+            Self {
+                players,
+                registry,
+                maintenance,
+                status: status.clone(),
+                handler: Arc::new(NoopHandler),
+            },
+            status,
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct PlayerCount {
     inner: Arc<Players>,
@@ -114,3 +391,144 @@ struct Players {
     count: AtomicU32,
     max: u32,
 }
+
+/// Shutdown signal of a listener, held by each of its connections.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    signal: watch::Receiver<bool>,
+    // the listener waits until every clone of this is dropped
+    _open: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    /// Creates the signal along with the handle triggering it and the receiver that closes once
+    /// every clone of the signal is dropped.
+    pub fn new() -> (ShutdownHandle, Shutdown, mpsc::Receiver<()>) {
+        let (trigger, signal) = watch::channel(false);
+        let (open, closed) = mpsc::channel(1);
+        (
+            ShutdownHandle {
+                trigger: Arc::new(trigger),
+            },
+            Shutdown {
+                signal,
+                _open: open,
+            },
+            closed,
+        )
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.signal.borrow()
+    }
+
+    /// Waits until the shutdown is triggered, returns right away if it already was.
+    pub async fn wait(&mut self) {
+        while !*self.signal.borrow() {
+            if self.signal.changed().await.is_err() {
+                // the handle is gone, nothing can trigger a shutdown anymore
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Triggers the shutdown of a listener.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    trigger: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        let _ = self.trigger.send(true);
+    }
+}
+
+/// Maintenance switch shared by the listener, its connections and the status provider.
+///
+/// Starts in the configured state and can be toggled while the server is running.
+#[derive(Debug, Clone)]
+pub struct MaintenanceMode {
+    enabled: Arc<AtomicBool>,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if self.enabled.swap(enabled, Ordering::AcqRel) != enabled {
+            info!(
+                "Maintenance mode {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+    }
+}
+
+/// Connections per address that did not reach the login state yet.
+///
+/// Limits how many handshake and status connections, each with its own read timeout, a single
+/// address can keep open.
+#[derive(Clone)]
+pub struct PendingConnections {
+    inner: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    max: usize,
+}
+
+impl PendingConnections {
+    pub fn new(max: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HashMap::new())),
+            max,
+        }
+    }
+
+    /// Reserves a slot for a new connection, `None` if the address already holds the maximum.
+    pub fn try_acquire(&self, ip: IpAddr) -> Option<PendingSlot> {
+        let mut pending = self.inner.lock().unwrap();
+        let count = pending.get(&ip).copied().unwrap_or(0);
+        if count >= self.max {
+            return None;
+        }
+        pending.insert(ip, count + 1);
+        Some(PendingSlot {
+            pending: self.clone(),
+            ip,
+        })
+    }
+
+    pub fn get(&self, ip: IpAddr) -> usize {
+        self.inner.lock().unwrap().get(&ip).copied().unwrap_or(0)
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut pending = self.inner.lock().unwrap();
+        if let Some(count) = pending.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Pending connection slot of an address, released once dropped.
+pub struct PendingSlot {
+    pending: PendingConnections,
+    ip: IpAddr,
+}
+
+impl Drop for PendingSlot {
+    fn drop(&mut self) {
+        self.pending.release(self.ip);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::cfg::SessionServerConfig;
+use crate::protocol::server::login::ProfileProperty;
+use anyhow::bail;
+use async_trait::async_trait;
+use log::warn;
+use md5::Md5;
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+use rsa::{Hash, PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use tokio::time::{sleep, timeout, Instant};
+use uuid::Uuid;
+
+/// Size of the key pair the shared secret is encrypted with, the same as vanilla servers use.
+pub const SERVER_KEY_BITS: usize = 1024;
+
+/// Key pair clients encrypt the shared secret and verify token with during online mode logins.
+#[derive(Debug)]
+pub struct ServerKeys {
+    private: RsaPrivateKey,
+    public_der: Vec<u8>,
+}
+
+impl ServerKeys {
+    pub fn generate() -> anyhow::Result<Self> {
+        let private = RsaPrivateKey::new(&mut rand::thread_rng(), SERVER_KEY_BITS)?;
+        let public_der = RsaPublicKey::from(&private)
+            .to_public_key_der()?
+            .as_ref()
+            .to_vec();
+        Ok(Self {
+            private,
+            public_der,
+        })
+    }
+
+    /// Public key in the DER format sent in the encryption request.
+    pub fn public_der(&self) -> &[u8] {
+        &self.public_der
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .private
+            .decrypt(PaddingScheme::new_pkcs1v15_encrypt(), data)?)
+    }
+}
+
+/// Checks the signature a client with a chat signing key sent instead of the encrypted verify token.
+///
+/// The client signs the verify token followed by the salt with SHA256withRSA, using the private
+/// half of the public key it sent in the login start packet.
+pub fn verify_token_signature(
+    public_key: &[u8],
+    verify_token: &[u8],
+    salt: i64,
+    signature: &[u8],
+) -> anyhow::Result<()> {
+    let key = RsaPublicKey::from_public_key_der(public_key)?;
+    let mut hasher = Sha256::new();
+    hasher.update(verify_token);
+    hasher.update(salt.to_be_bytes());
+    let digest = hasher.finalize();
+    key.verify(
+        PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+        &digest,
+        signature,
+    )?;
+    Ok(())
+}
+
+/// Profile the session server returns for a player that joined.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// Status of the session server answering that the player did not join.
+pub const SESSION_NOT_JOINED: u16 = 204;
+
+/// Answer of a single request to the session server's `hasJoined` endpoint.
+#[derive(Debug, Clone)]
+pub struct SessionResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Transport for `hasJoined` requests, replaceable to authenticate against a mock.
+#[async_trait]
+pub trait SessionServer: Send + Sync {
+    /// Sends a single request, errors are failures to get any response at all.
+    async fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> anyhow::Result<SessionResponse>;
+
+    /// Whether requests can succeed at all, failed requests are not retried otherwise.
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+/// The Mojang session server.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MojangSessionServer;
+
+#[cfg(feature = "online")]
+#[async_trait]
+impl SessionServer for MojangSessionServer {
+    async fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> anyhow::Result<SessionResponse> {
+        let response = reqwest::Client::new()
+            .get("https://sessionserver.mojang.com/session/minecraft/hasJoined")
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send()
+            .await?;
+        Ok(SessionResponse {
+            status: response.status().as_u16(),
+            body: response.text().await?,
+        })
+    }
+}
+
+#[cfg(not(feature = "online"))]
+#[async_trait]
+impl SessionServer for MojangSessionServer {
+    fn is_available(&self) -> bool {
+        false
+    }
+
+    async fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> anyhow::Result<SessionResponse> {
+        log::warn!(
+            "Can not authenticate {} ({}), online mode requires the `online` feature",
+            username,
+            server_hash
+        );
+        anyhow::bail!(
+            "Can not authenticate {} ({}), online mode requires the `online` feature",
+            username,
+            server_hash
+        )
+    }
+}
+
+/// Asks the session server whether the player joined with the server hash.
+///
+/// Requests that time out, fail to connect or get a 5xx or 429 response are retried with
+/// exponential backoff, as long as attempts and the total time of the configuration allow it.
+/// Failures of an unavailable session server are returned right away.
+/// Returns `None` if the player did not authenticate with Mojang.
+pub async fn has_joined(
+    server: &dyn SessionServer,
+    username: &str,
+    server_hash: &str,
+    cfg: &SessionServerConfig,
+) -> anyhow::Result<Option<GameProfile>> {
+    let deadline = Instant::now() + cfg.total_timeout();
+    let mut backoff = cfg.initial_backoff();
+    let mut attempt = 1;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let request = server.has_joined(username, server_hash);
+        let error = match timeout(cfg.request_timeout().min(remaining), request).await {
+            Ok(Ok(response)) => match response.status {
+                200 => return Ok(Some(serde_json::from_str(&response.body)?)),
+                SESSION_NOT_JOINED => return Ok(None),
+                status if status == 429 || status >= 500 => {
+                    format!("session server responded with {}", status)
+                }
+                status => bail!("Session server responded with {}", status),
+            },
+            Ok(Err(e)) if !server.is_available() => return Err(e),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "request timed out".to_string(),
+        };
+
+        if attempt >= cfg.attempts || Instant::now() + backoff >= deadline {
+            bail!(
+                "Could not reach the session server after {} attempts: {}",
+                attempt,
+                error
+            )
+        }
+        warn!(
+            "Session server request for {} failed ({}), retrying in {}ms",
+            username,
+            error,
+            backoff.as_millis()
+        );
+        sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Server hash sent to the session server when checking that a player joined.
+///
+/// This is the SHA-1 digest of all inputs, read as a signed big-endian number and written as
+/// hex without leading zeros, negative values get a `-` prefix instead of the two's complement.
+pub fn mc_auth_digest(server_id: &str, secret: &[u8], pubkey: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(secret);
+    hasher.update(pubkey);
+    let mut digest: [u8; 20] = hasher.finalize().into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // two's complement, inverting every byte and adding one
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            let (value, overflow) = (!*byte).overflowing_add(carry as u8);
+            *byte = value;
+            carry = overflow;
+        }
+    }
+
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    let hex = match hex.trim_start_matches('0') {
+        "" => "0",
+        trimmed => trimmed,
+    };
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_string()
+    }
+}
+
+/// UUID of a player in offline mode, derived from the username like `UUID.nameUUIDFromBytes`.
+pub fn offline_uuid(username: &str) -> Uuid {
+    let mut bytes: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", username)).into();
+    // version 3, IETF variant
+    bytes[6] = (bytes[6] & 0x0F) | 0x30;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    Uuid::from_bytes(bytes)
+}
@@ -1,30 +1,49 @@
 #![allow(dead_code)]
 
-use crate::cfg::{RuntimeConfiguration, SoulflameConfiguration};
+use crate::cfg::{ChannelScheduling, RuntimeConfiguration, SoulflameConfiguration};
 use crate::chat::Component;
 use crate::net_io::{PacketRead, PacketWrite};
+use crate::network::auth::{
+    has_joined, mc_auth_digest, offline_uuid, verify_token_signature, GameProfile,
+    MojangSessionServer,
+};
 use crate::network::encode::{PacketDecoder, PacketEncoder};
-use crate::network::PlayerCount;
-use crate::protocol::client::handshake::{HandshakeState, InHandshake};
+use crate::network::handler::{tick, PacketHandler};
+use crate::network::keepalive::{KeepAlive, KEEP_ALIVE_INTERVAL, KEEP_ALIVE_TIMEOUT};
+use crate::network::player::PlayerHandle;
+use crate::network::registry::PlayerRegistry;
+use crate::network::status::StatusProvider;
+use crate::network::{MaintenanceMode, PendingSlot, PlayerCount, SharedState, Shutdown};
+use crate::protocol::client::handshake::{
+    BungeeForward, HandshakeState, InHandshake, PacketHandshakeIn,
+};
+use crate::protocol::client::login::{InLogin, PacketLoginInStart, VerifyToken};
 use crate::protocol::client::play::PacketPlayIn;
-use crate::protocol::client::status::{InStatus, PacketStatusInPing};
-use crate::protocol::server::login::PacketLoginOutDisconnect;
-use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutDisconnect};
+use crate::protocol::client::status::InStatus;
+use crate::protocol::server::login::{
+    PacketLoginOutCompression, PacketLoginOutDisconnect, PacketLoginOutEncryptionRequest,
+    PacketLoginOutSuccess,
+};
+use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutDisconnect, PacketPlayOutLogin};
 use crate::protocol::server::status::{
-    OutStatus, PacketStatusOutPong, PacketStatusOutResponse, ServerPlayers, ServerVersion,
-    StatusResponse,
+    OutStatus, PacketStatusOutPong, PacketStatusOutResponse, StatusResponse,
 };
+use crate::{LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOLS};
 use anyhow::bail;
 use flume::{Receiver, Sender};
 use lobstermessage::lobster;
-use log::{info, warn};
+use log::{debug, info, warn};
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::time::timeout;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Instant};
+use uuid::Uuid;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ProtocolState {
@@ -45,36 +64,116 @@ pub struct ClientConnection {
     outgoing: OutgoingPacketChannel,
 
     send_packets: Sender<PacketPlayOut>,
-    receive_packets: Receiver<PacketPlayIn>,
+    receive_packets: Option<Receiver<PacketPlayIn>>,
+    player: PlayerHandle,
+    handshake: Option<PacketHandshakeIn>,
+    status: Arc<dyn StatusProvider>,
+    forward: Option<BungeeForward>,
+    pending: Option<PendingSlot>,
+    keep_alive: KeepAlive,
+    handler: Arc<dyn PacketHandler>,
+    maintenance: MaintenanceMode,
+    shutdown: Option<Shutdown>,
+    registry: PlayerRegistry,
+    uuid: Option<Uuid>,
 }
 
 impl ClientConnection {
     pub async fn new(
         stream: TcpStream,
         addr: SocketAddr,
-        players: PlayerCount,
+        shared: SharedState,
         config: SoulflameConfiguration,
         runtime: RuntimeConfiguration,
     ) -> Self {
         let (reader, writer) = stream.into_split();
+        Self::from_split(reader, writer, addr, shared, config, runtime).await
+    }
 
+    /// Creates a connection over arbitrary read and write halves, e.g. an in-memory duplex.
+    pub async fn from_split<R, W>(
+        reader: R,
+        writer: W,
+        addr: SocketAddr,
+        shared: SharedState,
+        config: SoulflameConfiguration,
+        runtime: RuntimeConfiguration,
+    ) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
         let (receive_packets_tx, receive_packets_rx) = flume::bounded(32);
         let (send_packets_tx, send_packets_rx) = flume::unbounded();
 
+        let mut inbound = InboundPacketChannel::new(
+            Box::new(reader),
+            receive_packets_tx,
+            addr.clone(),
+            runtime.read_timeout,
+        );
+        inbound.set_log_connections(config.log_connections);
+        inbound.set_max_packet_size(config.max_packet_size);
+        let keep_alive = KeepAlive::new();
+        inbound.set_keep_alive(keep_alive.clone());
+
         Self {
             addr,
 
-            players,
+            players: shared.players,
+            status: shared.status,
             config,
             runtime,
             state: ProtocolState::Handshake,
-            inbound: InboundPacketChannel::new(reader, receive_packets_tx, addr.clone()),
-            outgoing: OutgoingPacketChannel::new(writer, send_packets_rx, addr.clone()),
+            inbound,
+            outgoing: OutgoingPacketChannel::new(Box::new(writer), send_packets_rx, addr.clone()),
+            player: PlayerHandle::new(addr, send_packets_tx.clone()),
             send_packets: send_packets_tx,
-            receive_packets: receive_packets_rx,
+            receive_packets: Some(receive_packets_rx),
+            handshake: None,
+            forward: None,
+            pending: None,
+            keep_alive,
+            handler: shared.handler,
+            maintenance: shared.maintenance,
+            shutdown: None,
+            registry: shared.registry,
+            uuid: None,
         }
     }
 
+    /// Replaces the provider answering status requests on this connection.
+    pub fn set_status_provider(&mut self, provider: Arc<dyn StatusProvider>) {
+        self.status = provider;
+    }
+
+    /// Replaces the handler play packets are dispatched to, unless they are taken beforehand.
+    pub fn set_handler(&mut self, handler: Arc<dyn PacketHandler>) {
+        self.handler = handler;
+    }
+
+    /// Shares the maintenance switch deciding whether logins are refused.
+    ///
+    /// The status provider keeps its own switch, a shared one has to be given the same switch.
+    pub fn set_maintenance(&mut self, maintenance: MaintenanceMode) {
+        self.maintenance = maintenance;
+    }
+
+    /// Disconnects the player once the listener shuts down, which waits for the connection to close.
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    /// Shares the registry the player is added to while online.
+    pub fn set_registry(&mut self, registry: PlayerRegistry) {
+        self.registry = registry;
+    }
+
+    /// Holds the pending slot of the client's address until the connection reaches login.
+    pub fn set_pending_slot(&mut self, slot: PendingSlot) {
+        self.pending = Some(slot);
+    }
+
     pub fn start(self) {
         tokio::task::spawn(async move {
             if let Err(e) = self.handle().await {
@@ -83,57 +182,404 @@ impl ClientConnection {
         });
     }
 
-    async fn handle(mut self) -> anyhow::Result<()> {
-        self.do_initial_handle().await?;
+    pub(crate) async fn handle(mut self) -> anyhow::Result<()> {
+        let result = self.do_initial_handle().await;
 
-        Ok(())
+        let mut players = self.players.clone();
+        let registry = self.registry.clone();
+        let player = self.player.clone();
+        // only set once the player took a slot and was registered, which failed logins release too
+        let uuid = self.uuid;
+        let result = match result {
+            Ok(()) if self.state == ProtocolState::Play => self.run_play().await,
+            other => other,
+        };
+        if let Some(uuid) = uuid {
+            players.remove_player();
+            registry.unregister(&uuid, &player);
+        }
+        result
+    }
+
+    /// Reads the handshake ahead of [`ClientConnection::start`], so it can be inspected for routing.
+    ///
+    /// The connection keeps the handshake and continues with the packets after it once started.
+    pub async fn peek_handshake(&mut self) -> anyhow::Result<&PacketHandshakeIn> {
+        if self.handshake.is_none() {
+            let InHandshake::PacketHandshakeIn(handshake) = self.read_packet().await?;
+            handshake.validate_protocol(self.config.max_protocol_version)?;
+            handshake.validate_address(self.config.max_hostname_length())?;
+            // packets are read and written for the client's version from here on
+            let version = *handshake.protocol_version() as u32;
+            self.player.set_protocol_version(version);
+            self.inbound.set_protocol_version(version);
+            self.outgoing.set_protocol_version(version);
+            self.handshake = Some(handshake);
+        }
+
+        Ok(self.handshake.as_ref().expect("Handshake should be read"))
+    }
+
+    /// Takes the inbound play packets for the game to handle.
+    ///
+    /// Dropping the receiver tears the whole connection down once the next packet arrives.
+    pub fn take_packets(&mut self) -> Option<Receiver<PacketPlayIn>> {
+        self.receive_packets.take()
+    }
+
+    /// Address the client connected from, the forwarded one if BungeeCord forwarding is enabled.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = addr;
+        self.inbound.set_addr(addr);
+        self.outgoing.set_addr(addr);
+        self.player.set_addr(addr);
+    }
+
+    /// Player data forwarded by the proxy, if BungeeCord forwarding is enabled.
+    pub fn forwarded(&self) -> Option<&BungeeForward> {
+        self.forward.as_ref()
+    }
+
+    /// Hands the connection over to the packet channels once the client is in play.
+    ///
+    /// Consumes the connection, so no pre-play read can race the play loop. Inbound packets are
+    /// dispatched to the handler, unless they were taken with [`ClientConnection::take_packets`].
+    async fn run_play(self) -> anyhow::Result<()> {
+        debug_assert_eq!(self.state, ProtocolState::Play);
+        // the kick for a timed out client goes through the outgoing channel, which then closes
+        let _keep_alive = AbortOnDrop(tokio::spawn(self.keep_alive.run(
+            self.player.clone(),
+            KEEP_ALIVE_INTERVAL,
+            KEEP_ALIVE_TIMEOUT,
+        )));
+        let _shutdown = self.shutdown.map(|shutdown| {
+            let reason = lobster(&self.config.shutdown_message);
+            AbortOnDrop(tokio::spawn(kick_on_shutdown(
+                shutdown,
+                self.player.clone(),
+                reason,
+            )))
+        });
+        let channels = run_channels(self.inbound, self.outgoing, self.config.channel_scheduling);
+        match self.receive_packets {
+            Some(packets) => {
+                let threshold = self.config.slow_handler_threshold();
+                tokio::select! {
+                    result = channels => result,
+                    result = tick(self.handler, self.player, packets, threshold) => result,
+                }
+            }
+            None => channels.await,
+        }
     }
 
     async fn do_initial_handle(&mut self) -> anyhow::Result<()> {
-        let InHandshake::PacketHandshakeIn(handshake) = self.read_packet().await?;
+        // forwarded handshakes may start with the same byte, proxies answer legacy pings themselves
+        if self.handshake.is_none()
+            && !self.config.bungeecord_forwarding
+            && self.inbound.peek_byte().await? == Some(LEGACY_PING)
+        {
+            return self.answer_legacy_ping().await;
+        }
+
+        let handshake = self.peek_handshake().await?.clone();
         match handshake.next_state() {
             HandshakeState::Status => {
                 self.state = ProtocolState::Status;
 
-                let _request = self.read_packet::<InStatus>().await?;
+                // port scanners commonly hang up right after the handshake
+                if self.inbound.try_read_packet::<InStatus>().await?.is_none() {
+                    if self.config.log_connections {
+                        info!(
+                            "Client {} closed status call before requesting it",
+                            self.addr.ip()
+                        );
+                    }
+                    return Ok(());
+                }
 
-                let payload = StatusResponse::new(
-                    ServerVersion::new("Latest".into(), 759),
-                    ServerPlayers::new(self.config.max_players as i32, 0, vec![]),
-                    lobster(&self.config.motd),
-                    self.runtime.favicon.clone(),
-                );
+                let payload = self
+                    .status
+                    .status(*handshake.protocol_version() as u32)
+                    .await;
 
                 self.send_packet(OutStatus::PacketStatusOutResponse(
                     PacketStatusOutResponse::new(payload),
                 ))
                 .await?;
 
-                match self.read_packet::<PacketStatusInPing>().await {
-                    Ok(ping) => {
+                // some server list tools close the connection right after the response
+                match self.inbound.try_read_packet::<InStatus>().await {
+                    Ok(Some(InStatus::PacketStatusInPing(ping))) => {
                         self.send_packet(PacketStatusOutPong::new(*ping.payload()))
                             .await?;
                     }
+                    Ok(Some(other)) => {
+                        if self.config.log_connections {
+                            warn!("Expected ping packet from status call, got {:?}", other);
+                        }
+                    }
+                    Ok(None) => {
+                        debug!("Client {} closed status call without ping", self.addr.ip());
+                    }
                     Err(e) => {
-                        warn!("Didn't receive ping packet from status call: {}", e);
+                        if self.config.log_connections {
+                            warn!("Didn't receive ping packet from status call: {}", e);
+                        }
                     }
                 }
             }
-            HandshakeState::Login => {
+            HandshakeState::Login | HandshakeState::Transfer => {
                 self.state = ProtocolState::Login;
+                self.pending = None;
 
-                warn!("Logging in is not yet implemented!");
+                if let Some(reason) = login_intent_denial(&self.config, handshake.next_state()) {
+                    self.disconnect(reason).await?;
+                    return Ok(());
+                }
 
-                self.disconnect(lobster("<red>Logging in is not yet supported!"))
+                // packets are only written in the layouts of the supported versions
+                let version = *handshake.protocol_version() as u32;
+                if let Some(reason) = protocol_denial(&self.config, version) {
+                    debug!(
+                        "Refusing login from {} with unsupported protocol {}",
+                        self.addr.ip(),
+                        version
+                    );
+                    self.disconnect(reason).await?;
+                    return Ok(());
+                }
+
+                if self.config.bungeecord_forwarding {
+                    match handshake.bungee_forward() {
+                        Ok(forward) => {
+                            // the proxy connects on behalf of the player
+                            self.set_addr(SocketAddr::new(forward.address, self.addr.port()));
+                            self.forward = Some(forward);
+                        }
+                        Err(_) => {
+                            self.disconnect(lobster(
+                                "<red>This server can only be joined through its proxy!",
+                            ))
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+
+                let start = match self.read_packet::<InLogin>().await? {
+                    InLogin::PacketLoginInStart(start) => start,
+                    other => {
+                        warn!("Expected login start packet, got {:?}", other);
+                        bail!("Expected login start packet, got {:?}", other)
+                    }
+                };
+                if start.validate_name().is_err() {
+                    self.disconnect(lobster(
+                        "<red>Usernames must be 3 to 16 letters, digits or underscores!",
+                    ))
                     .await?;
+                    return Ok(());
+                }
 
-                return Ok(());
+                self.login(start).await?;
             }
         };
 
         Ok(())
     }
 
+    /// Answers a legacy server list ping with the current status and closes the connection.
+    async fn answer_legacy_ping(&mut self) -> anyhow::Result<()> {
+        self.state = ProtocolState::Status;
+        if self.config.log_connections {
+            info!("Client {} sent a legacy server list ping", self.addr.ip());
+        }
+        let status = self.status.status(LEGACY_PING_PROTOCOL_VERSION).await;
+        self.outgoing
+            .write_raw(&legacy_ping_response(&status))
+            .await?;
+        self.outgoing.close().await
+    }
+
+    /// Logs the player in, moving the connection to the play state unless it was disconnected.
+    async fn login(&mut self, start: PacketLoginInStart) -> anyhow::Result<()> {
+        // the load failure was already logged on startup
+        if self.runtime.registry_codec.is_none() {
+            debug!(
+                "Refusing login of {}, no registry codec is loaded",
+                start.name()
+            );
+            self.disconnect(lobster("<red>The server is misconfigured!"))
+                .await?;
+            return Ok(());
+        }
+
+        // the proxy already authenticated forwarded players
+        let (uuid, name, properties) = match &self.forward {
+            Some(forward) => (
+                forward.uuid,
+                start.name().clone(),
+                forward.properties.clone(),
+            ),
+            None if self.config.online_mode => match self.authenticate(&start).await? {
+                Ok(profile) => (profile.id, profile.name, profile.properties),
+                Err(reason) => {
+                    self.disconnect(reason).await?;
+                    return Ok(());
+                }
+            },
+            None => (offline_uuid(start.name()), start.name().clone(), vec![]),
+        };
+
+        // checked after authentication, so a bypassing username can not be spoofed in online mode
+        if self.maintenance.is_enabled() && !self.config.bypasses_maintenance(&uuid, &name) {
+            info!(
+                "Refusing login of {} ({}), the server is in maintenance",
+                name, uuid
+            );
+            let reason = lobster(&self.config.maintenance_kick_message);
+            self.disconnect(reason).await?;
+            return Ok(());
+        }
+
+        if self.players.try_add().is_err() {
+            self.disconnect(lobster("<red>The server is full!")).await?;
+            return Ok(());
+        }
+        self.player.set_name(name.clone());
+        let admitted = self
+            .registry
+            .admit(uuid, self.player.clone(), self.config.duplicate_login)
+            .await;
+        if let Some(reason) = admitted {
+            self.players.remove_player();
+            self.disconnect(reason).await?;
+            return Ok(());
+        }
+        self.uuid = Some(uuid);
+
+        // the login success is the first packet affected by the threshold
+        if self.config.compression().is_some() {
+            self.enable_compression().await?;
+        }
+
+        info!("{} ({}) logged in from {}", name, uuid, self.addr.ip());
+        self.send_packet(PacketLoginOutSuccess::new(uuid, name, properties))
+            .await?;
+        self.state = ProtocolState::Play;
+        self.join().await
+    }
+
+    /// Sends the packets clients expect right after the login success, starting with the join game.
+    async fn join(&mut self) -> anyhow::Result<()> {
+        let registry_codec = self
+            .runtime
+            .registry_codec
+            .as_deref()
+            .expect("Registry codec should be checked before login")
+            .clone();
+        self.send_packet(PacketPlayOutLogin::overworld(
+            self.player.entity_id(),
+            self.config.default_gamemode,
+            registry_codec,
+            self.config.max_players,
+        )?)
+        .await?;
+        self.player.set_gamemode(self.config.default_gamemode);
+        self.player.send_empty_recipes().await?;
+        self.player.send_empty_tags().await?;
+        self.player
+            .send_server_links(self.config.server_links())
+            .await?;
+        Ok(())
+    }
+
+    /// Runs the encryption exchange and checks with the session server that the player joined.
+    ///
+    /// Both channels are encrypted afterwards, returns the disconnect reason if authentication failed.
+    async fn authenticate(
+        &mut self,
+        start: &PacketLoginInStart,
+    ) -> anyhow::Result<Result<GameProfile, Component>> {
+        let keys = match &self.runtime.keys {
+            Some(keys) => keys.clone(),
+            None => {
+                warn!("Online mode is enabled, but no server key pair was generated");
+                return Ok(Err(lobster("<red>The server is misconfigured!")));
+            }
+        };
+
+        let verify_token: [u8; 4] = rand::random();
+        self.send_packet(PacketLoginOutEncryptionRequest::new(
+            String::new(),
+            keys.public_der().to_vec(),
+            verify_token.to_vec(),
+        ))
+        .await?;
+
+        let response = match self.read_packet::<InLogin>().await? {
+            InLogin::PacketLoginInEncryptionResponse(response) => response,
+            other => {
+                warn!("Expected encryption response packet, got {:?}", other);
+                bail!("Expected encryption response packet, got {:?}", other)
+            }
+        };
+
+        let token_valid = match response.verification() {
+            VerifyToken::Encrypted(token) => keys
+                .decrypt(token)
+                .map_or(false, |token| token == verify_token),
+            VerifyToken::Signed { salt, signature } => match start.public_key() {
+                Some(key) => {
+                    verify_token_signature(key.public_key(), &verify_token, *salt, signature)
+                        .is_ok()
+                }
+                None => false,
+            },
+        };
+        let secret = keys
+            .decrypt(response.shared_secret())
+            .ok()
+            .and_then(|secret| <[u8; 16]>::try_from(secret).ok());
+        let secret = match secret {
+            Some(secret) if token_valid => secret,
+            _ => {
+                warn!("Client {} failed the encryption exchange", self.addr.ip());
+                return Ok(Err(lobster("<red>Failed to verify username!")));
+            }
+        };
+
+        self.inbound.set_encryption(secret);
+        self.outgoing.set_encryption(secret);
+
+        let server_hash = mc_auth_digest("", &secret, keys.public_der());
+        match has_joined(
+            &MojangSessionServer,
+            start.name(),
+            &server_hash,
+            &self.config.session_server,
+        )
+        .await
+        {
+            Ok(Some(profile)) => Ok(Ok(profile)),
+            Ok(None) => {
+                warn!("{} did not authenticate with Mojang", start.name());
+                Ok(Err(lobster("<red>Failed to verify username!")))
+            }
+            Err(e) => {
+                warn!("Could not authenticate {}: {}", start.name(), e);
+                Ok(Err(lobster(
+                    "<red>Could not reach the authentication servers, try again later!",
+                )))
+            }
+        }
+    }
+
     pub async fn disconnect(&mut self, reason: Component) -> anyhow::Result<()> {
         let mut r = reason.clone();
         match self.state {
@@ -143,9 +589,12 @@ impl ClientConnection {
                     .await?
             }
             ProtocolState::Play => {
+                // packets queued before the disconnect, e.g. a final broadcast, must not be lost
+                self.outgoing.drain(DISCONNECT_DRAIN_TIMEOUT).await?;
                 self.outgoing
                     .send_packet(PacketPlayOutDisconnect::new(reason))
-                    .await?
+                    .await?;
+                self.outgoing.close().await?
             }
             _ => bail!("Can not disconnect player during {:?} state!", self.state),
         };
@@ -154,7 +603,47 @@ impl ClientConnection {
         Ok(())
     }
 
+    /// Announces the configured compression threshold and compresses further packets with it.
+    ///
+    /// A negative threshold is still announced, but leaves both channels uncompressed.
+    pub(crate) async fn enable_compression(&mut self) -> anyhow::Result<()> {
+        self.send_packet(PacketLoginOutCompression::new(
+            self.config.compression_threshold,
+        ))
+        .await?;
+
+        if let Some(threshold) = self.config.compression() {
+            self.inbound.set_compression(threshold);
+            self.outgoing.set_compression(threshold);
+        }
+        Ok(())
+    }
+
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.outgoing.enc.compression_threshold()
+    }
+
+    /// Debugging aid, records the raw inbound byte stream of this connection into a file.
+    ///
+    /// Recordings can be replayed through a [`PacketDecoder`] to reproduce protocol bugs.
+    pub async fn record_stream<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        self.inbound.record_to(path).await
+    }
+
+    pub fn player_handle(&self) -> PlayerHandle {
+        self.player.clone()
+    }
+
+    /// Reads the next packet directly from the inbound channel.
+    ///
+    /// Only valid before play, afterwards the play loop owns the channel and routes every
+    /// packet to the receiver, so reading here as well would steal its bytes.
     pub async fn read_packet<P: PacketRead>(&mut self) -> anyhow::Result<P> {
+        assert_ne!(
+            self.state,
+            ProtocolState::Play,
+            "Packets in play are read by the play loop"
+        );
         self.inbound.read_packet().await
     }
 
@@ -163,25 +652,183 @@ impl ClientConnection {
     }
 }
 
+/// Returns a disconnect reason if the configuration does not allow logins with this intent.
+pub(crate) fn login_intent_denial(
+    config: &SoulflameConfiguration,
+    intent: &HandshakeState,
+) -> Option<Component> {
+    if config.accept_transfers_only && !matches!(intent, HandshakeState::Transfer) {
+        return Some(lobster(
+            "<red>This server can only be joined through a transfer!",
+        ));
+    }
+    None
+}
+
+/// Returns a disconnect reason if clients of this protocol version can not join.
+pub(crate) fn protocol_denial(config: &SoulflameConfiguration, version: u32) -> Option<Component> {
+    if SUPPORTED_PROTOCOLS.contains(&version) {
+        return None;
+    }
+    let reason = if version < LATEST_PROTOCOL_VERSION {
+        format!("<red>Outdated client! Please use {}", config.version_name())
+    } else {
+        format!(
+            "<red>Outdated server! I'm still on {}",
+            config.version_name()
+        )
+    };
+    Some(lobster(&reason))
+}
+
+/// First byte of a server list ping from clients older than 1.7, which send no handshake.
+pub const LEGACY_PING: u8 = 0xFE;
+
+/// Protocol version sent in legacy ping responses, which no legacy client matches, like vanilla.
+pub const LEGACY_PING_PROTOCOL_VERSION: u32 = 127;
+
+/// Kick packet answering a legacy ping, with the status fields separated by null characters.
+pub fn legacy_ping_response(status: &StatusResponse) -> Vec<u8> {
+    let text = format!(
+        "§1\0{}\0{}\0{}\0{}\0{}",
+        LEGACY_PING_PROTOCOL_VERSION,
+        status.version().name(),
+        status.description().clone().flatten(),
+        status.players().online(),
+        status.players().max()
+    );
+    let mut chars = text.encode_utf16().collect::<Vec<_>>();
+    chars.truncate(u16::MAX as usize);
+
+    let mut bytes = vec![0xFF];
+    bytes.extend((chars.len() as u16).to_be_bytes());
+    for char in chars {
+        bytes.extend(char.to_be_bytes());
+    }
+    bytes
+}
+
+/// Runs both directions of a connection until either of them ends.
+///
+/// The other direction is cancelled along with it, so no task outlives the connection.
+pub async fn run_channels(
+    inbound: InboundPacketChannel,
+    outgoing: OutgoingPacketChannel,
+    scheduling: ChannelScheduling,
+) -> anyhow::Result<()> {
+    match scheduling {
+        ChannelScheduling::Shared => {
+            tokio::select! {
+                result = inbound.start() => result,
+                result = outgoing.start() => result,
+            }
+        }
+        ChannelScheduling::Split => {
+            // both tasks are aborted on drop, even if this future is cancelled itself
+            let mut inbound = AbortOnDrop(tokio::spawn(inbound.start()));
+            let mut outgoing = AbortOnDrop(tokio::spawn(outgoing.start()));
+            tokio::select! {
+                result = &mut inbound.0 => result?,
+                result = &mut outgoing.0 => result?,
+            }
+        }
+    }
+}
+
+/// Disconnects the player once the shutdown is triggered.
+///
+/// The outgoing channel closes after writing the disconnect, ending the connection.
+async fn kick_on_shutdown(mut shutdown: Shutdown, player: PlayerHandle, reason: Component) {
+    shutdown.wait().await;
+    if let Err(e) = player.kick(reason).await {
+        debug!(
+            "Could not disconnect {} on shutdown: {}",
+            player.addr().ip(),
+            e
+        );
+    }
+    // keeps the listener waiting until the connection is gone
+    std::future::pending::<()>().await;
+}
+
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// How long queued packets may take to be written when a play connection is disconnected.
+pub const DISCONNECT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+pub type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// How often an idle inbound channel checks whether the server dropped its packet receiver.
+const RECEIVER_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Resolves once every receiver of the packets was dropped.
+async fn receiver_dropped(packets: &Sender<PacketPlayIn>) {
+    let mut interval = tokio::time::interval(RECEIVER_CHECK_INTERVAL);
+    while !packets.is_disconnected() {
+        interval.tick().await;
+    }
+}
+
 pub struct InboundPacketChannel {
-    reader: OwnedReadHalf,
+    reader: BoxedReader,
     packets: Sender<PacketPlayIn>,
     dec: PacketDecoder,
     buffer: [u8; 1024],
     addr: SocketAddr,
+    recorder: Option<File>,
+    log_connections: bool,
+    keep_alive: Option<KeepAlive>,
+    read_timeout: Option<Duration>,
 }
 
 impl InboundPacketChannel {
-    pub fn new(reader: OwnedReadHalf, packets: Sender<PacketPlayIn>, addr: SocketAddr) -> Self {
+    /// Creates the channel, reads waiting longer than `read_timeout` end the connection.
+    pub fn new(
+        reader: BoxedReader,
+        packets: Sender<PacketPlayIn>,
+        addr: SocketAddr,
+        read_timeout: Option<Duration>,
+    ) -> Self {
         Self {
             reader,
             packets,
             dec: PacketDecoder::new(),
             buffer: [0u8; 1024],
             addr,
+            recorder: None,
+            log_connections: true,
+            keep_alive: None,
+            read_timeout,
         }
     }
 
+    /// Whether clean disconnects are logged, errors are logged either way.
+    pub fn set_log_connections(&mut self, log: bool) {
+        self.log_connections = log;
+    }
+
+    /// Acknowledges the keep-alives answered by the client in play.
+    pub fn set_keep_alive(&mut self, keep_alive: KeepAlive) {
+        self.keep_alive = Some(keep_alive);
+    }
+
+    pub fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = addr;
+    }
+
+    /// Writes every byte received from the client into the file, as it arrives on the wire.
+    pub async fn record_to<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        self.recorder = Some(File::create(path).await?);
+        Ok(())
+    }
+
     pub fn set_encryption(&mut self, key: [u8; 16]) {
         self.dec.set_encryption(key);
     }
@@ -190,40 +837,110 @@ impl InboundPacketChannel {
         self.dec.set_compression(threshold);
     }
 
+    pub fn set_max_packet_size(&mut self, max: usize) {
+        self.dec.set_max_packet_size(max);
+    }
+
+    pub fn set_protocol_version(&mut self, version: u32) {
+        self.dec.set_protocol_version(version);
+    }
+
     pub async fn start(mut self) -> anyhow::Result<()> {
+        let packets = self.packets.clone();
         loop {
-            let packet = self.read_packet::<PacketPlayIn>().await?;
+            let next = tokio::select! {
+                next = self.try_read_packet::<PacketPlayIn>() => next?,
+                _ = receiver_dropped(&packets) => {
+                    warn!("Server dropped connection for client {}!", self.addr.ip());
+                    bail!("Server dropped connection for client {}!", self.addr.ip())
+                }
+            };
+            let packet = match next {
+                Some(packet) => packet,
+                None => {
+                    if self.log_connections {
+                        info!("Client {} closed the connection", self.addr.ip());
+                    }
+                    return Ok(());
+                }
+            };
+            if let (PacketPlayIn::PacketPlayInKeepAlive(answer), Some(keep_alive)) =
+                (&packet, &self.keep_alive)
+            {
+                keep_alive.acknowledge(*answer.keep_alive_id());
+            }
             if let Err(_) = self.packets.send_async(packet).await {
-                info!("Server dropped connection for client {}!", self.addr.ip());
-                return Ok(());
+                // ending with an error cancels the outgoing side and closes the socket
+                warn!("Server dropped connection for client {}!", self.addr.ip());
+                bail!("Server dropped connection for client {}!", self.addr.ip())
             }
         }
     }
 
     pub async fn read_packet<P: PacketRead>(&mut self) -> anyhow::Result<P> {
+        match self.try_read_packet().await? {
+            Some(packet) => Ok(packet),
+            None => {
+                warn!("Read 0 bytes from client!");
+                bail!("Read 0 bytes from client!")
+            }
+        }
+    }
+
+    /// Reads the next packet, or `None` if the client closed the connection between packets.
+    pub async fn try_read_packet<P: PacketRead>(&mut self) -> anyhow::Result<Option<P>> {
         loop {
             let next: Option<P> = self.dec.read::<P>().await?;
-            if let Some(packet) = next {
-                return Ok(packet);
+            if next.is_some() {
+                return Ok(next);
             }
 
-            // 5s timeout
-            let time = Duration::from_secs(5);
+            if self.receive().await? == 0 {
+                if self.dec.has_pending() {
+                    warn!("Client closed connection in the middle of a packet!");
+                    bail!("Client closed connection in the middle of a packet!")
+                }
+                return Ok(None);
+            }
+        }
+    }
 
-            let read = timeout(time, self.reader.read(&mut self.buffer)).await??;
-            if read == 0 {
-                warn!("Read 0 bytes from client!");
-                bail!("Read 0 bytes from client!")
+    /// Returns the next byte without consuming it, `None` if the client closed the connection.
+    pub async fn peek_byte(&mut self) -> anyhow::Result<Option<u8>> {
+        loop {
+            if let Some(byte) = self.dec.peek_byte() {
+                return Ok(Some(byte));
+            }
+            if self.receive().await? == 0 {
+                return Ok(None);
             }
+        }
+    }
 
-            let bytes = &self.buffer[..read];
-            self.dec.digest(bytes);
+    /// Hands the bytes of the next read to the decoder, returns how many were read.
+    async fn receive(&mut self) -> anyhow::Result<usize> {
+        let read = match self.read_timeout {
+            Some(time) => match timeout(time, self.reader.read(&mut self.buffer)).await {
+                Ok(read) => read?,
+                Err(_) => {
+                    warn!("Client {} timed out", self.addr.ip());
+                    bail!("Client {} timed out", self.addr.ip())
+                }
+            },
+            None => self.reader.read(&mut self.buffer).await?,
+        };
+        let bytes = &self.buffer[..read];
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_all(bytes).await?;
+            recorder.flush().await?;
         }
+        self.dec.digest(bytes);
+        Ok(read)
     }
 }
 
 pub struct OutgoingPacketChannel {
-    writer: OwnedWriteHalf,
+    writer: BoxedWriter,
     packets: Receiver<PacketPlayOut>,
     enc: PacketEncoder,
     buffer: Vec<u8>,
@@ -231,7 +948,7 @@ pub struct OutgoingPacketChannel {
 }
 
 impl OutgoingPacketChannel {
-    pub fn new(writer: OwnedWriteHalf, packets: Receiver<PacketPlayOut>, addr: SocketAddr) -> Self {
+    pub fn new(writer: BoxedWriter, packets: Receiver<PacketPlayOut>, addr: SocketAddr) -> Self {
         Self {
             writer,
             packets,
@@ -241,6 +958,10 @@ impl OutgoingPacketChannel {
         }
     }
 
+    pub fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = addr;
+    }
+
     pub fn set_encryption(&mut self, key: [u8; 16]) {
         self.enc.set_encryption(key);
     }
@@ -249,17 +970,71 @@ impl OutgoingPacketChannel {
         self.enc.set_compression(threshold);
     }
 
+    pub fn set_protocol_version(&mut self, version: u32) {
+        self.enc.set_protocol_version(version);
+    }
+
     pub async fn start(mut self) -> anyhow::Result<()> {
         while let Ok(packet) = self.packets.recv_async().await {
+            let disconnect = matches!(packet, PacketPlayOut::PacketPlayOutDisconnect(_));
             self.send_packet(packet).await?;
+            if disconnect {
+                // everything queued before the disconnect has been written already
+                return self.close().await;
+            }
+            // a full queue never waits on its own, give the inbound side a chance to read
+            tokio::task::yield_now().await;
         }
         Ok(())
     }
 
     pub async fn send_packet<P: PacketWrite + Debug>(&mut self, packet: P) -> anyhow::Result<()> {
-        self.enc.consume(&mut self.buffer, &packet).await?;
-        self.writer.write_all(&self.buffer).await?;
+        let written = match self.enc.consume(&mut self.buffer, &packet).await {
+            Ok(()) => self
+                .writer
+                .write_all(&self.buffer)
+                .await
+                .map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        // a frame that failed must not end up in front of the next packet
         self.buffer.clear();
+        written
+    }
+
+    /// Writes the packets that are already queued, giving up once the timeout passes.
+    ///
+    /// The timeout is checked between packets, so no packet is cut off in the middle of its frame.
+    pub async fn drain(&mut self, time: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + time;
+        while !self.packets.is_empty() {
+            if Instant::now() >= deadline {
+                warn!(
+                    "Could not write all queued packets to {} in time, {} left",
+                    self.addr.ip(),
+                    self.packets.len()
+                );
+                break;
+            }
+            match self.packets.try_recv() {
+                Ok(packet) => self.send_packet(packet).await?,
+                Err(_) => break,
+            }
+        }
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Writes bytes as they are, bypassing the packet framing.
+    pub async fn write_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.writer.write_all(bytes).await?;
+        Ok(())
+    }
+
+    /// Flushes the written packets and closes the write half of the connection.
+    pub async fn close(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().await?;
+        self.writer.shutdown().await?;
         Ok(())
     }
 }
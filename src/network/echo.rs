@@ -0,0 +1,27 @@
+//! Minimal server behaviour for manual protocol testing, echoing chat back to the sender.
+
+use crate::chat::Component;
+use crate::network::handler::PacketHandler;
+use crate::network::player::PlayerHandle;
+use crate::protocol::client::play::PacketPlayInChatMessage;
+use crate::protocol::server::play::PacketPlayOutSystemChat;
+use async_trait::async_trait;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct EchoHandler;
+
+#[async_trait]
+impl PacketHandler for EchoHandler {
+    async fn on_chat(
+        &self,
+        player: &PlayerHandle,
+        packet: &PacketPlayInChatMessage,
+    ) -> anyhow::Result<()> {
+        player
+            .send_packet(PacketPlayOutSystemChat::new(
+                Component::text(packet.message().clone()),
+                0,
+            ))
+            .await
+    }
+}
@@ -1,20 +1,30 @@
+use crate::net_io::hexdump::annotated_hex_dump;
 use crate::net_io::{PacketRead, PacketWrite, VarInt};
 use crate::LATEST_PROTOCOL_VERSION;
-use aes::cipher::{AsyncStreamCipher, KeyIvInit};
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use aes::Aes128;
+use anyhow::bail;
 use async_compression::tokio::bufread::{ZlibDecoder, ZlibEncoder};
+use bytes::BufMut;
 use cfb8::{Decryptor, Encryptor};
+use log::{log_enabled, trace, warn, Level};
 use std::io::Cursor;
 use tokio::io::AsyncReadExt;
 
 pub type AesEnc = Encryptor<Aes128>;
 pub type AesDec = Decryptor<Aes128>;
 
+/// Largest packet accepted from clients by default, before and after decompression.
+pub const MAX_PACKET_SIZE: usize = 2 * 1024 * 1024;
+
 #[derive(Debug, Clone)]
 pub struct PacketEncoder {
     encryptor: Option<AesEnc>,
     shared_secret: Option<[u8; 16]>,
     staging_buf: Vec<u8>,
+    frame_buf: Vec<u8>,
+    protocol_version: u32,
 
     compression_threshold: Option<usize>,
     compression_buf: Vec<u8>,
@@ -26,11 +36,18 @@ impl PacketEncoder {
             encryptor: None,
             shared_secret: None,
             staging_buf: vec![],
+            frame_buf: vec![],
+            protocol_version: LATEST_PROTOCOL_VERSION,
             compression_threshold: None,
             compression_buf: vec![],
         }
     }
 
+    /// Protocol version packets are written for, the latest one by default.
+    pub fn set_protocol_version(&mut self, version: u32) {
+        self.protocol_version = version;
+    }
+
     pub fn set_encryption(&mut self, key: [u8; 16]) {
         self.encryptor = Some(AesEnc::new_from_slices(&key, &key).expect("Invalid key size!"));
         self.shared_secret = Some(key);
@@ -40,31 +57,50 @@ impl PacketEncoder {
         self.compression_threshold = Some(threshold);
     }
 
-    pub async fn consume<P: PacketWrite>(
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Frames the packet into any [`BufMut`], reusing the encoder's buffers for every packet.
+    pub async fn consume<P: PacketWrite, B: BufMut>(
         &mut self,
-        out_buffer: &mut Vec<u8>,
+        out_buffer: &mut B,
         packet: &P,
     ) -> anyhow::Result<()> {
         packet
-            .pack_write(&mut self.staging_buf, LATEST_PROTOCOL_VERSION)
+            .pack_write(&mut self.staging_buf, self.protocol_version)
+            .await?;
+
+        #[cfg(feature = "metrics")]
+        {
+            let VarInt(id) = VarInt::pack_read(
+                &mut Cursor::new(&self.staging_buf[..]),
+                self.protocol_version,
+            )
             .await?;
+            crate::network::metrics::record_packet_size::<P>(id, self.staging_buf.len());
+        }
 
         if let Some(_) = self.compression_threshold {
-            self.write_compressed(out_buffer).await?;
+            self.write_compressed().await?;
         } else {
-            self.write(out_buffer).await?;
+            self.write().await?;
         }
 
         if let Some(enc) = &mut self.encryptor {
-            enc.clone().encrypt(out_buffer);
+            for byte in self.frame_buf.chunks_mut(1) {
+                enc.encrypt_block_mut(GenericArray::from_mut_slice(byte));
+            }
         }
+        out_buffer.put_slice(&self.frame_buf);
 
         self.staging_buf.clear();
+        self.frame_buf.clear();
 
         Ok(())
     }
 
-    async fn write_compressed(&mut self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    async fn write_compressed(&mut self) -> anyhow::Result<()> {
         let threshold = self.compression_threshold.unwrap();
         let mut data_len = 0;
         let mut slice = self.staging_buf.as_slice();
@@ -76,31 +112,29 @@ impl PacketEncoder {
             data_len = self.staging_buf.len();
         }
 
-        let mut buf: Vec<u8> = vec![];
+        let mut data_len_buf: Vec<u8> = vec![];
         VarInt(data_len as i32)
-            .pack_write(&mut buf, LATEST_PROTOCOL_VERSION)
+            .pack_write(&mut data_len_buf, LATEST_PROTOCOL_VERSION)
             .await?;
 
-        let packet_size = buf.len() + slice.len();
+        let packet_size = data_len_buf.len() + slice.len();
         VarInt(packet_size as i32)
-            .pack_write(&mut buf, LATEST_PROTOCOL_VERSION)
-            .await?;
-        VarInt(data_len as i32)
-            .pack_write(&mut buf, LATEST_PROTOCOL_VERSION)
+            .pack_write(&mut self.frame_buf, LATEST_PROTOCOL_VERSION)
             .await?;
-        buffer.extend_from_slice(&buf);
+        self.frame_buf.extend_from_slice(&data_len_buf);
+        self.frame_buf.extend_from_slice(slice);
 
         self.compression_buf.clear();
 
         Ok(())
     }
 
-    async fn write(&mut self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    async fn write(&mut self) -> anyhow::Result<()> {
         let packet_len = self.staging_buf.len() as i32;
         VarInt(packet_len)
-            .pack_write(buffer, LATEST_PROTOCOL_VERSION)
+            .pack_write(&mut self.frame_buf, LATEST_PROTOCOL_VERSION)
             .await?;
-        buffer.extend_from_slice(&self.staging_buf);
+        self.frame_buf.extend_from_slice(&self.staging_buf);
 
         Ok(())
     }
@@ -114,6 +148,8 @@ pub struct PacketDecoder {
 
     compression_threshold: Option<usize>,
     compression_buf: Vec<u8>,
+    max_packet_size: usize,
+    protocol_version: u32,
 }
 
 impl PacketDecoder {
@@ -124,9 +160,21 @@ impl PacketDecoder {
             staging_buf: vec![],
             compression_threshold: None,
             compression_buf: vec![],
+            max_packet_size: MAX_PACKET_SIZE,
+            protocol_version: LATEST_PROTOCOL_VERSION,
         }
     }
 
+    /// Protocol version packets are read for, the latest one by default.
+    pub fn set_protocol_version(&mut self, version: u32) {
+        self.protocol_version = version;
+    }
+
+    /// Limits the declared and the decompressed length of packets, larger packets are an error.
+    pub fn set_max_packet_size(&mut self, max: usize) {
+        self.max_packet_size = max;
+    }
+
     pub fn set_encryption(&mut self, key: [u8; 16]) {
         self.decryptor = Some(AesDec::new_from_slices(&key, &key).expect("Invalid key size!"));
         self.shared_secret = Some(key);
@@ -136,47 +184,108 @@ impl PacketDecoder {
         self.compression_threshold = Some(threshold);
     }
 
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Whether bytes of an incomplete packet are still waiting for the rest of it.
+    pub fn has_pending(&self) -> bool {
+        !self.staging_buf.is_empty()
+    }
+
+    /// First byte that was digested but not read yet.
+    pub fn peek_byte(&self) -> Option<u8> {
+        self.staging_buf.first().copied()
+    }
+
     pub fn digest(&mut self, packet_bytes: &[u8]) {
+        let start = self.staging_buf.len();
         self.staging_buf.extend(packet_bytes);
 
+        // only the new bytes are decrypted, advancing the cipher for the next digest
         if let Some(dec) = &mut self.decryptor {
-            dec.clone().decrypt(&mut self.staging_buf[..]);
+            for byte in self.staging_buf[start..].chunks_mut(1) {
+                dec.decrypt_block_mut(GenericArray::from_mut_slice(byte));
+            }
         }
     }
 
     pub async fn read<P: PacketRead>(&mut self) -> anyhow::Result<Option<P>> {
         let mut reader = Cursor::new(&self.staging_buf[..]);
-        let packet = if let Ok(VarInt(size)) =
-            VarInt::pack_read(&mut reader, LATEST_PROTOCOL_VERSION).await
-        {
-            let varint_len = reader.position() as usize;
+        let size = match VarInt::pack_read(&mut reader, LATEST_PROTOCOL_VERSION).await {
+            Ok(VarInt(size)) => size,
+            // the buffer ends in the middle of the length, more bytes have to arrive first
+            Err(_)
+                if self.staging_buf.len() < 5
+                    && self.staging_buf.iter().all(|byte| byte & 0x80 != 0) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let varint_len = reader.position() as usize;
+        if size < 0 || size as usize > self.max_packet_size {
+            warn!("Invalid packet length: {}", size);
+            bail!("Invalid packet length: {}", size)
+        }
 
-            if self.staging_buf.len() - varint_len >= size as usize {
-                reader = Cursor::new(&self.staging_buf[varint_len..varint_len + size as usize]);
+        let packet = if self.staging_buf.len() - varint_len >= size as usize {
+            reader = Cursor::new(&self.staging_buf[varint_len..varint_len + size as usize]);
 
-                if let Some(_) = self.compression_threshold {
-                    let VarInt(data_len) =
-                        VarInt::pack_read(&mut reader, LATEST_PROTOCOL_VERSION).await?;
+            if let Some(_) = self.compression_threshold {
+                let VarInt(data_len) =
+                    VarInt::pack_read(&mut reader, LATEST_PROTOCOL_VERSION).await?;
 
-                    if data_len > 0 {
-                        let mut dec =
-                            ZlibDecoder::new(&reader.get_ref()[reader.position() as usize..]);
-                        dec.read_to_end(&mut self.compression_buf).await?;
-                        reader = Cursor::new(&self.compression_buf);
+                if data_len < 0 || data_len as usize > self.max_packet_size {
+                    warn!("Invalid decompressed packet length: {}", data_len);
+                    bail!("Invalid decompressed packet length: {}", data_len)
+                }
+                if data_len > 0 {
+                    // reading one byte past the declared length is enough to notice a lie
+                    let dec = ZlibDecoder::new(&reader.get_ref()[reader.position() as usize..]);
+                    dec.take(data_len as u64 + 1)
+                        .read_to_end(&mut self.compression_buf)
+                        .await?;
+                    let decompressed = self.compression_buf.len();
+                    if decompressed != data_len as usize {
+                        self.compression_buf.clear();
+                        warn!(
+                            "Packet decompressed to {} bytes instead of {}",
+                            decompressed, data_len
+                        );
+                        bail!(
+                            "Packet decompressed to {} bytes instead of {}",
+                            decompressed,
+                            data_len
+                        )
                     }
+                    reader = Cursor::new(&self.compression_buf);
                 }
+            }
 
-                let packet = P::pack_read(&mut reader, LATEST_PROTOCOL_VERSION).await?;
+            let packet = match P::pack_read(&mut reader, self.protocol_version).await {
+                Ok(packet) => packet,
+                Err(e) => {
+                    if log_enabled!(Level::Trace) {
+                        trace!(
+                            "Failed to decode packet: {}\n{}",
+                            e,
+                            annotated_hex_dump(
+                                &self.staging_buf[..varint_len + size as usize],
+                                true
+                            )
+                        );
+                    }
+                    return Err(e);
+                }
+            };
 
-                let read = size as usize + varint_len;
-                self.staging_buf = self.staging_buf.split_off(read);
+            let read = size as usize + varint_len;
+            self.staging_buf = self.staging_buf.split_off(read);
 
-                self.compression_buf.clear();
+            self.compression_buf.clear();
 
-                Some(packet)
-            } else {
-                None
-            }
+            Some(packet)
         } else {
             None
         };
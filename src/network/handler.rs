@@ -0,0 +1,194 @@
+use crate::network::player::PlayerHandle;
+use crate::network::registry::PlayerRegistry;
+use crate::protocol::client::play::{
+    ClientCommandAction, PacketPlayIn, PacketPlayInChatMessage, PacketPlayInInteract,
+    PacketPlayInPluginMessage, PacketPlayInSetPlayerPosition, PacketPlayInSwingArm,
+    PacketPlayInUpdateSign,
+};
+use crate::protocol::server::play::PacketPlayOutRespawn;
+use async_trait::async_trait;
+use flume::Receiver;
+use log::warn;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Hooks for reacting to inbound play packets.
+///
+/// Every hook does nothing by default, except for the respawn and statistics requests, which
+/// clients wait on an answer for.
+#[async_trait]
+pub trait PacketHandler: Send + Sync {
+    async fn on_chat(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInChatMessage,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called when a dead player clicks respawn, respawns the player in the overworld by default,
+    /// keeping its game mode.
+    async fn on_respawn(&self, player: &PlayerHandle) -> anyhow::Result<()> {
+        player
+            .respawn(PacketPlayOutRespawn::overworld(player.gamemode())?)
+            .await
+    }
+
+    /// Called when the player opens the statistics menu, answers with no statistics by default.
+    async fn on_request_stats(&self, player: &PlayerHandle) -> anyhow::Result<()> {
+        player.send_statistics(vec![]).await
+    }
+
+    async fn on_interact(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInInteract,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn on_move(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInSetPlayerPosition,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn on_plugin_message(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInPluginMessage,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn on_swing_arm(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInSwingArm,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Called with the text of a sign the player finished editing.
+    async fn on_update_sign(
+        &self,
+        _player: &PlayerHandle,
+        _packet: &PacketPlayInUpdateSign,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Handler that only applies the built-in server-side state changes.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoopHandler;
+
+impl PacketHandler for NoopHandler {}
+
+/// Handler showing players' arm swings to the players around them.
+#[derive(Debug, Clone)]
+pub struct AnimationHandler {
+    registry: PlayerRegistry,
+}
+
+impl AnimationHandler {
+    pub fn new(registry: PlayerRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+#[async_trait]
+impl PacketHandler for AnimationHandler {
+    async fn on_swing_arm(
+        &self,
+        player: &PlayerHandle,
+        packet: &PacketPlayInSwingArm,
+    ) -> anyhow::Result<()> {
+        self.registry.broadcast_swing(player, *packet.hand()).await
+    }
+}
+
+/// Applies an inbound play packet to the player's server-side state and passes it to the handler.
+pub async fn dispatch(
+    handler: &dyn PacketHandler,
+    player: &PlayerHandle,
+    packet: PacketPlayIn,
+) -> anyhow::Result<()> {
+    match packet {
+        PacketPlayIn::PacketPlayInChatMessage(chat) => handler.on_chat(player, &chat).await,
+        PacketPlayIn::PacketPlayInClientCommand(command) => match command.action() {
+            ClientCommandAction::PerformRespawn => handler.on_respawn(player).await,
+            ClientCommandAction::RequestStats => handler.on_request_stats(player).await,
+        },
+        PacketPlayIn::PacketPlayInCloseContainer(close) => {
+            player.on_window_closed(*close.window_id())
+        }
+        PacketPlayIn::PacketPlayInPluginMessage(message) => {
+            player.on_plugin_message(&message)?;
+            handler.on_plugin_message(player, &message).await
+        }
+        PacketPlayIn::PacketPlayInInteract(interact) => {
+            handler.on_interact(player, &interact).await
+        }
+        PacketPlayIn::PacketPlayInSetPlayerPosition(position) => {
+            player.on_position(&position);
+            handler.on_move(player, &position).await
+        }
+        PacketPlayIn::PacketPlayInSwingArm(swing) => handler.on_swing_arm(player, &swing).await,
+        PacketPlayIn::PacketPlayInUpdateSign(sign) => {
+            sign.validate_lines()?;
+            handler.on_update_sign(player, &sign).await
+        }
+        PacketPlayIn::PacketPlayInKeepAlive(_) | PacketPlayIn::Null(_) => Ok(()),
+    }
+}
+
+/// Dispatches the packet and warns if handling it took longer than the threshold.
+///
+/// Returns the time handling took if it exceeded the threshold.
+pub async fn dispatch_timed(
+    handler: &dyn PacketHandler,
+    player: &PlayerHandle,
+    packet: PacketPlayIn,
+    threshold: Option<Duration>,
+) -> anyhow::Result<Option<Duration>> {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => return dispatch(handler, player, packet).await.map(|_| None),
+    };
+
+    let name = packet.packet_name();
+    let start = Instant::now();
+    let result = dispatch(handler, player, packet).await;
+    let elapsed = start.elapsed();
+
+    let slow = if elapsed > threshold {
+        warn!(
+            "Handling {} for {} took {}ms (threshold: {}ms)",
+            name,
+            player.addr().ip(),
+            elapsed.as_millis(),
+            threshold.as_millis()
+        );
+        Some(elapsed)
+    } else {
+        None
+    };
+    result.map(|_| slow)
+}
+
+/// Dispatches the player's inbound packets to the handler until the connection closes.
+///
+/// A packet the handler fails on ends the loop, which disconnects the player.
+pub async fn tick(
+    handler: Arc<dyn PacketHandler>,
+    player: PlayerHandle,
+    packets: Receiver<PacketPlayIn>,
+    threshold: Option<Duration>,
+) -> anyhow::Result<()> {
+    while let Ok(packet) = packets.recv_async().await {
+        dispatch_timed(handler.as_ref(), &player, packet, threshold).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::network::player::PlayerHandle;
+use crate::protocol::server::play::PacketPlayOutKeepAlive;
+use lobstermessage::lobster;
+use log::{debug, info};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{interval_at, Instant};
+
+/// How often players in play are sent a keep-alive.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long a player may leave keep-alives unanswered before being disconnected.
+pub const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keep-alive sent last to a player, and when the player last answered one.
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    inner: Arc<Mutex<KeepAliveState>>,
+}
+
+#[derive(Debug)]
+struct KeepAliveState {
+    pending: Option<i64>,
+    last_ack: Instant,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(KeepAliveState {
+                pending: None,
+                last_ack: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl KeepAlive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the player's answer, returns whether it matched the keep-alive sent last.
+    pub fn acknowledge(&self, id: i64) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        if state.pending != Some(id) {
+            debug!("Player answered unknown keep-alive {}", id);
+            return false;
+        }
+        state.pending = None;
+        state.last_ack = Instant::now();
+        true
+    }
+
+    /// Time since the player last answered a keep-alive, or since the keep-alive loop started.
+    pub fn since_ack(&self) -> Duration {
+        self.inner.lock().unwrap().last_ack.elapsed()
+    }
+
+    /// Sends keep-alives to the player, until disconnecting them for not answering in time.
+    pub async fn run(
+        self,
+        player: PlayerHandle,
+        interval: Duration,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().last_ack = Instant::now();
+
+        let mut ticks = interval_at(Instant::now() + interval, interval);
+        loop {
+            ticks.tick().await;
+            if self.since_ack() > timeout {
+                info!(
+                    "Client {} did not answer keep-alives for {}s",
+                    player.addr().ip(),
+                    timeout.as_secs()
+                );
+                return player.kick(lobster("<red>Timed out")).await;
+            }
+
+            let id = rand::random();
+            self.inner.lock().unwrap().pending = Some(id);
+            player.send_packet(PacketPlayOutKeepAlive::new(id)).await?;
+        }
+    }
+}
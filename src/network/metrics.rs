@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Upper bounds (inclusive) of the packet size buckets in bytes, the last one catches the rest.
+pub const SIZE_BUCKETS: [usize; 8] = [16, 64, 256, 1024, 4096, 16384, 65536, usize::MAX];
+
+/// Key of a recorded packet: name of the written packet or stage type, and the packet id.
+pub type PacketKey = (String, i32);
+
+#[derive(Debug, Clone, Default)]
+pub struct SizeHistogram {
+    buckets: [u64; SIZE_BUCKETS.len()],
+    count: u64,
+    total_bytes: u64,
+}
+
+impl SizeHistogram {
+    pub fn record(&mut self, size: usize) {
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|bound| size <= *bound)
+            .unwrap_or(SIZE_BUCKETS.len() - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_bytes += size as u64;
+    }
+
+    pub fn buckets(&self) -> &[u64; SIZE_BUCKETS.len()] {
+        &self.buckets
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PACKET_SIZES: Mutex<HashMap<PacketKey, SizeHistogram>> = Mutex::new(HashMap::new());
+}
+
+/// Records the serialized (uncompressed) size of an outbound packet.
+pub fn record_packet_size<P>(id: i32, size: usize) {
+    let name = std::any::type_name::<P>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    PACKET_SIZES
+        .lock()
+        .unwrap()
+        .entry((name, id))
+        .or_default()
+        .record(size);
+}
+
+/// Snapshot of the outbound packet size histograms.
+pub fn packet_sizes() -> HashMap<PacketKey, SizeHistogram> {
+    PACKET_SIZES.lock().unwrap().clone()
+}
+
+pub fn reset_packet_sizes() {
+    PACKET_SIZES.lock().unwrap().clear();
+}
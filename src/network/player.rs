@@ -0,0 +1,599 @@
+use crate::chat::Component;
+use crate::net_io::packet::Packet;
+use crate::net_io::{Angle, Flags8, Position};
+use crate::protocol::client::play::{PacketPlayInPluginMessage, PacketPlayInSetPlayerPosition};
+use crate::protocol::server::play::{
+    DamagePosition, EffectFlags, GameMode, PacketPlayOut, PacketPlayOutAwardStatistics,
+    PacketPlayOutBlockBreakAnimation, PacketPlayOutBundleDelimiter, PacketPlayOutCloseContainer,
+    PacketPlayOutDamageEvent, PacketPlayOutDisconnect, PacketPlayOutEntityEffect,
+    PacketPlayOutEntityEvent, PacketPlayOutExplosion, PacketPlayOutOpenScreen,
+    PacketPlayOutPluginMessage, PacketPlayOutRemoveEntityEffect, PacketPlayOutRespawn,
+    PacketPlayOutServerLinks, PacketPlayOutSetCooldown, PacketPlayOutSoundEffect,
+    PacketPlayOutSpawnEntity, PacketPlayOutSpawnPlayer, PacketPlayOutStopSound,
+    PacketPlayOutSynchronizePosition, PacketPlayOutTabListHeaderFooter,
+    PacketPlayOutUpdateRecipeBook, PacketPlayOutUpdateRecipes, PacketPlayOutUpdateTags,
+    PacketPlayOutWindowItems, PlayerMotion, RecipeBookAction, RecipeBookSettings, RelativeFlags,
+    ServerLink, SoundCategory, SoundFilter, SoundRef, Statistic, WindowType,
+    BUNDLE_PROTOCOL_VERSION, DAMAGE_EVENT_PROTOCOL_VERSION, ENTITY_EVENT_HURT,
+    EXPLOSION_EFFECTS_PROTOCOL_VERSION, PLAYER_ENTITY_TYPE, SERVER_LINKS_PROTOCOL_VERSION,
+    SPAWN_ENTITY_PLAYER_PROTOCOL_VERSION,
+};
+use crate::util::Identifier;
+use crate::world::block::Location;
+use crate::world::border::WorldBorder;
+use crate::world::item::Slot;
+use anyhow::bail;
+use flume::Sender;
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Window id of the player's own inventory, which is always open.
+pub const PLAYER_INVENTORY_WINDOW: u8 = 0;
+
+/// Tracks the container window currently opened for a player.
+#[derive(Debug, Default)]
+pub struct ContainerState {
+    open_window: Option<u8>,
+}
+
+impl ContainerState {
+    pub fn open(&mut self, window_id: u8) {
+        self.open_window = Some(window_id);
+    }
+
+    pub fn current(&self) -> Option<u8> {
+        self.open_window
+    }
+
+    /// Ensures the window id references the player inventory or the currently open window.
+    pub fn check(&self, window_id: u8) -> anyhow::Result<()> {
+        if window_id == PLAYER_INVENTORY_WINDOW || self.open_window == Some(window_id) {
+            return Ok(());
+        }
+        warn!(
+            "Client referenced window {} which is not open (open: {:?})",
+            window_id, self.open_window
+        );
+        bail!(
+            "Client referenced window {} which is not open (open: {:?})",
+            window_id,
+            self.open_window
+        )
+    }
+
+    pub fn close(&mut self, window_id: u8) -> anyhow::Result<()> {
+        self.check(window_id)?;
+        if window_id != PLAYER_INVENTORY_WINDOW {
+            self.open_window = None;
+        }
+        Ok(())
+    }
+}
+
+/// Cheap, cloneable handle for sending play packets to a connected player.
+#[derive(Debug, Clone)]
+pub struct PlayerHandle {
+    addr: Arc<Mutex<SocketAddr>>,
+    packets: Sender<PacketPlayOut>,
+    containers: Arc<Mutex<ContainerState>>,
+    channels: Arc<Mutex<HashSet<String>>>,
+    protocol_version: Arc<AtomicU32>,
+    entity_id: Arc<AtomicI32>,
+    location: Arc<Mutex<Location>>,
+    gamemode: Arc<Mutex<GameMode>>,
+    teleport_id: Arc<AtomicI32>,
+    name: Arc<Mutex<String>>,
+}
+
+/// Packets collected to be sent as a single bundle.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    packets: Vec<PacketPlayOut>,
+}
+
+impl Bundle {
+    pub fn push<P: Packet<PacketPlayOut>>(&mut self, packet: P) {
+        self.packets.push(packet.into_stage());
+    }
+}
+
+impl PlayerHandle {
+    pub fn new(addr: SocketAddr, packets: Sender<PacketPlayOut>) -> Self {
+        Self {
+            addr: Arc::new(Mutex::new(addr)),
+            packets,
+            containers: Arc::new(Mutex::new(ContainerState::default())),
+            channels: Arc::new(Mutex::new(HashSet::new())),
+            protocol_version: Arc::new(AtomicU32::new(crate::LATEST_PROTOCOL_VERSION)),
+            entity_id: Arc::new(AtomicI32::new(0)),
+            location: Arc::new(Mutex::new(Location::simple(0.0f32, 0.0, 0.0))),
+            gamemode: Arc::new(Mutex::new(GameMode::Survival)),
+            teleport_id: Arc::new(AtomicI32::new(0)),
+            name: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        *self.addr.lock().unwrap()
+    }
+
+    /// Replaces the address the player connected from, e.g. with the one forwarded by a proxy.
+    pub fn set_addr(&self, addr: SocketAddr) {
+        *self.addr.lock().unwrap() = addr;
+    }
+
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version.load(Ordering::Acquire)
+    }
+
+    pub fn set_protocol_version(&self, version: u32) {
+        self.protocol_version.store(version, Ordering::Release);
+    }
+
+    /// Username the player logged in with, empty before login.
+    pub fn name(&self) -> String {
+        self.name.lock().unwrap().clone()
+    }
+
+    pub fn set_name(&self, name: String) {
+        *self.name.lock().unwrap() = name;
+    }
+
+    /// Id of the player's own entity, as seen by other clients.
+    pub fn entity_id(&self) -> i32 {
+        self.entity_id.load(Ordering::Acquire)
+    }
+
+    pub fn set_entity_id(&self, entity_id: i32) {
+        self.entity_id.store(entity_id, Ordering::Release);
+    }
+
+    pub fn location(&self) -> Location {
+        *self.location.lock().unwrap()
+    }
+
+    pub fn set_location(&self, location: Location) {
+        *self.location.lock().unwrap() = location;
+    }
+
+    /// Game mode the player joined or last respawned in.
+    pub fn gamemode(&self) -> GameMode {
+        *self.gamemode.lock().unwrap()
+    }
+
+    pub fn set_gamemode(&self, gamemode: GameMode) {
+        *self.gamemode.lock().unwrap() = gamemode;
+    }
+
+    /// Makes another player visible to this one.
+    ///
+    /// Clients since [`SPAWN_ENTITY_PLAYER_PROTOCOL_VERSION`] spawn players with the spawn entity packet.
+    pub async fn spawn_player(
+        &self,
+        entity_id: i32,
+        uuid: Uuid,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        let (x, y, z) = (
+            location.x() as f64,
+            location.y() as f64,
+            location.z() as f64,
+        );
+        let yaw = Angle::from_degrees(location.yaw());
+        let pitch = Angle::from_degrees(location.pitch());
+        if self.protocol_version() >= SPAWN_ENTITY_PLAYER_PROTOCOL_VERSION {
+            self.send_packet(PacketPlayOutSpawnEntity::new(
+                entity_id,
+                uuid,
+                PLAYER_ENTITY_TYPE,
+                x,
+                y,
+                z,
+                pitch,
+                yaw,
+                yaw,
+                0,
+                0,
+                0,
+                0,
+            ))
+            .await
+        } else {
+            self.send_packet(PacketPlayOutSpawnPlayer::new(
+                entity_id, uuid, x, y, z, yaw, pitch,
+            ))
+            .await
+        }
+    }
+
+    /// Whether both handles belong to the same connection.
+    pub fn same_session(&self, other: &PlayerHandle) -> bool {
+        self.packets.same_channel(&other.packets)
+    }
+
+    pub fn open_window(&self) -> Option<u8> {
+        self.containers.lock().unwrap().current()
+    }
+
+    pub fn check_window(&self, window_id: u8) -> anyhow::Result<()> {
+        self.containers.lock().unwrap().check(window_id)
+    }
+
+    pub async fn send_packet<P: Packet<PacketPlayOut>>(&self, packet: P) -> anyhow::Result<()> {
+        if self.packets.send_async(packet.into_stage()).await.is_err() {
+            bail!(
+                "Tried to send packet to closed connection {}",
+                self.addr().ip()
+            )
+        }
+        Ok(())
+    }
+
+    /// Sends the packets collected by the closure so the client applies them in the same tick.
+    ///
+    /// Clients older than [`BUNDLE_PROTOCOL_VERSION`] receive the packets without delimiters.
+    pub async fn bundle<F: FnOnce(&mut Bundle)>(&self, f: F) -> anyhow::Result<()> {
+        let mut bundle = Bundle::default();
+        f(&mut bundle);
+
+        let delimit = self.protocol_version() >= BUNDLE_PROTOCOL_VERSION;
+        if delimit {
+            self.send_packet(PacketPlayOutBundleDelimiter::new())
+                .await?;
+        }
+        for packet in bundle.packets {
+            if self.packets.send_async(packet).await.is_err() {
+                bail!(
+                    "Tried to send packet to closed connection {}",
+                    self.addr().ip()
+                )
+            }
+        }
+        if delimit {
+            self.send_packet(PacketPlayOutBundleDelimiter::new())
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn open_inventory(
+        &self,
+        window_id: u8,
+        window_type: WindowType,
+        title: Component,
+        slots: Vec<Slot>,
+    ) -> anyhow::Result<()> {
+        if slots.len() != window_type.slot_count() {
+            warn!(
+                "Invalid slot count for window {:?} (expected: {}, received: {})",
+                window_type,
+                window_type.slot_count(),
+                slots.len()
+            );
+            bail!(
+                "Invalid slot count for window {:?} (expected: {}, received: {})",
+                window_type,
+                window_type.slot_count(),
+                slots.len()
+            )
+        }
+
+        self.send_packet(PacketPlayOutOpenScreen::new(
+            window_id as i32,
+            window_type,
+            title,
+        ))
+        .await?;
+        self.send_packet(PacketPlayOutWindowItems::new(
+            window_id,
+            0,
+            slots,
+            Slot::empty(),
+        ))
+        .await?;
+        self.containers.lock().unwrap().open(window_id);
+        Ok(())
+    }
+
+    /// Closes the currently open window, if any.
+    pub async fn close_inventory(&self) -> anyhow::Result<()> {
+        let open = self.containers.lock().unwrap().open_window.take();
+        if let Some(window_id) = open {
+            self.send_packet(PacketPlayOutCloseContainer::new(window_id))
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn kick(&self, reason: Component) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutDisconnect::new(reason)).await
+    }
+
+    /// Whether the client registered the plugin channel, `minecraft` channels are always supported.
+    pub fn supports_channel(&self, channel: &Identifier) -> bool {
+        channel.namespace() == *crate::util::MINECRAFT_NAMESPACE
+            || self.channels.lock().unwrap().contains(&channel.to_string())
+    }
+
+    /// Sends a plugin message if the client registered the channel.
+    ///
+    /// Returns whether the message was sent.
+    pub async fn send_plugin_message(
+        &self,
+        channel: Identifier,
+        data: Vec<u8>,
+    ) -> anyhow::Result<bool> {
+        if !self.supports_channel(&channel) {
+            debug!(
+                "Skipping plugin message on unregistered channel {}",
+                channel.to_string()
+            );
+            return Ok(false);
+        }
+        self.send_packet(PacketPlayOutPluginMessage::new(channel, data))
+            .await?;
+        Ok(true)
+    }
+
+    /// Greys out the item for the amount of ticks, a cooldown of 0 clears it.
+    pub async fn set_item_cooldown(&self, item_id: i32, ticks: i32) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutSetCooldown::new(item_id, ticks))
+            .await
+    }
+
+    /// Shows the block cracking over the duration, removing the cracks once it is over.
+    ///
+    /// The breaking entity id only has to be unique per block being broken.
+    pub async fn animate_block_break(
+        &self,
+        entity_id: i32,
+        location: Position,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let step = duration / 10;
+        for stage in 0..10u8 {
+            self.send_packet(PacketPlayOutBlockBreakAnimation::new(
+                entity_id, location, stage,
+            ))
+            .await?;
+            tokio::time::sleep(step).await;
+        }
+        self.send_packet(PacketPlayOutBlockBreakAnimation::new(
+            entity_id, location, 10,
+        ))
+        .await
+    }
+
+    /// Decorates the tab list, an empty text component clears its section.
+    pub async fn set_tab_list(&self, header: Component, footer: Component) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutTabListHeaderFooter::new(header, footer))
+            .await
+    }
+
+    /// Shows the entity taking damage, source entity ids are sent shifted by one with `0` for none.
+    ///
+    /// Clients older than [`DAMAGE_EVENT_PROTOCOL_VERSION`] only play the hurt animation.
+    pub async fn show_damage(
+        &self,
+        entity_id: i32,
+        source_type_id: i32,
+        source_cause: Option<i32>,
+        source_direct: Option<i32>,
+        position: Option<DamagePosition>,
+    ) -> anyhow::Result<()> {
+        if self.protocol_version() < DAMAGE_EVENT_PROTOCOL_VERSION {
+            return self
+                .send_packet(PacketPlayOutEntityEvent::new(entity_id, ENTITY_EVENT_HURT))
+                .await;
+        }
+
+        self.send_packet(PacketPlayOutDamageEvent::new(
+            entity_id,
+            source_type_id,
+            source_cause.map_or(0, |id| id + 1),
+            source_direct.map_or(0, |id| id + 1),
+            position,
+        ))
+        .await
+    }
+
+    /// Applies the potion effect, amplifiers above 127 wrap around on the client and are refused.
+    pub async fn apply_effect(
+        &self,
+        entity_id: i32,
+        effect_id: i32,
+        amplifier: u8,
+        duration: i32,
+        flags: EffectFlags,
+    ) -> anyhow::Result<()> {
+        if amplifier > i8::MAX as u8 {
+            warn!("Invalid effect amplifier provided: {}!", amplifier);
+            bail!("Invalid effect amplifier provided: {}!", amplifier)
+        }
+        if duration <= 0 {
+            warn!("Invalid effect duration provided: {}!", duration);
+            bail!("Invalid effect duration provided: {}!", duration)
+        }
+
+        self.send_packet(PacketPlayOutEntityEffect::new(
+            entity_id,
+            effect_id,
+            amplifier,
+            duration,
+            Flags8(flags),
+            None,
+        ))
+        .await
+    }
+
+    pub async fn clear_effect(&self, entity_id: i32, effect_id: i32) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutRemoveEntityEffect::new(entity_id, effect_id))
+            .await
+    }
+
+    /// Sends an empty recipe list and recipe book, which clients expect while joining.
+    pub async fn send_empty_recipes(&self) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutUpdateRecipes::new(vec![]))
+            .await?;
+        self.send_packet(PacketPlayOutUpdateRecipeBook::new(RecipeBookAction::Init {
+            settings: RecipeBookSettings::new(
+                false, false, false, false, false, false, false, false,
+            ),
+            recipes: vec![],
+            highlighted: vec![],
+        }))
+        .await
+    }
+
+    /// Sends an empty tag set, which clients expect while joining.
+    pub async fn send_empty_tags(&self) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutUpdateTags::new(vec![])).await
+    }
+
+    /// Sends the links shown in the pause menu, returns whether the client supports them.
+    pub async fn send_server_links(&self, links: Vec<ServerLink>) -> anyhow::Result<bool> {
+        if self.protocol_version() < SERVER_LINKS_PROTOCOL_VERSION {
+            return Ok(false);
+        }
+        self.send_packet(PacketPlayOutServerLinks::new(links))
+            .await?;
+        Ok(true)
+    }
+
+    /// Shows an explosion destroying the blocks in the radius and pushing the player.
+    ///
+    /// Returns whether it was sent, newer clients expect an explosion format that is not supported.
+    pub async fn show_explosion(
+        &self,
+        x: f32,
+        y: f32,
+        z: f32,
+        radius: f32,
+        player_motion: PlayerMotion,
+    ) -> anyhow::Result<bool> {
+        if self.protocol_version() >= EXPLOSION_EFFECTS_PROTOCOL_VERSION {
+            return Ok(false);
+        }
+        self.send_packet(PacketPlayOutExplosion::new(
+            x,
+            y,
+            z,
+            radius,
+            PacketPlayOutExplosion::records_in_radius(radius),
+            player_motion,
+        ))
+        .await?;
+        Ok(true)
+    }
+
+    /// Respawns the player in the packet's game mode and moves it back to its current location.
+    pub async fn respawn(&self, respawn: PacketPlayOutRespawn) -> anyhow::Result<()> {
+        self.set_gamemode(*respawn.gamemode());
+        self.send_packet(respawn).await?;
+        let location = self.location();
+        self.send_packet(PacketPlayOutSynchronizePosition::new(
+            location.x() as f64,
+            location.y() as f64,
+            location.z() as f64,
+            location.yaw(),
+            location.pitch(),
+            Flags8(RelativeFlags::empty()),
+            self.teleport_id.fetch_add(1, Ordering::AcqRel),
+            false,
+        ))
+        .await
+    }
+
+    pub async fn send_statistics(&self, statistics: Vec<Statistic>) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutAwardStatistics::new(statistics))
+            .await
+    }
+
+    /// Sends the whole world border, which clients expect while joining.
+    pub async fn send_world_border(&self, border: &WorldBorder) -> anyhow::Result<()> {
+        self.send_packet(border.initialize()).await
+    }
+
+    pub async fn send_brand(&self, brand: &str) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutPluginMessage::brand(brand).await?)
+            .await
+    }
+
+    /// Plays a sound at the location, positions are sent as fixed-point with 3 fraction bits.
+    pub async fn play_sound(
+        &self,
+        sound: SoundRef,
+        category: SoundCategory,
+        location: &Location,
+        volume: f32,
+        pitch: f32,
+    ) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutSoundEffect::new(
+            sound,
+            category,
+            (location.x() * 8.0) as i32,
+            (location.y() * 8.0) as i32,
+            (location.z() * 8.0) as i32,
+            volume,
+            pitch,
+            rand::random(),
+        ))
+        .await
+    }
+
+    /// Stops the playing sounds matching the source and sound name, every sound if neither is given.
+    pub async fn stop_sound(
+        &self,
+        source: Option<SoundCategory>,
+        sound: Option<Identifier>,
+    ) -> anyhow::Result<()> {
+        self.send_packet(PacketPlayOutStopSound::new(SoundFilter { source, sound }))
+            .await
+    }
+
+    /// Called for every plugin message, tracks the channels registered by the client.
+    pub fn on_plugin_message(&self, packet: &PacketPlayInPluginMessage) -> anyhow::Result<()> {
+        let register = Identifier::minecraft("register")?;
+        let unregister = Identifier::minecraft("unregister")?;
+        if packet.channel() != &register && packet.channel() != &unregister {
+            return Ok(());
+        }
+
+        let channels = String::from_utf8_lossy(packet.data())
+            .split('\0')
+            .filter(|channel| !channel.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let mut registered = self.channels.lock().unwrap();
+        if packet.channel() == &register {
+            registered.extend(channels);
+        } else {
+            for channel in channels {
+                registered.remove(&channel);
+            }
+        }
+        Ok(())
+    }
+
+    /// Called when the client moved, keeping its rotation.
+    pub fn on_position(&self, packet: &PacketPlayInSetPlayerPosition) {
+        let mut location = self.location.lock().unwrap();
+        *location = Location::full(
+            *packet.x() as f32,
+            *packet.feet_y() as f32,
+            *packet.z() as f32,
+            location.yaw(),
+            location.pitch(),
+        );
+    }
+
+    /// Called when the client reports that it closed a window.
+    pub fn on_window_closed(&self, window_id: u8) -> anyhow::Result<()> {
+        self.containers.lock().unwrap().close(window_id)
+    }
+}
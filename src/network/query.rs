@@ -0,0 +1,267 @@
+use crate::cfg::SoulflameConfiguration;
+use crate::chat::Component;
+use crate::network::registry::PlayerRegistry;
+use crate::network::status::StatusProvider;
+use crate::network::PlayerCount;
+use crate::LATEST_PROTOCOL_VERSION;
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// First two bytes of every query request.
+pub const QUERY_MAGIC: [u8; 2] = [0xFE, 0xFD];
+/// Request type asking for a challenge token.
+pub const QUERY_HANDSHAKE: u8 = 9;
+/// Request type asking for the basic or full stat.
+pub const QUERY_STAT: u8 = 0;
+
+/// How long a challenge token stays valid, vanilla clears tokens every 30 seconds as well.
+pub const CHALLENGE_TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Padding after the session id of a full stat response.
+const FULL_STAT_PADDING: [u8; 11] = *b"splitnum\0\x80\0";
+/// Padding in front of the player names of a full stat response.
+const FULL_STAT_PLAYERS_PADDING: [u8; 10] = *b"\x01player_\0\0";
+
+/// Challenge tokens handed out to addresses, which they have to send back with stat requests.
+#[derive(Debug)]
+pub struct ChallengeTokens {
+    tokens: HashMap<SocketAddr, (i32, Instant)>,
+    lifetime: Duration,
+}
+
+impl ChallengeTokens {
+    pub fn new(lifetime: Duration) -> Self {
+        Self {
+            tokens: HashMap::new(),
+            lifetime,
+        }
+    }
+
+    /// Hands out a new token to the address, replacing its previous one.
+    pub fn issue(&mut self, addr: SocketAddr) -> i32 {
+        self.purge();
+        let token = rand::random::<i32>() & i32::MAX;
+        self.tokens.insert(addr, (token, Instant::now()));
+        token
+    }
+
+    /// Whether the token was handed out to the address and did not expire yet.
+    pub fn validate(&self, addr: SocketAddr, token: i32) -> bool {
+        match self.tokens.get(&addr) {
+            Some((issued, at)) => *issued == token && at.elapsed() < self.lifetime,
+            None => false,
+        }
+    }
+
+    fn purge(&mut self) {
+        let lifetime = self.lifetime;
+        self.tokens.retain(|_, (_, at)| at.elapsed() < lifetime);
+    }
+}
+
+/// Server details reported in stat responses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryStatus {
+    pub motd: String,
+    pub version: String,
+    pub players: Vec<String>,
+    pub online: u32,
+    pub max_players: u32,
+    pub host_ip: String,
+    pub host_port: u16,
+}
+
+impl QueryStatus {
+    /// Details of the server, the MOTD should be the one currently shown in the server list.
+    pub fn from_cfg(
+        cfg: &SoulflameConfiguration,
+        motd: &Component,
+        players: &PlayerCount,
+        registry: &PlayerRegistry,
+        host_ip: String,
+        host_port: u16,
+    ) -> Self {
+        Self {
+            motd: motd.clone().flatten(),
+            version: cfg.version_name().to_string(),
+            players: registry.names(),
+            online: players.get(),
+            max_players: cfg.max_players,
+            host_ip,
+            host_port,
+        }
+    }
+
+    /// Basic stat response, the MOTD, player counts and address as null-terminated strings.
+    pub fn basic_stat(&self, session_id: i32) -> Vec<u8> {
+        let mut bytes = response_header(session_id);
+        for value in [
+            self.motd.as_str(),
+            "SMP",
+            "world",
+            &self.online.to_string(),
+            &self.max_players.to_string(),
+        ] {
+            push_string(&mut bytes, value);
+        }
+        // unlike everything else, the port is little-endian
+        bytes.extend(self.host_port.to_le_bytes());
+        push_string(&mut bytes, &self.host_ip);
+        bytes
+    }
+
+    /// Full stat response, the server details as key-value pairs followed by the player names.
+    pub fn full_stat(&self, session_id: i32) -> Vec<u8> {
+        let mut bytes = response_header(session_id);
+        bytes.extend(FULL_STAT_PADDING);
+        for (key, value) in [
+            ("hostname", self.motd.as_str()),
+            ("gametype", "SMP"),
+            ("game_id", "MINECRAFT"),
+            ("version", &self.version),
+            ("plugins", ""),
+            ("map", "world"),
+            ("numplayers", &self.online.to_string()),
+            ("maxplayers", &self.max_players.to_string()),
+            ("hostport", &self.host_port.to_string()),
+            ("hostip", &self.host_ip),
+        ] {
+            push_string(&mut bytes, key);
+            push_string(&mut bytes, value);
+        }
+        // an empty key ends the pairs
+        bytes.push(0);
+
+        bytes.extend(FULL_STAT_PLAYERS_PADDING);
+        for player in &self.players {
+            push_string(&mut bytes, player);
+        }
+        bytes.push(0);
+        bytes
+    }
+}
+
+fn response_header(session_id: i32) -> Vec<u8> {
+    let mut bytes = vec![QUERY_STAT];
+    bytes.extend(session_id.to_be_bytes());
+    bytes
+}
+
+fn push_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend(value.as_bytes());
+    bytes.push(0);
+}
+
+/// Answers a query request, `None` if it is invalid or its challenge token is not valid.
+pub fn answer_query(
+    request: &[u8],
+    addr: SocketAddr,
+    tokens: &mut ChallengeTokens,
+    status: &QueryStatus,
+) -> Option<Vec<u8>> {
+    if request.len() < 7 || request[..2] != QUERY_MAGIC {
+        return None;
+    }
+    let kind = request[2];
+    let session_id = i32::from_be_bytes(request[3..7].try_into().ok()?);
+    let payload = &request[7..];
+
+    match kind {
+        QUERY_HANDSHAKE => {
+            let mut bytes = vec![QUERY_HANDSHAKE];
+            bytes.extend(session_id.to_be_bytes());
+            push_string(&mut bytes, &tokens.issue(addr).to_string());
+            Some(bytes)
+        }
+        QUERY_STAT if payload.len() >= 4 => {
+            let token = i32::from_be_bytes(payload[..4].try_into().ok()?);
+            if !tokens.validate(addr, token) {
+                debug!("Query from {} has an invalid challenge token", addr);
+                return None;
+            }
+            // full stat requests are padded with four more bytes
+            if payload.len() >= 8 {
+                Some(status.full_stat(session_id))
+            } else {
+                Some(status.basic_stat(session_id))
+            }
+        }
+        _ => None,
+    }
+}
+
+pub struct QueryListener {
+    inner: UdpSocket,
+    tokens: ChallengeTokens,
+    config: SoulflameConfiguration,
+    players: PlayerCount,
+    registry: PlayerRegistry,
+    status: Arc<dyn StatusProvider>,
+    addr: String,
+    port: u16,
+}
+
+impl QueryListener {
+    /// Answers queries on the UDP port, which is reported as the game port as well.
+    ///
+    /// Players and the MOTD should be shared with the [`NetworkListener`](crate::network::NetworkListener).
+    pub async fn init(
+        addr: String,
+        port: u16,
+        configuration: SoulflameConfiguration,
+        players: PlayerCount,
+        registry: PlayerRegistry,
+        status: Arc<dyn StatusProvider>,
+    ) -> anyhow::Result<()> {
+        let socket = UdpSocket::bind(format!("{}:{}", addr, port))
+            .await
+            .context("Failed to bind query to port! Is it already in use?")?;
+        info!("Started query listener successfully!");
+
+        let this = QueryListener {
+            inner: socket,
+            tokens: ChallengeTokens::new(CHALLENGE_TOKEN_LIFETIME),
+            config: configuration,
+            players,
+            registry,
+            status,
+            addr,
+            port,
+        };
+        this.network_loop().await;
+
+        Ok(())
+    }
+
+    async fn network_loop(mut self) {
+        let mut buffer = [0u8; 1460];
+        loop {
+            let (read, addr) = match self.inner.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Could not receive query: {}", e);
+                    continue;
+                }
+            };
+            let response = self.status.status(LATEST_PROTOCOL_VERSION).await;
+            let status = QueryStatus::from_cfg(
+                &self.config,
+                response.description(),
+                &self.players,
+                &self.registry,
+                self.addr.clone(),
+                self.port,
+            );
+            if let Some(response) = answer_query(&buffer[..read], addr, &mut self.tokens, &status) {
+                if let Err(e) = self.inner.send_to(&response, addr).await {
+                    warn!("Could not answer query from {}: {}", addr, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::network::MaintenanceMode;
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Port RCON listens on by default, the same as vanilla.
+pub const DEFAULT_RCON_PORT: u16 = 25575;
+
+/// Packet type of a login request, carrying the password.
+pub const RCON_LOGIN: i32 = 3;
+/// Packet type of a command request.
+pub const RCON_COMMAND: i32 = 2;
+/// Packet type of the answer to a login request.
+pub const RCON_AUTH_RESPONSE: i32 = 2;
+/// Packet type of the output of a command.
+pub const RCON_RESPONSE: i32 = 0;
+
+/// Request id answered to failed logins.
+pub const RCON_AUTH_FAILURE: i32 = -1;
+
+/// Smallest valid packet length, the request id, type and the two terminating null bytes.
+pub const MIN_RCON_PACKET_LENGTH: i32 = 10;
+/// Largest packet length accepted from clients, the same limit vanilla uses.
+pub const MAX_RCON_PACKET_LENGTH: i32 = 1460;
+/// Longest body of a single response packet, longer outputs are split into several packets.
+pub const MAX_RCON_RESPONSE_BODY: usize = 4096;
+
+/// Runs the commands sent by authenticated RCON clients.
+#[async_trait]
+pub trait RconHandler: Send + Sync {
+    /// Executes the command, returning its output.
+    async fn execute(&self, command: &str) -> String;
+}
+
+/// Handler of the `maintenance [on|off]` command, e.g. for the switch of a listener.
+pub struct MaintenanceCommand {
+    maintenance: MaintenanceMode,
+}
+
+impl MaintenanceCommand {
+    pub fn new(maintenance: MaintenanceMode) -> Self {
+        Self { maintenance }
+    }
+}
+
+#[async_trait]
+impl RconHandler for MaintenanceCommand {
+    async fn execute(&self, command: &str) -> String {
+        let mut args = command.split_whitespace();
+        if args.next() != Some("maintenance") {
+            return format!("Unknown command: {}", command);
+        }
+        match args.next() {
+            Some("on") => self.maintenance.set_enabled(true),
+            Some("off") => self.maintenance.set_enabled(false),
+            None => {}
+            Some(_) => return "Usage: maintenance [on|off]".to_string(),
+        }
+        format!(
+            "Maintenance mode is {}",
+            if self.maintenance.is_enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )
+    }
+}
+
+/// Single RCON packet, framed by its little-endian length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RconPacket {
+    pub request_id: i32,
+    pub kind: i32,
+    pub body: String,
+}
+
+impl RconPacket {
+    pub fn new<S: Into<String>>(request_id: i32, kind: i32, body: S) -> Self {
+        Self {
+            request_id,
+            kind,
+            body: body.into(),
+        }
+    }
+
+    /// Reads the next packet, or `None` if the client closed the connection between packets.
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<Option<Self>> {
+        let len = match reader.read_i32_le().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if !(MIN_RCON_PACKET_LENGTH..=MAX_RCON_PACKET_LENGTH).contains(&len) {
+            warn!("Invalid RCON packet length: {}", len);
+            bail!("Invalid RCON packet length: {}", len)
+        }
+
+        let mut data = vec![0u8; len as usize];
+        reader.read_exact(&mut data).await?;
+        let request_id = i32::from_le_bytes(data[0..4].try_into()?);
+        let kind = i32::from_le_bytes(data[4..8].try_into()?);
+        let body = &data[8..];
+        let body = match body.iter().position(|byte| *byte == 0) {
+            Some(end) => &body[..end],
+            None => {
+                warn!("RCON packet body is not null-terminated");
+                bail!("RCON packet body is not null-terminated")
+            }
+        };
+        Ok(Some(Self {
+            request_id,
+            kind,
+            body: String::from_utf8(body.to_vec())?,
+        }))
+    }
+
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> anyhow::Result<()> {
+        let body = self.body.as_bytes();
+        let mut data = Vec::with_capacity(body.len() + 14);
+        data.extend(((body.len() + 10) as i32).to_le_bytes());
+        data.extend(self.request_id.to_le_bytes());
+        data.extend(self.kind.to_le_bytes());
+        data.extend(body);
+        data.extend([0, 0]);
+        writer.write_all(&data).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct RconListener {
+    inner: TcpListener,
+    password: Arc<String>,
+    handler: Arc<dyn RconHandler>,
+}
+
+impl RconListener {
+    pub async fn init(
+        addr: String,
+        port: u16,
+        password: String,
+        handler: Arc<dyn RconHandler>,
+    ) -> anyhow::Result<()> {
+        // vanilla refuses to start RCON without a password as well
+        if password.is_empty() {
+            warn!("RCON requires a password, not starting it");
+            bail!("RCON requires a password, not starting it")
+        }
+
+        let listener = TcpListener::bind(format!("{}:{}", addr, port))
+            .await
+            .context("Failed to bind RCON to port! Is it already in use?")?;
+        info!("Started RCON listener successfully!");
+
+        let this = RconListener {
+            inner: listener,
+            password: Arc::new(password),
+            handler,
+        };
+        this.network_loop().await;
+
+        Ok(())
+    }
+
+    async fn network_loop(self) {
+        loop {
+            if let Ok((stream, addr)) = self.inner.accept().await {
+                let password = self.password.clone();
+                let handler = self.handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_rcon(stream, addr, &password, handler.as_ref()).await {
+                        info!("RCON connection from {} closed: {}", addr.ip(), e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Serves a single RCON connection until the client closes it.
+///
+/// Commands are only executed once the client logged in with the password.
+pub async fn handle_rcon<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    addr: SocketAddr,
+    password: &str,
+    handler: &dyn RconHandler,
+) -> anyhow::Result<()> {
+    let mut authenticated = false;
+    while let Some(packet) = RconPacket::read(&mut stream).await? {
+        match packet.kind {
+            RCON_LOGIN => {
+                authenticated = packet.body == password;
+                let request_id = if authenticated {
+                    info!("RCON client {} logged in", addr.ip());
+                    packet.request_id
+                } else {
+                    warn!("RCON client {} sent a wrong password", addr.ip());
+                    RCON_AUTH_FAILURE
+                };
+                RconPacket::new(request_id, RCON_AUTH_RESPONSE, "")
+                    .write(&mut stream)
+                    .await?;
+            }
+            RCON_COMMAND if authenticated => {
+                info!("RCON client {} ran '{}'", addr.ip(), packet.body);
+                let output = handler.execute(&packet.body).await;
+                for chunk in split_response(&output) {
+                    RconPacket::new(packet.request_id, RCON_RESPONSE, chunk)
+                        .write(&mut stream)
+                        .await?;
+                }
+            }
+            RCON_COMMAND => {
+                RconPacket::new(RCON_AUTH_FAILURE, RCON_AUTH_RESPONSE, "")
+                    .write(&mut stream)
+                    .await?;
+            }
+            other => {
+                warn!("Unknown RCON packet type {} from {}", other, addr.ip());
+                bail!("Unknown RCON packet type {} from {}", other, addr.ip())
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits command output into response bodies, without cutting characters apart.
+fn split_response(output: &str) -> Vec<&str> {
+    if output.is_empty() {
+        return vec![""];
+    }
+
+    let mut chunks = vec![];
+    let mut rest = output;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(MAX_RCON_RESPONSE_BODY);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
@@ -0,0 +1,200 @@
+use crate::cfg::DuplicateLogin;
+use crate::chat::Component;
+use crate::net_io::packet::Packet;
+use crate::network::player::PlayerHandle;
+use crate::protocol::client::play::Hand;
+use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutEntityAnimation};
+use crate::protocol::server::status::PlayerSample;
+use crate::world::block::Location;
+use crate::world::entity::EntityIdAllocator;
+use anyhow::bail;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Distance in blocks within which players see each other's animations.
+pub const ANIMATION_BROADCAST_RADIUS: f32 = 48.0;
+
+/// Players that are currently online, keyed by their account UUID.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerRegistry {
+    inner: Arc<Mutex<HashMap<Uuid, PlayerHandle>>>,
+    entity_ids: EntityIdAllocator,
+}
+
+impl PlayerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<PlayerHandle> {
+        self.inner.lock().unwrap().get(uuid).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Names and UUIDs of at most `limit` online players, for the server list.
+    pub fn sample(&self, limit: usize) -> Vec<PlayerSample> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .take(limit)
+            .map(|(uuid, player)| PlayerSample::new(player.name(), *uuid))
+            .collect()
+    }
+
+    /// Names of every online player, sorted alphabetically.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .values()
+            .map(|player| player.name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Registers the player, resolving an already online session according to the policy.
+    ///
+    /// The player is given a fresh entity id. Returns the session that was replaced, which should be kicked by the caller.
+    pub fn register(
+        &self,
+        uuid: Uuid,
+        player: PlayerHandle,
+        policy: DuplicateLogin,
+    ) -> anyhow::Result<Option<PlayerHandle>> {
+        let mut players = self.inner.lock().unwrap();
+        if players.contains_key(&uuid) && policy == DuplicateLogin::Deny {
+            warn!("Player {} tried to log in while already online", uuid);
+            bail!("Player {} tried to log in while already online", uuid)
+        }
+        player.set_entity_id(self.entity_ids.allocate());
+        Ok(players.insert(uuid, player))
+    }
+
+    /// Removes the player, unless the session was already replaced by a newer one.
+    pub fn unregister(&self, uuid: &Uuid, player: &PlayerHandle) {
+        let mut players = self.inner.lock().unwrap();
+        if matches!(players.get(uuid), Some(current) if current.same_session(player)) {
+            players.remove(uuid);
+        }
+    }
+
+    /// Registers a logging in player, returns the disconnect reason if the login is refused.
+    pub async fn admit(
+        &self,
+        uuid: Uuid,
+        player: PlayerHandle,
+        policy: DuplicateLogin,
+    ) -> Option<Component> {
+        match self.register(uuid, player, policy) {
+            Ok(Some(previous)) => {
+                let reason = Component::text("You logged in from another location".to_string());
+                if let Err(e) = previous.kick(reason).await {
+                    warn!("Could not kick previous session of {}: {}", uuid, e);
+                }
+                None
+            }
+            Ok(None) => None,
+            Err(_) => Some(Component::text(
+                "You are already logged in to this server".to_string(),
+            )),
+        }
+    }
+
+    /// Sends the packet to every online player.
+    pub async fn broadcast<P: Packet<PacketPlayOut> + Clone>(&self, packet: P) {
+        let players: Vec<PlayerHandle> = self.inner.lock().unwrap().values().cloned().collect();
+        for player in players {
+            if let Err(e) = player.send_packet(packet.clone()).await {
+                warn!("Could not broadcast to {}: {}", player.addr().ip(), e);
+            }
+        }
+    }
+
+    /// Disconnects the player, returns whether they were online.
+    ///
+    /// The player is removed once their connection closed.
+    pub async fn kick(&self, uuid: &Uuid, reason: Component) -> anyhow::Result<bool> {
+        match self.get(uuid) {
+            Some(player) => {
+                player.kick(reason).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Sends the packet to every player within the radius of the location, except `except`.
+    pub async fn broadcast_within<P: Packet<PacketPlayOut> + Clone>(
+        &self,
+        origin: &Location,
+        radius: f32,
+        packet: P,
+        except: Option<&PlayerHandle>,
+    ) -> anyhow::Result<()> {
+        let nearby: Vec<PlayerHandle> = self
+            .inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|player| !matches!(except, Some(except) if except.same_session(player)))
+            .filter(|player| player.location().distance_squared(origin) <= radius * radius)
+            .cloned()
+            .collect();
+
+        for player in nearby {
+            if let Err(e) = player.send_packet(packet.clone()).await {
+                warn!("Could not broadcast to {}: {}", player.addr().ip(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the player visible to every other online player.
+    pub async fn spawn_for_others(&self, uuid: &Uuid) -> anyhow::Result<()> {
+        let player = match self.get(uuid) {
+            Some(player) => player,
+            None => bail!("Player {} is not online", uuid),
+        };
+        let others: Vec<PlayerHandle> = self
+            .inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|other| !other.same_session(&player))
+            .cloned()
+            .collect();
+
+        for other in others {
+            if let Err(e) = other
+                .spawn_player(player.entity_id(), *uuid, player.location())
+                .await
+            {
+                warn!("Could not spawn {} for {}: {}", uuid, other.addr().ip(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows the player's arm swing to the players around them.
+    pub async fn broadcast_swing(&self, player: &PlayerHandle, hand: Hand) -> anyhow::Result<()> {
+        self.broadcast_within(
+            &player.location(),
+            ANIMATION_BROADCAST_RADIUS,
+            PacketPlayOutEntityAnimation::swing(player.entity_id(), hand),
+            Some(player),
+        )
+        .await
+    }
+}
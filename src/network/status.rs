@@ -0,0 +1,126 @@
+use crate::cfg::{favicon_from_cfg, RuntimeConfiguration, SoulflameConfiguration};
+use crate::network::registry::PlayerRegistry;
+use crate::network::{MaintenanceMode, PlayerCount};
+use crate::protocol::server::status::{ServerPlayers, ServerVersion, StatusResponse};
+use crate::{LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOLS};
+use async_trait::async_trait;
+use lobstermessage::lobster;
+use log::{info, warn};
+use std::sync::RwLock;
+
+/// Builds the response shown to clients in the server list.
+#[async_trait]
+pub trait StatusProvider: Send + Sync {
+    async fn status(&self, client_version: u32) -> StatusResponse;
+}
+
+/// Version shown to a client in the server list.
+///
+/// Supported protocols are echoed back, so the client shows the server as compatible. Any other
+/// client gets our own protocol with a name telling which version to use, shown as outdated.
+pub fn status_version(client_version: u32, version_name: &str) -> ServerVersion {
+    if SUPPORTED_PROTOCOLS.contains(&client_version) {
+        ServerVersion::new(version_name.to_string(), client_version as i32)
+    } else {
+        ServerVersion::new(
+            format!("Requires {}", version_name),
+            LATEST_PROTOCOL_VERSION as i32,
+        )
+    }
+}
+
+/// MOTD and favicon shown in the server list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branding {
+    pub motd: String,
+    pub favicon: Option<String>,
+}
+
+/// Status built from the server configuration.
+///
+/// The branding can be swapped at runtime, status requests answered afterwards show the new one.
+/// During maintenance the maintenance MOTD is shown instead.
+pub struct DefaultStatusProvider {
+    config: SoulflameConfiguration,
+    branding: RwLock<Branding>,
+    maintenance: MaintenanceMode,
+    players: PlayerCount,
+    registry: PlayerRegistry,
+}
+
+impl DefaultStatusProvider {
+    pub fn new(config: SoulflameConfiguration, runtime: RuntimeConfiguration) -> Self {
+        Self {
+            branding: RwLock::new(Branding {
+                motd: config.motd.clone(),
+                favicon: runtime.favicon,
+            }),
+            maintenance: MaintenanceMode::new(config.maintenance),
+            players: PlayerCount::new(config.max_players),
+            registry: PlayerRegistry::new(),
+            config,
+        }
+    }
+
+    /// Shares the online players counted and sampled in responses.
+    pub fn set_players(&mut self, players: PlayerCount, registry: PlayerRegistry) {
+        self.players = players;
+        self.registry = registry;
+    }
+
+    /// Shares the maintenance switch, e.g. with the listener toggling it.
+    pub fn set_maintenance(&mut self, maintenance: MaintenanceMode) {
+        self.maintenance = maintenance;
+    }
+
+    pub fn branding(&self) -> Branding {
+        self.branding.read().unwrap().clone()
+    }
+
+    pub fn set_branding(&self, branding: Branding) {
+        *self.branding.write().unwrap() = branding;
+    }
+
+    /// Reloads the MOTD and favicon from the configuration.
+    ///
+    /// The current favicon is kept if the new one can not be loaded, the MOTD is updated either way.
+    pub async fn reload(&self, config: &SoulflameConfiguration) {
+        let favicon = match favicon_from_cfg(config).await {
+            Ok(favicon) => Some(favicon),
+            Err(e) => {
+                warn!(
+                    "Could not reload the favicon, keeping the current one: {}",
+                    e
+                );
+                self.branding().favicon
+            }
+        };
+        self.set_branding(Branding {
+            motd: config.motd.clone(),
+            favicon,
+        });
+        info!("Reloaded MOTD and favicon");
+    }
+}
+
+#[async_trait]
+impl StatusProvider for DefaultStatusProvider {
+    async fn status(&self, client_version: u32) -> StatusResponse {
+        let branding = self.branding();
+        let motd = if self.maintenance.is_enabled() {
+            &self.config.maintenance_motd
+        } else {
+            &branding.motd
+        };
+        StatusResponse::new(
+            status_version(client_version, self.config.version_name()),
+            ServerPlayers::new(
+                self.config.max_players as i32,
+                self.players.get() as i32,
+                self.registry.sample(self.config.sample_size),
+            ),
+            lobster(motd),
+            branding.favicon,
+        )
+    }
+}
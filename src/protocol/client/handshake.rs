@@ -1,10 +1,27 @@
 use crate::net_io::VarInt;
+use crate::protocol::server::login::ProfileProperty;
 use crate::{define_enum, staged_packets};
+use anyhow::bail;
+use log::warn;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Highest protocol version a client may sensibly report.
+///
+/// Snapshot versions are encoded as `0x40000000 | n`, so anything above this is garbage.
+pub const MAX_SANE_PROTOCOL_VERSION: u32 = 0x4000_FFFF;
+
+/// Longest server address vanilla clients send in the handshake.
+pub const VANILLA_MAX_HOSTNAME_LENGTH: usize = 255;
+
+/// Default address length limit with proxy forwarding, which appends player data to the address.
+pub const FORWARDING_MAX_HOSTNAME_LENGTH: usize = 4096;
 
 define_enum! {
     HandshakeState {
         Status = 1,
-        Login = 2
+        Login = 2,
+        Transfer = 3
     }
 }
 
@@ -18,3 +35,88 @@ staged_packets! {
         }
     }
 }
+
+/// Player data forwarded by a BungeeCord proxy in the handshake address.
+#[derive(Debug, Clone)]
+pub struct BungeeForward {
+    pub host: String,
+    pub address: IpAddr,
+    pub uuid: Uuid,
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl PacketHandshakeIn {
+    /// Ensures the reported protocol version is non-negative and, if `max` is set, not above it.
+    pub fn validate_protocol(&self, max: Option<u32>) -> anyhow::Result<()> {
+        let version = self.protocol_version;
+        if version < 0 {
+            warn!(
+                "Client sent negative protocol version in handshake: {}",
+                version
+            );
+            bail!(
+                "Client sent negative protocol version in handshake: {}",
+                version
+            )
+        }
+
+        if let Some(max) = max {
+            if version as u32 > max {
+                warn!(
+                    "Client sent protocol version out of range (max: {}, received: {})",
+                    max, version
+                );
+                bail!(
+                    "Client sent protocol version out of range (max: {}, received: {})",
+                    max,
+                    version
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensures the server address is at most `max` characters long.
+    pub fn validate_address(&self, max: usize) -> anyhow::Result<()> {
+        let length = self.server_address.chars().count();
+        if length > max {
+            warn!(
+                "Client sent server address that is too long (max: {}, received: {})",
+                max, length
+            );
+            bail!(
+                "Client sent server address that is too long (max: {}, received: {})",
+                max,
+                length
+            )
+        }
+        Ok(())
+    }
+
+    /// Parses the `host\0ip\0uuid[\0properties]` address sent by BungeeCord IP forwarding.
+    pub fn bungee_forward(&self) -> anyhow::Result<BungeeForward> {
+        let fields: Vec<&str> = self.server_address.split('\0').collect();
+        if fields.len() != 3 && fields.len() != 4 {
+            warn!(
+                "Client sent malformed BungeeCord forward with {} fields",
+                fields.len()
+            );
+            bail!(
+                "Client sent malformed BungeeCord forward with {} fields",
+                fields.len()
+            )
+        }
+
+        let properties = match fields.get(3) {
+            Some(properties) => serde_json::from_str(properties)?,
+            None => vec![],
+        };
+        Ok(BungeeForward {
+            host: fields[0].to_string(),
+            address: fields[1].parse()?,
+            uuid: Uuid::parse_str(fields[2])?,
+            properties,
+        })
+    }
+}
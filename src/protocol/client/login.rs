@@ -1,21 +1,69 @@
-use crate::net_io::{ByteArray, VarInt};
-use crate::staged_packets;
-pub type ByteVec = Vec<u8>;
+use crate::net_io::{ByteArray, PacketRead, PacketWrite, VarInt};
+use crate::{packet_struct, staged_packets};
+use anyhow::bail;
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+packet_struct! {
+    PlayerPublicKey {
+        expires_at: i64,
+        public_key: Vec<u8>,
+        key_signature: Vec<u8>
+    }
+}
+
+/// Proof that the client decrypted the verify token, clients with a chat signing key sign it instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyToken {
+    Encrypted(Vec<u8>),
+    Signed { salt: i64, signature: Vec<u8> },
+}
+
+#[async_trait]
+impl PacketWrite for VerifyToken {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match self {
+            VerifyToken::Encrypted(token) => {
+                true.pack_write(buffer, target_version).await?;
+                token.pack_write(buffer, target_version).await
+            }
+            VerifyToken::Signed { salt, signature } => {
+                false.pack_write(buffer, target_version).await?;
+                salt.pack_write(buffer, target_version).await?;
+                signature.pack_write(buffer, target_version).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PacketRead for VerifyToken {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        if bool::pack_read(buffer, target_version).await? {
+            Ok(VerifyToken::Encrypted(
+                Vec::pack_read(buffer, target_version).await?,
+            ))
+        } else {
+            Ok(VerifyToken::Signed {
+                salt: i64::pack_read(buffer, target_version).await?,
+                signature: Vec::pack_read(buffer, target_version).await?,
+            })
+        }
+    }
+}
 
 staged_packets! {
     InLogin("login", Inbound) {
         PacketLoginInStart(0x00) {
             name: String,
-            key_expiration: Option<i64>,
-            public_key: Option<ByteVec>,
-            signature: Option<ByteVec>
+            public_key: Option<PlayerPublicKey>
         };
 
         PacketLoginInEncryptionResponse(0x01) {
             shared_secret: Vec<u8>,
-            verify_token: Option<ByteVec>,
-            salt: Option<i64>,
-            message_signature: Option<ByteVec>
+            verification: VerifyToken
         };
 
         PacketLoginInPluginResponse(0x02) {
@@ -24,3 +72,24 @@ staged_packets! {
         };
     }
 }
+
+impl PacketLoginInStart {
+    /// Ensures the username is 3 to 16 characters long and only uses letters, digits and `_`.
+    pub fn validate_name(&self) -> anyhow::Result<()> {
+        let valid_chars = self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !(3..=16).contains(&self.name.len()) || !valid_chars {
+            warn!(
+                "Client tried to log in with invalid username {:?}",
+                self.name
+            );
+            bail!(
+                "Client tried to log in with invalid username {:?}",
+                self.name
+            )
+        }
+        Ok(())
+    }
+}
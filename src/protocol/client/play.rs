@@ -1,9 +1,206 @@
-use crate::staged_packets;
+use crate::net_io::{ByteArray, PacketRead, PacketWrite, Position, VarInt};
+use crate::util::Identifier;
+use crate::{define_enum, staged_packets};
+use anyhow::bail;
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+define_enum! {
+    Hand {
+        MainHand = 0,
+        OffHand = 1
+    }
+}
+
+define_enum! {
+    ClientCommandAction {
+        PerformRespawn = 0,
+        RequestStats = 1
+    }
+}
+
+/// First protocol version (1.20) with text on both sides of a sign.
+pub const SIGN_SIDES_PROTOCOL_VERSION: u32 = 763;
+
+/// Most characters a single sign line may have in an update, the same limit vanilla uses.
+pub const MAX_SIGN_LINE_LENGTH: usize = 384;
+
+/// Side of a sign being edited, clients before 1.20 can only edit the front.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignSide {
+    Front,
+    Back,
+}
+
+#[async_trait]
+impl PacketWrite for SignSide {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        if target_version < SIGN_SIDES_PROTOCOL_VERSION {
+            return Ok(());
+        }
+        (*self == SignSide::Front)
+            .pack_write(buffer, target_version)
+            .await
+    }
+}
+
+#[async_trait]
+impl PacketRead for SignSide {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        if target_version < SIGN_SIDES_PROTOCOL_VERSION
+            || bool::pack_read(buffer, target_version).await?
+        {
+            Ok(SignSide::Front)
+        } else {
+            Ok(SignSide::Back)
+        }
+    }
+}
+
+/// Kind of entity interaction, `InteractAt` additionally carries the targeted position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InteractType {
+    Interact {
+        hand: Hand,
+    },
+    Attack,
+    InteractAt {
+        target_x: f32,
+        target_y: f32,
+        target_z: f32,
+        hand: Hand,
+    },
+}
+
+#[async_trait]
+impl PacketWrite for InteractType {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match self {
+            InteractType::Interact { hand } => {
+                VarInt(0).pack_write(buffer, target_version).await?;
+                hand.pack_write(buffer, target_version).await
+            }
+            InteractType::Attack => VarInt(1).pack_write(buffer, target_version).await,
+            InteractType::InteractAt {
+                target_x,
+                target_y,
+                target_z,
+                hand,
+            } => {
+                VarInt(2).pack_write(buffer, target_version).await?;
+                target_x.pack_write(buffer, target_version).await?;
+                target_y.pack_write(buffer, target_version).await?;
+                target_z.pack_write(buffer, target_version).await?;
+                hand.pack_write(buffer, target_version).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PacketRead for InteractType {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        Ok(match VarInt::pack_read(buffer, target_version).await?.0 {
+            0 => InteractType::Interact {
+                hand: Hand::pack_read(buffer, target_version).await?,
+            },
+            1 => InteractType::Attack,
+            2 => InteractType::InteractAt {
+                target_x: f32::pack_read(buffer, target_version).await?,
+                target_y: f32::pack_read(buffer, target_version).await?,
+                target_z: f32::pack_read(buffer, target_version).await?,
+                hand: Hand::pack_read(buffer, target_version).await?,
+            },
+            other => {
+                warn!("Invalid InteractType ID provided: {}!", other);
+                bail!("Invalid InteractType ID provided: {}!", other)
+            }
+        })
+    }
+}
 
 staged_packets! {
     PacketPlayIn("play", Inbound) {
         Null(0x00) {
 
+        };
+
+        PacketPlayInChatMessage(0x04) {
+            message: String,
+            timestamp: i64,
+            salt: i64,
+            signature: Vec<u8>,
+            signed_preview: bool
+        };
+
+        PacketPlayInClientCommand(0x06) {
+            action: ClientCommandAction
+        };
+
+        PacketPlayInCloseContainer(0x0B) {
+            window_id: u8
+        };
+
+        PacketPlayInPluginMessage(0x0C) {
+            channel: Identifier,
+            data: ByteArray
+        };
+
+        PacketPlayInInteract(0x0F) {
+            entity_id: VarInt,
+            action: InteractType,
+            sneaking: bool
+        };
+
+        PacketPlayInKeepAlive(0x11) {
+            keep_alive_id: i64
+        };
+
+        PacketPlayInSetPlayerPosition(0x13) {
+            x: f64,
+            feet_y: f64,
+            z: f64,
+            on_ground: bool
+        };
+
+        PacketPlayInUpdateSign(0x2D) {
+            location: Position,
+            side: SignSide,
+            line1: String,
+            line2: String,
+            line3: String,
+            line4: String
+        };
+
+        PacketPlayInSwingArm(0x2E) {
+            hand: Hand
+        }
+    }
+}
+
+impl PacketPlayInUpdateSign {
+    pub fn lines(&self) -> [&String; 4] {
+        [&self.line1, &self.line2, &self.line3, &self.line4]
+    }
+
+    /// Checks that no line is longer than [`MAX_SIGN_LINE_LENGTH`] characters.
+    pub fn validate_lines(&self) -> anyhow::Result<()> {
+        for line in self.lines() {
+            let length = line.chars().count();
+            if length > MAX_SIGN_LINE_LENGTH {
+                warn!(
+                    "Client sent sign line with {} characters, at most {} are allowed",
+                    length, MAX_SIGN_LINE_LENGTH
+                );
+                bail!(
+                    "Client sent sign line with {} characters, at most {} are allowed",
+                    length,
+                    MAX_SIGN_LINE_LENGTH
+                )
+            }
         }
+        Ok(())
     }
 }
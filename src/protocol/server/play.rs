@@ -1,10 +1,1156 @@
 use crate::chat::Component;
-use crate::staged_packets;
+use crate::net_io::{
+    Angle, BitFlags8, ByteArray, Flags8, IdOr, PacketRead, PacketWrite, Position, VarInt, VarLong,
+};
+use crate::protocol::client::play::{Hand, SignSide};
+use crate::util::Identifier;
+use crate::world::item::Slot;
+use crate::{action_packet, define_enum, define_string_enum, packet_struct, staged_packets};
+use anyhow::bail;
+use async_trait::async_trait;
+use log::warn;
+use nbt::Blob;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
+use std::str::FromStr;
+use uuid::Uuid;
+
+define_enum! {
+    WindowType {
+        Generic9x1 = 0,
+        Generic9x2 = 1,
+        Generic9x3 = 2,
+        Generic9x4 = 3,
+        Generic9x5 = 4,
+        Generic9x6 = 5,
+        Generic3x3 = 6,
+        Anvil = 7,
+        Beacon = 8,
+        BlastFurnace = 9,
+        BrewingStand = 10,
+        Crafting = 11,
+        Enchantment = 12,
+        Furnace = 13,
+        Grindstone = 14,
+        Hopper = 15,
+        Lectern = 16,
+        Loom = 17,
+        Merchant = 18,
+        ShulkerBox = 19,
+        Smithing = 20,
+        Smoker = 21,
+        CartographyTable = 22,
+        Stonecutter = 23
+    }
+}
+
+impl WindowType {
+    /// Amount of slots in the window, including the player inventory where it is shown.
+    pub fn slot_count(&self) -> usize {
+        use WindowType::*;
+        let container = match self {
+            Generic9x1 | Generic3x3 => 9,
+            Generic9x2 => 18,
+            Generic9x3 | ShulkerBox => 27,
+            Generic9x4 => 36,
+            Generic9x5 => 45,
+            Generic9x6 => 54,
+            Anvil | BlastFurnace | Furnace | Grindstone | Merchant | Smithing | Smoker
+            | CartographyTable => 3,
+            Beacon => 1,
+            BrewingStand | Hopper => 5,
+            Crafting => 10,
+            Enchantment | Stonecutter => 2,
+            Loom => 4,
+            // lectern does not show the player inventory
+            Lectern => return 1,
+        };
+        container + 36
+    }
+}
+
+define_enum! {
+    GameMode: u8 {
+        Survival = 0,
+        Creative = 1,
+        Adventure = 2,
+        Spectator = 3
+    }
+}
+
+impl GameMode {
+    /// Abilities a player in this game mode starts with.
+    pub fn abilities(&self) -> AbilityFlags {
+        match self {
+            GameMode::Survival | GameMode::Adventure => AbilityFlags::empty(),
+            GameMode::Creative => {
+                AbilityFlags::INVULNERABLE
+                    | AbilityFlags::ALLOW_FLYING
+                    | AbilityFlags::INSTANT_BREAK
+            }
+            GameMode::Spectator => {
+                AbilityFlags::INVULNERABLE | AbilityFlags::FLYING | AbilityFlags::ALLOW_FLYING
+            }
+        }
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "survival" => GameMode::Survival,
+            "creative" => GameMode::Creative,
+            "adventure" => GameMode::Adventure,
+            "spectator" => GameMode::Spectator,
+            _ => {
+                warn!("Invalid game mode provided: {}!", s);
+                bail!("Invalid game mode provided: {}!", s)
+            }
+        })
+    }
+}
+
+impl Display for GameMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator",
+        })
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Serialize, Deserialize)]
+    pub struct AbilityFlags: u8 {
+        const INVULNERABLE = 0x01;
+        const FLYING = 0x02;
+        const ALLOW_FLYING = 0x04;
+        const INSTANT_BREAK = 0x08;
+    }
+}
+
+impl BitFlags8 for AbilityFlags {
+    fn bits(&self) -> u8 {
+        AbilityFlags::bits(self)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        AbilityFlags::from_bits(bits)
+    }
+
+    fn from_bits_truncate(bits: u8) -> Self {
+        AbilityFlags::from_bits_truncate(bits)
+    }
+}
+
+define_enum! {
+    SoundCategory {
+        Master = 0,
+        Music = 1,
+        Record = 2,
+        Weather = 3,
+        Block = 4,
+        Hostile = 5,
+        Neutral = 6,
+        Player = 7,
+        Ambient = 8,
+        Voice = 9
+    }
+}
+
+packet_struct! {
+    SoundEvent {
+        name: Identifier,
+        fixed_range: Option<f32>
+    }
+}
+
+/// Reference to a sound, either by its registry id or an inline sound event.
+pub type SoundRef = IdOr<SoundEvent>;
+
+/// Sounds stopped by a stop sound packet, every playing sound if neither is set.
+///
+/// Prefixed with flags marking the source (`0x01`) and the sound name (`0x02`) as present.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoundFilter {
+    pub source: Option<SoundCategory>,
+    pub sound: Option<Identifier>,
+}
+
+#[async_trait]
+impl PacketWrite for SoundFilter {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        let flags = self.source.is_some() as u8 | (self.sound.is_some() as u8) << 1;
+        flags.pack_write(buffer, target_version).await?;
+        if let Some(source) = &self.source {
+            source.pack_write(buffer, target_version).await?;
+        }
+        if let Some(sound) = &self.sound {
+            sound.pack_write(buffer, target_version).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PacketRead for SoundFilter {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        let flags = u8::pack_read(buffer, target_version).await?;
+        let source = if flags & 0x01 != 0 {
+            Some(SoundCategory::pack_read(buffer, target_version).await?)
+        } else {
+            None
+        };
+        let sound = if flags & 0x02 != 0 {
+            Some(Identifier::pack_read(buffer, target_version).await?)
+        } else {
+            None
+        };
+        Ok(SoundFilter { source, sound })
+    }
+}
+
+bitflags::bitflags! {
+    /// Marks which position and rotation values of a teleport are relative.
+    #[derive(Serialize, Deserialize)]
+    pub struct RelativeFlags: u8 {
+        const X = 0x01;
+        const Y = 0x02;
+        const Z = 0x04;
+        const Y_ROT = 0x08;
+        const X_ROT = 0x10;
+    }
+}
+
+impl BitFlags8 for RelativeFlags {
+    fn bits(&self) -> u8 {
+        RelativeFlags::bits(self)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        RelativeFlags::from_bits(bits)
+    }
+
+    fn from_bits_truncate(bits: u8) -> Self {
+        RelativeFlags::from_bits_truncate(bits)
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Serialize, Deserialize)]
+    pub struct TeamFlags: u8 {
+        const ALLOW_FRIENDLY_FIRE = 0x01;
+        const SEE_INVISIBLE_TEAMMATES = 0x02;
+    }
+}
+
+impl BitFlags8 for TeamFlags {
+    fn bits(&self) -> u8 {
+        TeamFlags::bits(self)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        TeamFlags::from_bits(bits)
+    }
+
+    fn from_bits_truncate(bits: u8) -> Self {
+        TeamFlags::from_bits_truncate(bits)
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Serialize, Deserialize)]
+    pub struct EffectFlags: u8 {
+        const AMBIENT = 0x01;
+        const SHOW_PARTICLES = 0x02;
+        const SHOW_ICON = 0x04;
+    }
+}
+
+impl BitFlags8 for EffectFlags {
+    fn bits(&self) -> u8 {
+        EffectFlags::bits(self)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        EffectFlags::from_bits(bits)
+    }
+
+    fn from_bits_truncate(bits: u8) -> Self {
+        EffectFlags::from_bits_truncate(bits)
+    }
+}
+
+define_string_enum! {
+    NameTagVisibility {
+        Always = "always",
+        HideForOtherTeams = "hideForOtherTeams",
+        HideForOwnTeam = "hideForOwnTeam",
+        Never = "never"
+    };
+
+    CollisionRule {
+        Always = "always",
+        PushOtherTeams = "pushOtherTeams",
+        PushOwnTeam = "pushOwnTeam",
+        Never = "never"
+    }
+}
+
+define_enum! {
+    TeamColor {
+        Black = 0,
+        DarkBlue = 1,
+        DarkGreen = 2,
+        DarkAqua = 3,
+        DarkRed = 4,
+        DarkPurple = 5,
+        Gold = 6,
+        Gray = 7,
+        DarkGray = 8,
+        Blue = 9,
+        Green = 10,
+        Aqua = 11,
+        Red = 12,
+        LightPurple = 13,
+        Yellow = 14,
+        White = 15,
+        Obfuscated = 16,
+        Bold = 17,
+        Strikethrough = 18,
+        Underlined = 19,
+        Italic = 20,
+        Reset = 21
+    }
+}
+
+packet_struct! {
+    TeamInfo {
+        display_name: Component,
+        friendly_flags: Flags8<TeamFlags>,
+        name_tag_visibility: NameTagVisibility,
+        collision_rule: CollisionRule,
+        color: TeamColor,
+        prefix: Component,
+        suffix: Component
+    }
+}
+
+packet_struct! {
+    DeathLocation {
+        dimension_name: Identifier,
+        location: i64
+    }
+}
+
+define_enum! {
+    AttributeOperation: u8 {
+        Add = 0,
+        MultiplyBase = 1,
+        MultiplyTotal = 2
+    }
+}
+
+packet_struct! {
+    AttributeModifier {
+        uuid: Uuid,
+        amount: f64,
+        operation: AttributeOperation
+    };
+
+    AttributeEntry {
+        key: Identifier,
+        value: f64,
+        modifiers: Vec<AttributeModifier>
+    }
+}
+
+packet_struct! {
+    DamagePosition {
+        x: f64,
+        y: f64,
+        z: f64
+    }
+}
+
+packet_struct! {
+    Statistic {
+        category_id: VarInt,
+        statistic_id: VarInt,
+        value: VarInt
+    }
+}
+
+packet_struct! {
+    ExplosionRecord {
+        x: i8,
+        y: i8,
+        z: i8
+    }
+}
+
+packet_struct! {
+    PlayerMotion {
+        x: f32,
+        y: f32,
+        z: f32
+    }
+}
+
+action_packet! {
+    /// Mode of a teams packet, along with the data sent for it.
+    TeamAction: u8 {
+        Create(0) {
+            info: TeamInfo,
+            entities: Vec<String>
+        },
+        Remove(1) {},
+        UpdateInfo(2) {
+            info: TeamInfo
+        },
+        AddEntities(3) {
+            entities: Vec<String>
+        },
+        RemoveEntities(4) {
+            entities: Vec<String>
+        }
+    }
+}
+
+packet_struct! {
+    RecipeBookSettings {
+        crafting_open: bool,
+        crafting_filter: bool,
+        smelting_open: bool,
+        smelting_filter: bool,
+        blast_furnace_open: bool,
+        blast_furnace_filter: bool,
+        smoker_open: bool,
+        smoker_filter: bool
+    }
+}
+
+action_packet! {
+    RecipeBookAction: VarInt {
+        Init(0) {
+            settings: RecipeBookSettings,
+            recipes: Vec<Identifier>,
+            highlighted: Vec<Identifier>
+        },
+        Add(1) {
+            settings: RecipeBookSettings,
+            recipes: Vec<Identifier>
+        },
+        Remove(2) {
+            settings: RecipeBookSettings,
+            recipes: Vec<Identifier>
+        }
+    }
+}
+
+packet_struct! {
+    Tag {
+        name: Identifier,
+        entries: Vec<VarInt>
+    }
+}
+
+packet_struct! {
+    TagRegistry {
+        registry: Identifier,
+        tags: Vec<Tag>
+    }
+}
+
+define_enum! {
+    ServerLinkType {
+        BugReport = 0,
+        CommunityGuidelines = 1,
+        Support = 2,
+        Status = 3,
+        Feedback = 4,
+        Community = 5,
+        Website = 6,
+        Forums = 7,
+        News = 8,
+        Announcements = 9
+    }
+}
+
+impl ServerLinkType {
+    /// Built-in link type by its snake case name, e.g. `"bug_report"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        use ServerLinkType::*;
+        Some(match name {
+            "bug_report" => BugReport,
+            "community_guidelines" => CommunityGuidelines,
+            "support" => Support,
+            "status" => Status,
+            "feedback" => Feedback,
+            "community" => Community,
+            "website" => Website,
+            "forums" => Forums,
+            "news" => News,
+            "announcements" => Announcements,
+            _ => return None,
+        })
+    }
+}
+
+/// Label of a server link, either a built-in type the client translates or custom text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerLinkLabel {
+    BuiltIn(ServerLinkType),
+    Custom(String),
+}
+
+/// NBT tag type of a string, which is a plain text component in network NBT.
+const NBT_STRING_TAG: u8 = 0x08;
+
+#[async_trait]
+impl PacketWrite for ServerLinkLabel {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match self {
+            ServerLinkLabel::BuiltIn(link_type) => {
+                true.pack_write(buffer, target_version).await?;
+                link_type.pack_write(buffer, target_version).await
+            }
+            ServerLinkLabel::Custom(text) => {
+                false.pack_write(buffer, target_version).await?;
+                let length = match u16::try_from(text.len()) {
+                    Ok(length) => length,
+                    Err(_) => {
+                        warn!("Server link label is too long: {} bytes!", text.len());
+                        bail!("Server link label is too long: {} bytes!", text.len())
+                    }
+                };
+                buffer.push(NBT_STRING_TAG);
+                length.pack_write(buffer, target_version).await?;
+                buffer.extend_from_slice(text.as_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PacketRead for ServerLinkLabel {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        if bool::pack_read(buffer, target_version).await? {
+            return Ok(ServerLinkLabel::BuiltIn(
+                ServerLinkType::pack_read(buffer, target_version).await?,
+            ));
+        }
+
+        let tag = u8::pack_read(buffer, target_version).await?;
+        if tag != NBT_STRING_TAG {
+            warn!(
+                "Only plain text server link labels are supported, got NBT tag {}",
+                tag
+            );
+            bail!(
+                "Only plain text server link labels are supported, got NBT tag {}",
+                tag
+            )
+        }
+        let length = u16::pack_read(buffer, target_version).await?;
+        let mut text = vec![0u8; length as usize];
+        std::io::Read::read_exact(buffer, &mut text)?;
+        Ok(ServerLinkLabel::Custom(String::from_utf8(text)?))
+    }
+}
+
+packet_struct! {
+    ServerLink {
+        label: ServerLinkLabel,
+        url: String
+    }
+}
+
+/// Recipe declared to the client, with its type specific data already encoded.
+///
+/// The layout of the data depends on the recipe type, so recipes can not be read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recipe {
+    pub recipe_type: Identifier,
+    pub recipe_id: Identifier,
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+impl PacketWrite for Recipe {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        self.recipe_type.pack_write(buffer, target_version).await?;
+        self.recipe_id.pack_write(buffer, target_version).await?;
+        buffer.extend_from_slice(&self.data);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PacketRead for Recipe {
+    #[allow(unused_variables)]
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        bail!("Recipes can not be read, their data layout depends on the recipe type")
+    }
+}
+
+/// Extra data carried by some particle types.
+///
+/// Which variant is expected depends on the particle id, so the data can only be decoded
+/// through [`ParticleData::read_for`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParticleData {
+    None,
+    /// `block`, `block_marker` and `falling_dust`
+    Block(i32),
+    Dust {
+        red: f32,
+        green: f32,
+        blue: f32,
+        scale: f32,
+    },
+    DustColorTransition {
+        from_red: f32,
+        from_green: f32,
+        from_blue: f32,
+        scale: f32,
+        to_red: f32,
+        to_green: f32,
+        to_blue: f32,
+    },
+    Item(Slot),
+    SculkCharge(f32),
+}
+
+impl ParticleData {
+    pub async fn read_for(
+        particle_id: i32,
+        buffer: &mut Cursor<&[u8]>,
+        target_version: u32,
+    ) -> anyhow::Result<Self> {
+        Ok(match particle_id {
+            2 | 3 | 25 => ParticleData::Block(VarInt::pack_read(buffer, target_version).await?.0),
+            14 => ParticleData::Dust {
+                red: f32::pack_read(buffer, target_version).await?,
+                green: f32::pack_read(buffer, target_version).await?,
+                blue: f32::pack_read(buffer, target_version).await?,
+                scale: f32::pack_read(buffer, target_version).await?,
+            },
+            15 => ParticleData::DustColorTransition {
+                from_red: f32::pack_read(buffer, target_version).await?,
+                from_green: f32::pack_read(buffer, target_version).await?,
+                from_blue: f32::pack_read(buffer, target_version).await?,
+                scale: f32::pack_read(buffer, target_version).await?,
+                to_red: f32::pack_read(buffer, target_version).await?,
+                to_green: f32::pack_read(buffer, target_version).await?,
+                to_blue: f32::pack_read(buffer, target_version).await?,
+            },
+            30 => ParticleData::SculkCharge(f32::pack_read(buffer, target_version).await?),
+            39 => ParticleData::Item(Slot::pack_read(buffer, target_version).await?),
+            _ => ParticleData::None,
+        })
+    }
+}
+
+#[async_trait]
+impl PacketWrite for ParticleData {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match self {
+            ParticleData::None => {}
+            ParticleData::Block(state) => VarInt(*state).pack_write(buffer, target_version).await?,
+            ParticleData::Dust {
+                red,
+                green,
+                blue,
+                scale,
+            } => {
+                for v in [red, green, blue, scale] {
+                    v.pack_write(buffer, target_version).await?;
+                }
+            }
+            ParticleData::DustColorTransition {
+                from_red,
+                from_green,
+                from_blue,
+                scale,
+                to_red,
+                to_green,
+                to_blue,
+            } => {
+                for v in [
+                    from_red, from_green, from_blue, scale, to_red, to_green, to_blue,
+                ] {
+                    v.pack_write(buffer, target_version).await?;
+                }
+            }
+            ParticleData::Item(slot) => slot.pack_write(buffer, target_version).await?,
+            ParticleData::SculkCharge(roll) => roll.pack_write(buffer, target_version).await?,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PacketRead for ParticleData {
+    #[allow(unused_variables)]
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        bail!("Particle data can not be read without its particle id, use ParticleData::read_for instead")
+    }
+}
+
+/// First protocol version that understands bundle delimiters.
+pub const BUNDLE_PROTOCOL_VERSION: u32 = 764;
+
+/// First protocol version with the damage event, older clients use an entity event instead.
+pub const DAMAGE_EVENT_PROTOCOL_VERSION: u32 = 762;
+
+/// First protocol version spawning players with the spawn entity packet instead of spawn player.
+pub const SPAWN_ENTITY_PLAYER_PROTOCOL_VERSION: u32 = 764;
+
+/// Entity type id of players in [`SPAWN_ENTITY_PLAYER_PROTOCOL_VERSION`].
+pub const PLAYER_ENTITY_TYPE: i32 = 122;
+
+/// First protocol version with server links shown in the pause menu.
+pub const SERVER_LINKS_PROTOCOL_VERSION: u32 = 767;
+
+/// First protocol version adding particles and a sound to explosions, which are not supported yet.
+pub const EXPLOSION_EFFECTS_PROTOCOL_VERSION: u32 = 765;
+
+/// View and simulation distance sent when joining, the same as the vanilla default.
+pub const JOIN_VIEW_DISTANCE: i32 = 10;
+
+/// Entity event status playing the hurt animation.
+pub const ENTITY_EVENT_HURT: i8 = 2;
 
 staged_packets! {
     PacketPlayOut("play", Outgoing) {
+        PacketPlayOutBundleDelimiter(_) {
+
+        };
+
+        PacketPlayOutSpawnEntity(0x00) {
+            entity_id: VarInt,
+            uuid: Uuid,
+            entity_type: VarInt,
+            x: f64,
+            y: f64,
+            z: f64,
+            pitch: Angle,
+            yaw: Angle,
+            head_yaw: Angle,
+            data: VarInt,
+            velocity_x: i16,
+            velocity_y: i16,
+            velocity_z: i16
+        };
+
+        PacketPlayOutSpawnPlayer(0x02) {
+            entity_id: VarInt,
+            uuid: Uuid,
+            x: f64,
+            y: f64,
+            z: f64,
+            yaw: Angle,
+            pitch: Angle
+        };
+
+        PacketPlayOutEntityAnimation(0x03) {
+            entity_id: VarInt,
+            animation: u8
+        };
+
+        PacketPlayOutAwardStatistics(0x04) {
+            statistics: Vec<Statistic>
+        };
+
+        PacketPlayOutBlockBreakAnimation(0x06) {
+            entity_id: VarInt,
+            location: Position,
+            stage: u8
+        };
+
+        PacketPlayOutCloseContainer(0x10) {
+            window_id: u8
+        };
+
+        PacketPlayOutWindowItems(0x11) {
+            window_id: u8,
+            state_id: VarInt,
+            slots: Vec<Slot>,
+            carried: Slot
+        };
+
+        PacketPlayOutSetCooldown(0x14) {
+            item_id: VarInt,
+            cooldown_ticks: VarInt
+        };
+
+        PacketPlayOutPluginMessage(0x15) {
+            channel: Identifier,
+            data: ByteArray
+        };
+
         PacketPlayOutDisconnect(0x17) {
             reason: Component
+        };
+
+        PacketPlayOutDamageEvent(_) {
+            entity_id: VarInt,
+            source_type_id: VarInt,
+            source_cause_id: VarInt,
+            source_direct_id: VarInt,
+            position: Option<DamagePosition>
+        };
+
+        PacketPlayOutEntityEvent(0x18) {
+            entity_id: i32,
+            status: i8
+        };
+
+        PacketPlayOutExplosion(0x19) {
+            x: f32,
+            y: f32,
+            z: f32,
+            strength: f32,
+            records: Vec<ExplosionRecord>,
+            player_motion: PlayerMotion
+        };
+
+        PacketPlayOutInitializeWorldBorder(0x1D) {
+            x: f64,
+            z: f64,
+            old_diameter: f64,
+            new_diameter: f64,
+            speed: VarLong,
+            portal_teleport_boundary: VarInt,
+            warning_blocks: VarInt,
+            warning_time: VarInt
+        };
+
+        PacketPlayOutParticle(0x21) {
+            particle_id: VarInt,
+            long_distance: bool,
+            x: f64,
+            y: f64,
+            z: f64,
+            offset_x: f32,
+            offset_y: f32,
+            offset_z: f32,
+            max_speed: f32,
+            count: i32,
+            data: ParticleData
+        };
+
+        PacketPlayOutKeepAlive(0x1E) {
+            keep_alive_id: i64
+        };
+
+        PacketPlayOutLogin(0x23) {
+            entity_id: i32,
+            is_hardcore: bool,
+            gamemode: GameMode,
+            previous_gamemode: i8,
+            dimension_names: Vec<Identifier>,
+            registry_codec: Blob,
+            dimension_type: Identifier,
+            dimension_name: Identifier,
+            hashed_seed: i64,
+            max_players: VarInt,
+            view_distance: VarInt,
+            simulation_distance: VarInt,
+            reduced_debug_info: bool,
+            enable_respawn_screen: bool,
+            is_debug: bool,
+            is_flat: bool,
+            death_location: Option<DeathLocation>
+        };
+
+        PacketPlayOutOpenScreen(0x2B) {
+            window_id: VarInt,
+            window_type: WindowType,
+            title: Component
+        };
+
+        PacketPlayOutOpenSignEditor(0x2C) {
+            location: Position,
+            side: SignSide
+        };
+
+        PacketPlayOutPlayerAbilities(0x2F) {
+            flags: Flags8<AbilityFlags>,
+            flying_speed: f32,
+            fov_modifier: f32
+        };
+
+        PacketPlayOutSynchronizePosition(0x36) {
+            x: f64,
+            y: f64,
+            z: f64,
+            yaw: f32,
+            pitch: f32,
+            flags: Flags8<RelativeFlags>,
+            teleport_id: VarInt,
+            dismount_vehicle: bool
+        };
+
+        PacketPlayOutUpdateRecipeBook(0x37) {
+            action: RecipeBookAction
+        };
+
+        PacketPlayOutRemoveEntityEffect(0x39) {
+            entity_id: VarInt,
+            effect_id: VarInt
+        };
+
+        PacketPlayOutRespawn(0x3B) {
+            dimension_type: Identifier,
+            dimension_name: Identifier,
+            hashed_seed: i64,
+            gamemode: GameMode,
+            previous_gamemode: i8,
+            is_debug: bool,
+            is_flat: bool,
+            copy_metadata: bool,
+            death_location: Option<DeathLocation>
+        };
+
+        PacketPlayOutEntityHeadLook(0x3C) {
+            entity_id: VarInt,
+            head_yaw: Angle
+        };
+
+        PacketPlayOutSetBorderCenter(0x41) {
+            x: f64,
+            z: f64
+        };
+
+        PacketPlayOutSetBorderLerpSize(0x42) {
+            old_diameter: f64,
+            new_diameter: f64,
+            speed: VarLong
+        };
+
+        PacketPlayOutSetBorderSize(0x43) {
+            diameter: f64
+        };
+
+        PacketPlayOutSetBorderWarningDelay(0x44) {
+            warning_time: VarInt
+        };
+
+        PacketPlayOutSetBorderWarningDistance(0x45) {
+            warning_blocks: VarInt
+        };
+
+        PacketPlayOutSetPassengers(0x54) {
+            vehicle_id: VarInt,
+            passengers: Vec<VarInt>
+        };
+
+        PacketPlayOutTeams(0x55) {
+            team_name: String,
+            action: TeamAction
+        };
+
+        PacketPlayOutSoundEffect(0x5D) {
+            sound: SoundRef,
+            category: SoundCategory,
+            x: i32,
+            y: i32,
+            z: i32,
+            volume: f32,
+            pitch: f32,
+            seed: i64
+        };
+
+        PacketPlayOutStopSound(0x5E) {
+            filter: SoundFilter
+        };
+
+        PacketPlayOutSystemChat(0x5F) {
+            content: Component,
+            position: VarInt
+        };
+
+        PacketPlayOutTabListHeaderFooter(0x60) {
+            header: Component,
+            footer: Component
+        };
+
+        PacketPlayOutUpdateAttributes(0x65) {
+            entity_id: VarInt,
+            attributes: Vec<AttributeEntry>
+        };
+
+        PacketPlayOutEntityEffect(0x66) {
+            entity_id: VarInt,
+            effect_id: VarInt,
+            amplifier: u8,
+            duration: VarInt,
+            flags: Flags8<EffectFlags>,
+            factor_data: Option<Blob>
+        };
+
+        PacketPlayOutUpdateRecipes(0x67) {
+            recipes: Vec<Recipe>
+        };
+
+        PacketPlayOutUpdateTags(0x68) {
+            registries: Vec<TagRegistry>
+        };
+
+        PacketPlayOutServerLinks(_) {
+            links: Vec<ServerLink>
         }
     }
 }
+
+impl PacketPlayOutPluginMessage {
+    /// Builds the `minecraft:brand` message announcing the server brand.
+    pub async fn brand(brand: &str) -> anyhow::Result<Self> {
+        let mut data = vec![];
+        brand
+            .to_string()
+            .pack_write(&mut data, crate::LATEST_PROTOCOL_VERSION)
+            .await?;
+        Ok(PacketPlayOutPluginMessage::new(
+            Identifier::minecraft("brand")?,
+            data,
+        ))
+    }
+}
+
+/// Entity animation of swinging the main arm.
+pub const ANIMATION_SWING_MAIN_ARM: u8 = 0;
+/// Entity animation of swinging the off hand.
+pub const ANIMATION_SWING_OFFHAND: u8 = 3;
+
+impl PacketPlayOutEntityAnimation {
+    /// Animation of the entity swinging its arm holding the hand.
+    pub fn swing(entity_id: i32, hand: Hand) -> Self {
+        let animation = match hand {
+            Hand::MainHand => ANIMATION_SWING_MAIN_ARM,
+            Hand::OffHand => ANIMATION_SWING_OFFHAND,
+        };
+        Self::new(entity_id, animation)
+    }
+}
+
+impl PacketPlayOutExplosion {
+    /// Offsets of every block within the radius of an explosion, relative to its block position.
+    ///
+    /// Offsets are sent as bytes, so the radius is capped at 127 blocks.
+    pub fn records_in_radius(radius: f32) -> Vec<ExplosionRecord> {
+        let radius = radius.clamp(0.0, i8::MAX as f32);
+        let bound = radius.floor() as i8;
+        let mut records = vec![];
+        for x in -bound..=bound {
+            for y in -bound..=bound {
+                for z in -bound..=bound {
+                    let distance = (x as f32).powi(2) + (y as f32).powi(2) + (z as f32).powi(2);
+                    if distance <= radius * radius {
+                        records.push(ExplosionRecord::new(x, y, z));
+                    }
+                }
+            }
+        }
+        records
+    }
+}
+
+impl PacketPlayOutLogin {
+    /// Join game packet placing the player in the overworld, with the registries of the codec.
+    pub fn overworld(
+        entity_id: i32,
+        gamemode: GameMode,
+        registry_codec: Blob,
+        max_players: u32,
+    ) -> anyhow::Result<Self> {
+        Ok(PacketPlayOutLogin::new(
+            entity_id,
+            false,
+            gamemode,
+            -1,
+            vec![Identifier::minecraft("overworld")?],
+            registry_codec,
+            Identifier::minecraft("overworld")?,
+            Identifier::minecraft("overworld")?,
+            0,
+            max_players.min(i32::MAX as u32) as i32,
+            JOIN_VIEW_DISTANCE,
+            JOIN_VIEW_DISTANCE,
+            false,
+            true,
+            false,
+            false,
+            None,
+        ))
+    }
+}
+
+impl PacketPlayOutRespawn {
+    /// Respawn in the overworld, as after dying, which resets the player's entity metadata.
+    pub fn overworld(gamemode: GameMode) -> anyhow::Result<Self> {
+        Ok(PacketPlayOutRespawn::new(
+            Identifier::minecraft("overworld")?,
+            Identifier::minecraft("overworld")?,
+            0,
+            gamemode,
+            -1,
+            false,
+            false,
+            false,
+            None,
+        ))
+    }
+}
+
+impl PacketPlayOutPlayerAbilities {
+    /// Abilities packet with the default speeds for the game mode.
+    pub fn for_gamemode(gamemode: GameMode) -> Self {
+        PacketPlayOutPlayerAbilities::new(Flags8(gamemode.abilities()), 0.05, 0.1)
+    }
+}
+
+impl PacketPlayOutUpdateAttributes {
+    pub fn builder(entity_id: i32) -> UpdateAttributesBuilder {
+        UpdateAttributesBuilder {
+            entity_id,
+            attributes: vec![],
+        }
+    }
+}
+
+/// Builds an attribute update, modifiers apply to the attribute added last.
+#[derive(Debug, Clone)]
+pub struct UpdateAttributesBuilder {
+    entity_id: i32,
+    attributes: Vec<AttributeEntry>,
+}
+
+impl UpdateAttributesBuilder {
+    pub fn attribute(mut self, key: Identifier, value: f64) -> Self {
+        self.attributes
+            .push(AttributeEntry::new(key, value, vec![]));
+        self
+    }
+
+    pub fn modifier(mut self, uuid: Uuid, amount: f64, operation: AttributeOperation) -> Self {
+        if let Some(entry) = self.attributes.last_mut() {
+            entry
+                .modifiers
+                .push(AttributeModifier::new(uuid, amount, operation));
+        }
+        self
+    }
+
+    pub fn build(self) -> PacketPlayOutUpdateAttributes {
+        PacketPlayOutUpdateAttributes::new(self.entity_id, self.attributes)
+    }
+}
@@ -1,5 +1,9 @@
+use crate::cfg::{build_favicon, load_favicon, png_dimensions, FAVICON_SIZE};
 use crate::chat::Component;
 use crate::{json_packet_struct, staged_packets};
+use anyhow::bail;
+use log::warn;
+use std::path::Path;
 use uuid::Uuid;
 
 json_packet_struct! {
@@ -23,10 +27,74 @@ json_packet_struct! {
         version: ServerVersion,
         players: ServerPlayers,
         description: Component,
-        favicon: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        favicon: Option<String>,
     };
 }
 
+impl StatusResponse {
+    pub fn builder(
+        version: ServerVersion,
+        players: ServerPlayers,
+        description: Component,
+    ) -> StatusResponseBuilder {
+        StatusResponseBuilder {
+            version,
+            players,
+            description,
+            favicon: None,
+        }
+    }
+}
+
+/// Builds a status response, only accepting favicons clients are able to show.
+#[derive(Debug, Clone)]
+pub struct StatusResponseBuilder {
+    version: ServerVersion,
+    players: ServerPlayers,
+    description: Component,
+    favicon: Option<String>,
+}
+
+impl StatusResponseBuilder {
+    /// Sets the favicon from the PNG image, which has to be 64x64.
+    pub fn favicon(mut self, png: &[u8]) -> anyhow::Result<Self> {
+        match png_dimensions(png) {
+            Some((FAVICON_SIZE, FAVICON_SIZE)) => {
+                self.favicon = Some(build_favicon(png));
+                Ok(self)
+            }
+            Some((width, height)) => {
+                warn!(
+                    "Favicon is {}x{} instead of {}x{}",
+                    width, height, FAVICON_SIZE, FAVICON_SIZE
+                );
+                bail!(
+                    "Favicon is {}x{} instead of {}x{}",
+                    width,
+                    height,
+                    FAVICON_SIZE,
+                    FAVICON_SIZE
+                )
+            }
+            None => {
+                warn!("Favicon is not a PNG image");
+                bail!("Favicon is not a PNG image")
+            }
+        }
+    }
+
+    /// Sets the favicon from the PNG file, which has to be 64x64.
+    pub async fn favicon_file<P: AsRef<Path>>(self, path: P) -> anyhow::Result<Self> {
+        let png = load_favicon(path.as_ref()).await?;
+        self.favicon(&png)
+    }
+
+    pub fn build(self) -> StatusResponse {
+        StatusResponse::new(self.version, self.players, self.description, self.favicon)
+    }
+}
+
 staged_packets! {
     OutStatus ("status", Outgoing) {
         PacketStatusOutResponse(0x00) {
@@ -1,10 +1,130 @@
-use crate::net_io::{PacketRead, PacketWrite};
+use crate::cfg::{RuntimeConfiguration, SoulflameConfiguration};
+use crate::net_io::{PacketRead, PacketWrite, VarInt};
+use crate::network::client::ClientConnection;
+use crate::network::encode::{PacketDecoder, PacketEncoder};
+use crate::network::{PlayerCount, SharedState};
+use anyhow::bail;
+use nbt::Blob;
 use std::io::Cursor;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
 use tokio::test;
 use tokio::time::Instant;
 
 const PROTO_VERSION: u32 = 759;
 
+/// Path in the temp directory no other test or test run uses.
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("soulflame_{}_{}", uuid::Uuid::new_v4(), name))
+}
+
+/// Client side of an in-memory connection to a [`ClientConnection`].
+struct TestClient {
+    stream: DuplexStream,
+    enc: PacketEncoder,
+    dec: PacketDecoder,
+}
+
+impl TestClient {
+    async fn send<P: PacketWrite + Sync>(&mut self, packet: &P) -> anyhow::Result<()> {
+        let mut buffer = vec![];
+        self.enc.consume(&mut buffer, packet).await?;
+        self.stream.write_all(&buffer).await?;
+        Ok(())
+    }
+
+    async fn read<P: PacketRead>(&mut self) -> anyhow::Result<P> {
+        let mut buffer = [0u8; 1024];
+        loop {
+            if let Some(packet) = self.dec.read::<P>().await? {
+                return Ok(packet);
+            }
+
+            let read = self.stream.read(&mut buffer).await?;
+            if read == 0 {
+                bail!("Server closed the connection")
+            }
+            self.dec.digest(&buffer[..read]);
+        }
+    }
+
+    /// Reads the packets sent while joining, which follow the login success.
+    async fn read_join(
+        &mut self,
+    ) -> anyhow::Result<Vec<crate::protocol::server::play::PacketPlayOut>> {
+        use crate::protocol::server::play::PacketPlayOut;
+
+        let mut packets = vec![];
+        loop {
+            let packet = self.read::<PacketPlayOut>().await?;
+            let last = matches!(packet, PacketPlayOut::PacketPlayOutUpdateTags(_));
+            packets.push(packet);
+            if last {
+                return Ok(packets);
+            }
+        }
+    }
+}
+
+/// Decodes every complete packet of the stage in the byte stream.
+async fn decode_stream<S: PacketRead>(bytes: &[u8]) -> anyhow::Result<Vec<S>> {
+    let mut dec = PacketDecoder::new();
+    dec.digest(bytes);
+    drain(&mut dec).await
+}
+
+async fn drain<S: PacketRead>(dec: &mut PacketDecoder) -> anyhow::Result<Vec<S>> {
+    let mut packets = vec![];
+    while let Some(packet) = dec.read::<S>().await? {
+        packets.push(packet);
+    }
+    Ok(packets)
+}
+
+/// Runtime configuration without a favicon or keys, with an empty registry codec.
+fn test_runtime() -> RuntimeConfiguration {
+    RuntimeConfiguration {
+        favicon: None,
+        keys: None,
+        registry_codec: Some(Arc::new(Blob::new())),
+        read_timeout: None,
+    }
+}
+
+async fn test_connection(config: SoulflameConfiguration) -> (ClientConnection, TestClient) {
+    let runtime = test_runtime();
+    let state = SharedState::new(&config, &runtime);
+    test_connection_with(config, state, runtime).await
+}
+
+/// In-memory connection sharing the player count, registry and status provider of `state`.
+async fn test_connection_with(
+    config: SoulflameConfiguration,
+    state: SharedState,
+    runtime: RuntimeConfiguration,
+) -> (ClientConnection, TestClient) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (reader, writer) = tokio::io::split(server);
+    let connection = ClientConnection::from_split(
+        reader,
+        writer,
+        "127.0.0.1:25565".parse().unwrap(),
+        state,
+        config,
+        runtime,
+    )
+    .await;
+
+    (
+        connection,
+        TestClient {
+            stream: client,
+            enc: PacketEncoder::new(),
+            dec: PacketDecoder::new(),
+        },
+    )
+}
+
 #[test]
 async fn packet_io() -> anyhow::Result<()> {
     let vi = "Test".to_string();
@@ -49,3 +169,4894 @@ fn report_time(i: Instant) {
 
     println!("Took {}mcs", dur.as_micros())
 }
+
+#[test]
+async fn handshake_negative_protocol() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{
+        HandshakeState, InHandshake, PacketHandshakeIn, MAX_SANE_PROTOCOL_VERSION,
+    };
+
+    let mut buffer = vec![];
+    PacketHandshakeIn::new(i32::MIN, "localhost".into(), 25565, HandshakeState::Status)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+
+    let InHandshake::PacketHandshakeIn(handshake) =
+        InHandshake::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert!(handshake
+        .validate_protocol(Some(MAX_SANE_PROTOCOL_VERSION))
+        .is_err());
+    assert!(handshake.validate_protocol(None).is_err());
+
+    let valid = PacketHandshakeIn::new(759, "localhost".into(), 25565, HandshakeState::Status);
+    assert!(valid
+        .validate_protocol(Some(MAX_SANE_PROTOCOL_VERSION))
+        .is_ok());
+
+    let huge = PacketHandshakeIn::new(i32::MAX, "localhost".into(), 25565, HandshakeState::Login);
+    assert!(huge
+        .validate_protocol(Some(MAX_SANE_PROTOCOL_VERSION))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+async fn open_screen_packet() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOutOpenScreen, WindowType};
+    use lobstermessage::lobster;
+
+    let mut buffer = vec![];
+    PacketPlayOutOpenScreen::new(1, WindowType::Generic9x3, lobster("Chest"))
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(&buffer[..3], &[0x2B, 0x01, 0x02]);
+    Ok(())
+}
+
+#[test]
+async fn window_items_packet() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutWindowItems};
+    use crate::world::item::Slot;
+
+    let packet =
+        PacketPlayOutWindowItems::new(1, 0, vec![Slot::item(1, 64), Slot::empty()], Slot::empty());
+    let mut buffer = vec![];
+    packet.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(
+        buffer,
+        vec![0x11, 0x01, 0x00, 0x02, 0x01, 0x01, 0x40, 0x00, 0x00, 0x00]
+    );
+
+    match PacketPlayOut::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await? {
+        PacketPlayOut::PacketPlayOutWindowItems(read) => {
+            assert_eq!(read.slots(), packet.slots());
+        }
+        other => panic!("Expected window items packet, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn open_inventory_slot_count() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::WindowType;
+    use crate::world::item::Slot;
+    use lobstermessage::lobster;
+
+    let (tx, rx) = flume::unbounded();
+    let handle = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+
+    let result = handle
+        .open_inventory(
+            1,
+            WindowType::Hopper,
+            lobster("Hopper"),
+            vec![Slot::empty(); 3],
+        )
+        .await;
+    assert!(result.is_err());
+    assert!(rx.is_empty());
+
+    handle
+        .open_inventory(
+            1,
+            WindowType::Hopper,
+            lobster("Hopper"),
+            vec![Slot::empty(); 41],
+        )
+        .await?;
+    assert_eq!(rx.len(), 2);
+    Ok(())
+}
+
+#[test]
+async fn close_container_clears_window() -> anyhow::Result<()> {
+    use crate::network::handler::{dispatch, NoopHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::PacketPlayIn;
+    use crate::protocol::server::play::WindowType;
+    use crate::world::item::Slot;
+    use lobstermessage::lobster;
+
+    let (tx, _rx) = flume::unbounded();
+    let handle = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    handle
+        .open_inventory(
+            3,
+            WindowType::Hopper,
+            lobster("Hopper"),
+            vec![Slot::empty(); 41],
+        )
+        .await?;
+    assert_eq!(handle.open_window(), Some(3));
+    assert!(handle.check_window(4).is_err());
+
+    let packet = PacketPlayIn::pack_read(&mut Cursor::new(&[0x0B, 0x03]), PROTO_VERSION).await?;
+    match &packet {
+        PacketPlayIn::PacketPlayInCloseContainer(close) => assert_eq!(*close.window_id(), 3),
+        other => panic!("Expected close container packet, got {:?}", other),
+    }
+    dispatch(&NoopHandler, &handle, packet).await?;
+
+    assert_eq!(handle.open_window(), None);
+    assert!(handle.check_window(3).is_err());
+    assert!(handle.on_window_closed(3).is_err());
+    Ok(())
+}
+
+#[test]
+async fn sound_ref_registry() -> anyhow::Result<()> {
+    use crate::net_io::IdOr;
+    use crate::protocol::server::play::SoundRef;
+
+    let mut buffer = vec![];
+    SoundRef::Id(5)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x06]);
+
+    let read = SoundRef::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert!(matches!(read, IdOr::Id(5)));
+    Ok(())
+}
+
+#[test]
+async fn sound_ref_inline() -> anyhow::Result<()> {
+    use crate::net_io::IdOr;
+    use crate::protocol::server::play::{SoundEvent, SoundRef};
+    use crate::util::Identifier;
+
+    let name = Identifier::minecraft("entity.cat.ambient")?;
+    let mut buffer = vec![];
+    SoundRef::Inline(SoundEvent::new(name.clone(), Some(16.0)))
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+
+    let text = name.to_string();
+    assert_eq!(buffer[0], 0x00);
+    assert_eq!(buffer[1] as usize, text.len());
+    assert_eq!(&buffer[2..2 + text.len()], text.as_bytes());
+    assert_eq!(&buffer[2 + text.len()..], &[0x01, 0x41, 0x80, 0x00, 0x00]);
+
+    match SoundRef::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await? {
+        IdOr::Inline(sound) => {
+            assert_eq!(sound.name(), &name);
+            assert_eq!(*sound.fixed_range(), Some(16.0));
+        }
+        other => panic!("Expected inline sound event, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn particle_without_data() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOutParticle, ParticleData};
+
+    let mut buffer = vec![];
+    PacketPlayOutParticle::new(
+        5,
+        false,
+        0.0,
+        64.0,
+        0.0,
+        0.5,
+        0.5,
+        0.5,
+        0.1,
+        10,
+        ParticleData::None,
+    )
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+
+    assert_eq!(&buffer[..3], &[0x21, 0x05, 0x00]);
+    assert_eq!(buffer.len(), 3 + 3 * 8 + 4 * 4 + 4);
+    assert_eq!(&buffer[buffer.len() - 4..], &[0x00, 0x00, 0x00, 0x0A]);
+    Ok(())
+}
+
+#[test]
+async fn particle_dust_data() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOutParticle, ParticleData};
+
+    let dust = ParticleData::Dust {
+        red: 1.0,
+        green: 0.0,
+        blue: 0.5,
+        scale: 2.0,
+    };
+    let mut buffer = vec![];
+    PacketPlayOutParticle::new(
+        14,
+        true,
+        0.0,
+        64.0,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        1,
+        dust.clone(),
+    )
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+
+    let data = &buffer[buffer.len() - 16..];
+    assert_eq!(
+        data,
+        &[
+            0x3F, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x40, 0x00,
+            0x00, 0x00
+        ]
+    );
+
+    let read = ParticleData::read_for(14, &mut Cursor::new(data), PROTO_VERSION).await?;
+    assert_eq!(read, dust);
+    Ok(())
+}
+
+#[test]
+async fn status_without_favicon() -> anyhow::Result<()> {
+    use crate::protocol::server::status::{ServerPlayers, ServerVersion, StatusResponse};
+    use lobstermessage::lobster;
+
+    let response = StatusResponse::new(
+        ServerVersion::new("Latest".into(), 759),
+        ServerPlayers::new(20, 0, vec![]),
+        lobster("A Soulflame server."),
+        None,
+    );
+    let json: serde_json::Value = serde_json::to_value(&response)?;
+    assert!(json.get("favicon").is_none());
+
+    let response = StatusResponse::new(
+        ServerVersion::new("Latest".into(), 759),
+        ServerPlayers::new(20, 0, vec![]),
+        lobster("A Soulflame server."),
+        Some("data:image/png;base64,".into()),
+    );
+    let json: serde_json::Value = serde_json::to_value(&response)?;
+    assert!(json.get("favicon").is_some());
+    Ok(())
+}
+
+#[test]
+async fn transfers_only_login() -> anyhow::Result<()> {
+    use crate::cfg::SoulflameConfiguration;
+    use crate::network::client::login_intent_denial;
+    use crate::protocol::client::handshake::HandshakeState;
+
+    let default = SoulflameConfiguration::default();
+    assert!(login_intent_denial(&default, &HandshakeState::Login).is_none());
+    assert!(login_intent_denial(&default, &HandshakeState::Transfer).is_none());
+
+    let transfers_only = SoulflameConfiguration {
+        accept_transfers_only: true,
+        ..Default::default()
+    };
+    assert!(login_intent_denial(&transfers_only, &HandshakeState::Login).is_some());
+    assert!(login_intent_denial(&transfers_only, &HandshakeState::Transfer).is_none());
+    Ok(())
+}
+
+#[test]
+async fn relative_flags() -> anyhow::Result<()> {
+    use crate::net_io::Flags8;
+    use crate::protocol::server::play::RelativeFlags;
+
+    let flags = Flags8(RelativeFlags::X | RelativeFlags::Z | RelativeFlags::X_ROT);
+    let mut buffer = vec![];
+    flags.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, vec![0b0001_0101]);
+
+    let read = Flags8::<RelativeFlags>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(read, flags);
+
+    let unknown =
+        Flags8::<RelativeFlags>::pack_read(&mut Cursor::new(&[0b0010_0001]), PROTO_VERSION).await;
+    assert!(unknown.is_err());
+    Ok(())
+}
+
+#[test]
+async fn peek_handshake_then_status() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::status::PacketStatusInRequest;
+    use crate::protocol::server::status::OutStatus;
+
+    let (mut connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "play.example.com".into(),
+            25565,
+            HandshakeState::Status,
+        ))
+        .await?;
+    client.send(&PacketStatusInRequest::new()).await?;
+
+    let handshake = connection.peek_handshake().await?;
+    assert_eq!(handshake.server_address(), "play.example.com");
+    connection.start();
+
+    match client.read::<OutStatus>().await? {
+        OutStatus::PacketStatusOutResponse(_) => {}
+        other => panic!("Expected status response, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+async fn packet_size_histogram() -> anyhow::Result<()> {
+    use crate::network::metrics::packet_sizes;
+    use crate::protocol::server::status::PacketStatusOutPong;
+
+    let mut enc = PacketEncoder::new();
+    let mut buffer = vec![];
+    for i in 0..3 {
+        enc.consume(&mut buffer, &PacketStatusOutPong::new(i))
+            .await?;
+    }
+
+    let sizes = packet_sizes();
+    let pong = sizes
+        .get(&("PacketStatusOutPong".to_string(), 0x01))
+        .expect("Pong packets should be recorded");
+    // packet id + i64 payload
+    assert!(pong.buckets()[0] >= 3);
+    assert!(pong.total_bytes() >= 3 * 9);
+    Ok(())
+}
+
+#[test]
+async fn vec_preallocation_cap() -> anyhow::Result<()> {
+    use crate::net_io::{preallocate, VarInt, MAX_PREALLOCATION_BYTES};
+
+    let vals = preallocate::<u64>(1024 * 1024);
+    assert!(vals.capacity() * std::mem::size_of::<u64>() <= MAX_PREALLOCATION_BYTES);
+    assert_eq!(preallocate::<u64>(4).capacity(), 4);
+
+    // declares the max array size, but only carries a single element
+    let mut buffer = vec![];
+    VarInt(1024 * 1024)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    1u64.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert!(
+        Vec::<u64>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn team_create_packet() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOut, TeamAction, TeamColor};
+    use crate::world::team::Team;
+
+    let team = Team::new("red").color(TeamColor::Red).friendly_fire(true);
+    let mut buffer = vec![];
+    team.create(vec!["Notch".into()])
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(&buffer[..6], &[0x55, 0x03, b'r', b'e', b'd', 0x00]);
+
+    match PacketPlayOut::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await? {
+        PacketPlayOut::PacketPlayOutTeams(packet) => match packet.action() {
+            TeamAction::Create { info, entities } => {
+                assert_eq!(entities, &vec!["Notch".to_string()]);
+                assert_eq!(info.friendly_flags().0.bits(), 0x01);
+                assert!(matches!(info.color(), TeamColor::Red));
+            }
+            other => panic!("Expected create action, got {:?}", other),
+        },
+        other => panic!("Expected teams packet, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn team_add_entities_packet() -> anyhow::Result<()> {
+    use crate::world::team::Team;
+
+    let mut buffer = vec![];
+    Team::new("red")
+        .add_entities(vec!["a".into(), "b".into()])
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(
+        buffer,
+        vec![0x55, 0x03, b'r', b'e', b'd', 0x03, 0x02, 0x01, b'a', 0x01, b'b']
+    );
+    Ok(())
+}
+
+#[test]
+async fn interact_attack() -> anyhow::Result<()> {
+    use crate::protocol::client::play::{InteractType, PacketPlayIn};
+
+    let bytes: [u8; 4] = [0x0F, 0x2A, 0x01, 0x01];
+    match PacketPlayIn::pack_read(&mut Cursor::new(&bytes), PROTO_VERSION).await? {
+        PacketPlayIn::PacketPlayInInteract(interact) => {
+            assert_eq!(*interact.entity_id(), 42);
+            assert_eq!(interact.action(), &InteractType::Attack);
+            assert!(*interact.sneaking());
+        }
+        other => panic!("Expected interact packet, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn interact_at_position() -> anyhow::Result<()> {
+    use crate::protocol::client::play::{Hand, InteractType, PacketPlayIn};
+
+    let bytes: [u8; 17] = [
+        0x0F, 0x2A, 0x02, 0x3F, 0x00, 0x00, 0x00, 0x3F, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00,
+    ];
+    match PacketPlayIn::pack_read(&mut Cursor::new(&bytes), PROTO_VERSION).await? {
+        PacketPlayIn::PacketPlayInInteract(interact) => {
+            assert_eq!(
+                interact.action(),
+                &InteractType::InteractAt {
+                    target_x: 0.5,
+                    target_y: 1.0,
+                    target_z: 0.0,
+                    hand: Hand::OffHand
+                }
+            );
+            assert!(!*interact.sneaking());
+        }
+        other => panic!("Expected interact packet, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn spoofed_brand_and_version() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::status::PacketStatusInRequest;
+    use crate::protocol::server::play::PacketPlayOutPluginMessage;
+    use crate::protocol::server::status::OutStatus;
+
+    let config = SoulflameConfiguration {
+        spoof_brand: Some("vanilla".into()),
+        spoof_version_name: Some("1.19".into()),
+        ..Default::default()
+    };
+    assert_eq!(config.brand(), "vanilla");
+    assert_eq!(
+        SoulflameConfiguration::default().brand(),
+        crate::SERVER_BRAND
+    );
+
+    let brand = PacketPlayOutPluginMessage::brand(config.brand()).await?;
+    assert_eq!(brand.channel().to_string(), "minecraft:brand");
+    assert_eq!(brand.data(), &b"\x07vanilla".to_vec());
+
+    let (connection, mut client) = test_connection(config).await;
+    connection.start();
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".into(),
+            25565,
+            HandshakeState::Status,
+        ))
+        .await?;
+    client.send(&PacketStatusInRequest::new()).await?;
+
+    match client.read::<OutStatus>().await? {
+        OutStatus::PacketStatusOutResponse(response) => {
+            let json = serde_json::to_value(response.response())?;
+            assert_eq!(json["version"]["name"], "1.19");
+        }
+        other => panic!("Expected status response, got {:?}", other),
+    }
+    Ok(())
+}
+
+/// Handshake to `localhost:25565` with protocol 759, followed by a status request and a ping.
+const STATUS_STREAM: [u8; 29] = [
+    0x10, 0x00, 0xF7, 0x05, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't', 0x63, 0xDD,
+    0x01, 0x01, 0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A,
+];
+
+async fn assert_status_stream(bytes: &[u8]) -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, InHandshake};
+    use crate::protocol::client::status::InStatus;
+
+    let mut dec = PacketDecoder::new();
+    dec.digest(bytes);
+
+    let InHandshake::PacketHandshakeIn(handshake) = dec
+        .read::<InHandshake>()
+        .await?
+        .expect("Stream should start with a handshake");
+    assert_eq!(*handshake.protocol_version(), 759);
+    assert_eq!(handshake.server_address(), "localhost");
+    assert_eq!(*handshake.server_port(), 25565);
+    assert_eq!(handshake.next_state(), &HandshakeState::Status);
+
+    let status = drain::<InStatus>(&mut dec).await?;
+    assert_eq!(status.len(), 2);
+    assert!(matches!(status[0], InStatus::PacketStatusInRequest(_)));
+    match &status[1] {
+        InStatus::PacketStatusInPing(ping) => assert_eq!(*ping.payload(), 42),
+        other => panic!("Expected ping packet, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn replay_status_stream() -> anyhow::Result<()> {
+    use crate::protocol::client::status::InStatus;
+
+    assert_status_stream(&STATUS_STREAM).await?;
+
+    assert_eq!(
+        decode_stream::<InStatus>(&STATUS_STREAM[17..]).await?.len(),
+        2
+    );
+    Ok(())
+}
+
+#[test]
+async fn record_status_stream() -> anyhow::Result<()> {
+    use crate::protocol::server::status::OutStatus;
+
+    let path = temp_path("record_status_stream.bin");
+    let (mut connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    connection.record_stream(&path).await?;
+    connection.start();
+
+    client.stream.write_all(&STATUS_STREAM).await?;
+    client.read::<OutStatus>().await?;
+    client.read::<OutStatus>().await?;
+
+    let recorded = tokio::fs::read(&path).await?;
+    assert_eq!(recorded, STATUS_STREAM.to_vec());
+    assert_status_stream(&recorded).await?;
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[test]
+async fn mount_and_dismount() -> anyhow::Result<()> {
+    use crate::protocol::server::play::PacketPlayOutSetPassengers;
+    use crate::world::entity::Passengers;
+
+    let mut passengers = Passengers::new();
+
+    async fn write(packets: Vec<PacketPlayOutSetPassengers>) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut written = vec![];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.pack_write(&mut buffer, PROTO_VERSION).await?;
+            written.push(buffer);
+        }
+        Ok(written)
+    }
+
+    assert_eq!(
+        write(passengers.mount(10, 20)).await?,
+        vec![vec![0x54, 0x0A, 0x01, 0x14]]
+    );
+    assert_eq!(passengers.vehicle_of(20), Some(10));
+
+    // switching vehicles announces that the old one lost its passenger
+    assert_eq!(
+        write(passengers.mount(11, 20)).await?,
+        vec![vec![0x54, 0x0A, 0x00], vec![0x54, 0x0B, 0x01, 0x14]]
+    );
+    assert_eq!(passengers.vehicle_of(20), Some(11));
+    assert!(passengers.passengers(10).is_empty());
+    assert_eq!(
+        write(passengers.mount(11, 20)).await?,
+        vec![vec![0x54, 0x0B, 0x01, 0x14]]
+    );
+    assert_eq!(
+        write(passengers.mount(10, 20)).await?,
+        vec![vec![0x54, 0x0B, 0x00], vec![0x54, 0x0A, 0x01, 0x14]]
+    );
+
+    let mut buffer = vec![];
+    passengers
+        .dismount(20)
+        .expect("Passenger should be riding")
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x54, 0x0A, 0x00]);
+    assert_eq!(passengers.vehicle_of(20), None);
+    assert!(passengers.dismount(20).is_none());
+    Ok(())
+}
+
+#[test]
+async fn gamemode_from_str() -> anyhow::Result<()> {
+    use crate::protocol::server::play::GameMode;
+
+    assert_eq!("survival".parse::<GameMode>()?, GameMode::Survival);
+    assert_eq!("CREATIVE".parse::<GameMode>()?, GameMode::Creative);
+    assert_eq!("Adventure".parse::<GameMode>()?, GameMode::Adventure);
+    assert_eq!("spectator".parse::<GameMode>()?, GameMode::Spectator);
+    assert!("hardcore".parse::<GameMode>().is_err());
+    Ok(())
+}
+
+#[test]
+async fn gamemode_abilities() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{AbilityFlags, GameMode, PacketPlayOutPlayerAbilities};
+
+    let mut buffer = vec![];
+    GameMode::Creative
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x01]);
+
+    let abilities = GameMode::Creative.abilities();
+    assert!(abilities.contains(AbilityFlags::ALLOW_FLYING | AbilityFlags::INSTANT_BREAK));
+    assert!(GameMode::Survival.abilities().is_empty());
+
+    let mut buffer = vec![];
+    PacketPlayOutPlayerAbilities::for_gamemode(GameMode::Creative)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer[..2], [0x2F, 0x0D]);
+    Ok(())
+}
+
+#[test]
+async fn angle_conversion() -> anyhow::Result<()> {
+    use crate::net_io::Angle;
+
+    assert_eq!(Angle::from_degrees(180.0), Angle(128));
+    assert_eq!(Angle::from_degrees(0.0), Angle(0));
+    assert_eq!(Angle::from_degrees(360.0), Angle(0));
+    assert_eq!(Angle::from_degrees(720.0 + 45.0), Angle(32));
+    assert_eq!(Angle::from_degrees(-360.0), Angle(0));
+    assert_eq!(Angle::from_degrees(359.9), Angle(255));
+    assert_eq!(Angle::from_degrees(-0.0001), Angle(255));
+    assert_eq!(Angle(255).to_degrees(), 358.59375);
+    for step in 0..=255u8 {
+        assert_eq!(Angle::from_degrees(Angle(step).to_degrees()), Angle(step));
+    }
+
+    let mut buffer = vec![];
+    Angle(200).pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, vec![200]);
+    assert_eq!(
+        Angle::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?,
+        Angle(200)
+    );
+    Ok(())
+}
+
+#[test]
+async fn entity_head_look() -> anyhow::Result<()> {
+    use crate::net_io::Angle;
+    use crate::world::entity::HeadRotations;
+
+    assert_eq!(Angle::from_degrees(90.0), Angle(64));
+    assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+    assert_eq!(Angle(128).to_degrees(), 180.0);
+
+    let mut heads = HeadRotations::new();
+    let mut buffer = vec![];
+    heads
+        .turn_head(5, 90.0)
+        .expect("Head should turn")
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x3C, 0x05, 0x40]);
+    assert!(heads.turn_head(5, 90.5).is_none());
+    Ok(())
+}
+
+#[test]
+async fn status_with_ping() -> anyhow::Result<()> {
+    use crate::protocol::server::status::OutStatus;
+
+    let (connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    let handle = tokio::spawn(connection.handle());
+
+    client.stream.write_all(&STATUS_STREAM).await?;
+    assert!(matches!(
+        client.read::<OutStatus>().await?,
+        OutStatus::PacketStatusOutResponse(_)
+    ));
+    match client.read::<OutStatus>().await? {
+        OutStatus::PacketStatusOutPong(pong) => assert_eq!(*pong.payload(), 42),
+        other => panic!("Expected pong packet, got {:?}", other),
+    }
+    drop(client);
+
+    handle.await??;
+    Ok(())
+}
+
+#[test]
+async fn status_without_ping() -> anyhow::Result<()> {
+    use crate::protocol::server::status::OutStatus;
+
+    let logs = capture_logs();
+    let (connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    let handle = tokio::spawn(connection.handle());
+
+    // handshake and status request only
+    client.stream.write_all(&STATUS_STREAM[..19]).await?;
+    assert!(matches!(
+        client.read::<OutStatus>().await?,
+        OutStatus::PacketStatusOutResponse(_)
+    ));
+    drop(client);
+
+    handle.await??;
+    // closing without a ping is a normal end of the call
+    assert_eq!(logs(log::Level::Warn), Vec::<String>::new());
+    Ok(())
+}
+
+#[test]
+async fn custom_status_provider() -> anyhow::Result<()> {
+    use crate::network::status::StatusProvider;
+    use crate::network::NetworkListener;
+    use crate::protocol::server::status::{
+        OutStatus, ServerPlayers, ServerVersion, StatusResponse,
+    };
+    use async_trait::async_trait;
+    use lobstermessage::lobster;
+    use std::sync::Arc;
+
+    struct Maintenance;
+
+    #[async_trait]
+    impl StatusProvider for Maintenance {
+        async fn status(&self, client_version: u32) -> StatusResponse {
+            assert_eq!(client_version, 759);
+            StatusResponse::new(
+                ServerVersion::new("Maintenance".to_string(), -1),
+                ServerPlayers::new(0, 0, vec![]),
+                lobster("<red>Down for maintenance"),
+                None,
+            )
+        }
+    }
+
+    let (mut connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    connection.set_status_provider(Arc::new(Maintenance));
+    connection.start();
+
+    client.stream.write_all(&STATUS_STREAM[..19]).await?;
+    match client.read::<OutStatus>().await? {
+        OutStatus::PacketStatusOutResponse(response) => {
+            let json = serde_json::to_value(response.response())?;
+            assert_eq!(json["version"]["name"], "Maintenance");
+            assert_eq!(json["version"]["protocol"], -1);
+        }
+        other => panic!("Expected status response, got {:?}", other),
+    }
+
+    // connections accepted by the listener share its provider
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        ..Default::default()
+    };
+    let mut listener = NetworkListener::bind("127.0.0.1".into(), 0, config).await?;
+    listener.set_status_provider(Arc::new(Maintenance));
+    let response = listener.status_provider().status(759).await;
+    let json = serde_json::to_value(&response)?;
+    assert_eq!(json["version"]["name"], "Maintenance");
+    Ok(())
+}
+
+#[test]
+async fn update_attributes_packet() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{AttributeOperation, PacketPlayOutUpdateAttributes};
+    use crate::util::Identifier;
+    use uuid::Uuid;
+
+    let speed = Identifier::minecraft("generic.movement_speed")?;
+    let mut expected = vec![0x65, 0x07, 0x01, 0x20];
+    expected.extend(b"minecraft:generic.movement_speed");
+    expected.extend(0.1f64.to_be_bytes());
+
+    let mut buffer = vec![];
+    PacketPlayOutUpdateAttributes::builder(7)
+        .attribute(speed.clone(), 0.1)
+        .build()
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut without_modifier = expected.clone();
+    without_modifier.push(0x00);
+    assert_eq!(buffer, without_modifier);
+
+    let uuid = Uuid::from_u128(1);
+    let mut buffer = vec![];
+    PacketPlayOutUpdateAttributes::builder(7)
+        .attribute(speed, 0.1)
+        .modifier(uuid, 0.5, AttributeOperation::MultiplyBase)
+        .build()
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut with_modifier = expected;
+    with_modifier.push(0x01);
+    with_modifier.extend(uuid.as_bytes());
+    with_modifier.extend(0.5f64.to_be_bytes());
+    with_modifier.push(0x01);
+    assert_eq!(buffer, with_modifier);
+    Ok(())
+}
+
+#[cfg(feature = "echo")]
+#[test]
+async fn echo_chat() -> anyhow::Result<()> {
+    use crate::network::echo::EchoHandler;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::client::play::PacketPlayInChatMessage;
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::play::PacketPlayOut;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    // connections get the handler from the state the listener shares with them
+    let mut state = SharedState::new(&config, &test_runtime());
+    state.handler = Arc::new(EchoHandler);
+    let (connection, mut client) = test_connection_with(config, state, test_runtime()).await;
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+    client.read_join().await?;
+
+    client
+        .send(&PacketPlayInChatMessage::new(
+            "hello".to_string(),
+            0,
+            0,
+            vec![],
+            false,
+        ))
+        .await?;
+    match client.read::<PacketPlayOut>().await? {
+        PacketPlayOut::PacketPlayOutSystemChat(chat) => {
+            assert_eq!(chat.content().clone().flatten(), "hello")
+        }
+        other => panic!("Expected chat packet, got {:?}", other),
+    }
+
+    drop(client);
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn varlong_edges() -> anyhow::Result<()> {
+    use crate::net_io::VarLong;
+
+    let minus_one = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    let mut buffer = vec![];
+    VarLong(-1).pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, minus_one.to_vec());
+
+    for v in [0, 1, -1, i64::MAX, i64::MIN] {
+        let mut buffer = vec![];
+        VarLong(v).pack_write(&mut buffer, PROTO_VERSION).await?;
+        let mut cursor = Cursor::new(&buffer[..]);
+        assert_eq!(
+            VarLong::pack_read(&mut cursor, PROTO_VERSION).await?,
+            VarLong(v)
+        );
+        assert_eq!(cursor.position() as usize, buffer.len());
+    }
+
+    let overlong: [u8; 11] = [
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x81, 0x01,
+    ];
+    assert!(
+        VarLong::pack_read(&mut Cursor::new(&overlong[..]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn cidr_access_list() -> anyhow::Result<()> {
+    use crate::network::AccessList;
+
+    let config = SoulflameConfiguration {
+        allow_cidrs: vec!["10.0.0.0/8".to_string(), "2001:db8::/32".to_string()],
+        deny_cidrs: vec!["10.1.0.0/16".to_string()],
+        ..Default::default()
+    };
+    let access = AccessList::from_cfg(&config)?;
+
+    assert!(!access.permits("10.1.2.3".parse()?));
+    assert!(access.permits("10.2.3.4".parse()?));
+    assert!(access.permits("2001:db8::1".parse()?));
+    assert!(!access.permits("192.168.0.1".parse()?));
+
+    let open = AccessList::from_cfg(&SoulflameConfiguration {
+        deny_cidrs: vec!["fe80::/10".to_string()],
+        ..Default::default()
+    })?;
+    assert!(open.permits("192.168.0.1".parse()?));
+    assert!(!open.permits("fe80::1".parse()?));
+
+    assert!(AccessList::from_cfg(&SoulflameConfiguration {
+        deny_cidrs: vec!["not a range".to_string()],
+        ..Default::default()
+    })
+    .is_err());
+    Ok(())
+}
+
+#[test]
+async fn duplicate_login_policies() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::network::player::PlayerHandle;
+    use crate::network::registry::PlayerRegistry;
+    use crate::protocol::server::play::PacketPlayOut;
+    use uuid::Uuid;
+
+    let uuid = Uuid::from_u128(7);
+    let addr = "127.0.0.1:25565".parse()?;
+    let (first_tx, first_rx) = flume::unbounded();
+    let first = PlayerHandle::new(addr, first_tx);
+    let (second_tx, _second_rx) = flume::unbounded();
+    let second = PlayerHandle::new(addr, second_tx);
+
+    let registry = PlayerRegistry::new();
+    assert!(registry
+        .admit(uuid, first.clone(), DuplicateLogin::Deny)
+        .await
+        .is_none());
+    assert!(registry
+        .admit(uuid, second.clone(), DuplicateLogin::Deny)
+        .await
+        .is_some());
+    assert!(registry.get(&uuid).unwrap().same_session(&first));
+    assert!(first_rx.is_empty());
+
+    assert!(registry
+        .admit(uuid, second.clone(), DuplicateLogin::Kick)
+        .await
+        .is_none());
+    assert!(registry.get(&uuid).unwrap().same_session(&second));
+    assert!(matches!(
+        first_rx.recv_async().await?,
+        PacketPlayOut::PacketPlayOutDisconnect(_)
+    ));
+
+    // the kicked session leaving must not remove the new one
+    registry.unregister(&uuid, &first);
+    assert_eq!(registry.len(), 1);
+    registry.unregister(&uuid, &second);
+    assert!(registry.is_empty());
+    Ok(())
+}
+
+#[test]
+async fn plugin_channel_registration() -> anyhow::Result<()> {
+    use crate::network::handler::{dispatch, NoopHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::{PacketPlayIn, PacketPlayInPluginMessage};
+    use crate::util::Identifier;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    let first = Identifier::new("example", "first")?;
+    let second = Identifier::new("example", "second")?;
+
+    dispatch(
+        &NoopHandler,
+        &player,
+        PacketPlayIn::PacketPlayInPluginMessage(PacketPlayInPluginMessage::new(
+            Identifier::minecraft("register")?,
+            b"example:first\0example:second".to_vec(),
+        )),
+    )
+    .await?;
+    assert!(player.supports_channel(&first));
+    assert!(player.supports_channel(&second));
+
+    dispatch(
+        &NoopHandler,
+        &player,
+        PacketPlayIn::PacketPlayInPluginMessage(PacketPlayInPluginMessage::new(
+            Identifier::minecraft("unregister")?,
+            b"example:first".to_vec(),
+        )),
+    )
+    .await?;
+    assert!(!player.supports_channel(&first));
+    assert!(player.supports_channel(&second));
+
+    assert!(!player.send_plugin_message(first, vec![1]).await?);
+    assert!(player.send_plugin_message(second, vec![1]).await?);
+    assert_eq!(rx.len(), 1);
+    Ok(())
+}
+
+#[test]
+async fn slow_handler_warning() -> anyhow::Result<()> {
+    use crate::network::handler::{dispatch_timed, PacketHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::{PacketPlayIn, PacketPlayInChatMessage};
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    struct SlowHandler;
+
+    #[async_trait]
+    impl PacketHandler for SlowHandler {
+        async fn on_chat(
+            &self,
+            _player: &PlayerHandle,
+            _packet: &PacketPlayInChatMessage,
+        ) -> anyhow::Result<()> {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            Ok(())
+        }
+    }
+
+    let (tx, _rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    let chat = PacketPlayIn::PacketPlayInChatMessage(PacketPlayInChatMessage::new(
+        "slow".to_string(),
+        0,
+        0,
+        vec![],
+        false,
+    ));
+    assert_eq!(chat.packet_name(), "PacketPlayInChatMessage");
+
+    let logs = capture_logs();
+    let slow = dispatch_timed(
+        &SlowHandler,
+        &player,
+        chat.clone(),
+        Some(Duration::from_millis(10)),
+    )
+    .await?;
+    assert!(slow.expect("Handler should be reported") >= Duration::from_millis(30));
+    let warnings = logs(log::Level::Warn);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("Handling PacketPlayInChatMessage for 127.0.0.1 took"));
+
+    let logs = capture_logs();
+    assert!(dispatch_timed(
+        &SlowHandler,
+        &player,
+        chat.clone(),
+        Some(Duration::from_secs(5))
+    )
+    .await?
+    .is_none());
+    assert!(dispatch_timed(&SlowHandler, &player, chat, None)
+        .await?
+        .is_none());
+    assert!(logs(log::Level::Warn).is_empty());
+    Ok(())
+}
+
+#[test]
+async fn id_or_inline() -> anyhow::Result<()> {
+    use crate::net_io::IdOr;
+
+    let mut buffer = vec![];
+    IdOr::<String>::Id(5)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x06]);
+    assert_eq!(
+        IdOr::<String>::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?,
+        IdOr::Id(5)
+    );
+
+    let mut buffer = vec![];
+    IdOr::Inline("chat".to_string())
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x00, 0x04, b'c', b'h', b'a', b't']);
+    assert_eq!(
+        IdOr::<String>::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?,
+        IdOr::Inline("chat".to_string())
+    );
+    Ok(())
+}
+
+#[test]
+async fn closing_read_side_stops_channels() -> anyhow::Result<()> {
+    use crate::cfg::ChannelScheduling;
+    use crate::network::client::{run_channels, InboundPacketChannel, OutgoingPacketChannel};
+    use std::time::Duration;
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (client, server) = tokio::io::duplex(1024);
+    let (reader, writer) = tokio::io::split(server);
+    let (inbound_tx, _inbound_rx) = flume::bounded(32);
+    // kept alive, so the outgoing channel would wait forever on its own
+    let (_outgoing_tx, outgoing_rx) = flume::unbounded();
+
+    let task = tokio::spawn(run_channels(
+        InboundPacketChannel::new(Box::new(reader), inbound_tx, addr, None),
+        OutgoingPacketChannel::new(Box::new(writer), outgoing_rx, addr),
+        ChannelScheduling::Shared,
+    ));
+    drop(client);
+
+    tokio::time::timeout(Duration::from_secs(1), task).await???;
+    Ok(())
+}
+
+#[test]
+async fn bundle_delimiters() -> anyhow::Result<()> {
+    use crate::net_io::Angle;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{
+        PacketPlayOut, PacketPlayOutBundleDelimiter, PacketPlayOutCloseContainer,
+        PacketPlayOutSpawnEntity, BUNDLE_PROTOCOL_VERSION, PLAYER_ENTITY_TYPE,
+    };
+    use uuid::Uuid;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_protocol_version(BUNDLE_PROTOCOL_VERSION);
+    player
+        .bundle(|b| {
+            b.push(PacketPlayOutCloseContainer::new(1));
+            b.push(PacketPlayOutCloseContainer::new(2));
+        })
+        .await?;
+
+    let names = rx.drain().map(|p| p.packet_name()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "PacketPlayOutBundleDelimiter",
+            "PacketPlayOutCloseContainer",
+            "PacketPlayOutCloseContainer",
+            "PacketPlayOutBundleDelimiter"
+        ]
+    );
+
+    player.set_protocol_version(PROTO_VERSION);
+    player
+        .bundle(|b| b.push(PacketPlayOutCloseContainer::new(1)))
+        .await?;
+    assert!(matches!(
+        rx.drain().collect::<Vec<_>>()[..],
+        [PacketPlayOut::PacketPlayOutCloseContainer(_)]
+    ));
+
+    // no supported version has the delimiter, so it is neither read nor written
+    let delimiter = [0x00];
+    assert!(
+        PacketPlayOut::pack_read(&mut Cursor::new(&delimiter[..]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    assert!(PacketPlayOutBundleDelimiter::new()
+        .pack_write(&mut vec![], PROTO_VERSION)
+        .await
+        .is_err());
+
+    let mut buffer = vec![];
+    PacketPlayOutSpawnEntity::new(
+        1,
+        Uuid::nil(),
+        PLAYER_ENTITY_TYPE,
+        0.0,
+        0.0,
+        0.0,
+        Angle(0),
+        Angle(0),
+        Angle(0),
+        0,
+        0,
+        0,
+        0,
+    )
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+    assert_eq!(buffer[0], 0x00);
+    assert!(matches!(
+        PacketPlayOut::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?,
+        PacketPlayOut::PacketPlayOutSpawnEntity(_)
+    ));
+    Ok(())
+}
+
+#[test]
+async fn compression_threshold_config() -> anyhow::Result<()> {
+    use crate::protocol::server::login::OutLogin;
+
+    for (threshold, expected) in [(-1, None), (256, Some(256))] {
+        let config = SoulflameConfiguration {
+            compression_threshold: threshold,
+            ..Default::default()
+        };
+        assert_eq!(config.compression(), expected);
+
+        let (mut connection, mut client) = test_connection(config).await;
+        connection.enable_compression().await?;
+        assert_eq!(connection.compression_threshold(), expected);
+
+        match client.read::<OutLogin>().await? {
+            OutLogin::PacketLoginOutCompression(packet) => {
+                assert_eq!(*packet.threshold(), threshold)
+            }
+            other => panic!("Expected compression packet, got {:?}", other),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+async fn item_cooldown_packet() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_item_cooldown(800, 20).await?;
+    player.set_item_cooldown(800, 0).await?;
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x14, 0xA0, 0x06, 0x14]);
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x14, 0xA0, 0x06, 0x00]);
+    Ok(())
+}
+
+#[test]
+async fn position_packing() -> anyhow::Result<()> {
+    use crate::net_io::Position;
+
+    for position in [
+        Position::new(0, 0, 0),
+        Position::new(-1, -1, -1),
+        Position::new(-33554432, -2048, -33554432),
+        Position::new(33554431, 2047, 33554431),
+        Position::new(-18357644, 831, 12345678),
+    ] {
+        let mut buffer = vec![];
+        position.pack_write(&mut buffer, PROTO_VERSION).await?;
+        assert_eq!(buffer, position.packed().to_be_bytes());
+        let read = Position::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?;
+        assert_eq!(read, position);
+    }
+    assert_eq!(Position::new(-1, -1, -1).packed(), -1);
+
+    // only 12 bits are left for y, larger heights wrap around
+    assert_eq!(
+        Position::unpack(Position::new(5, 2048, 7).packed()),
+        Position::new(5, -2048, 7)
+    );
+    assert_eq!(
+        Position::unpack(Position::new(5, -2049, 7).packed()),
+        Position::new(5, 2047, 7)
+    );
+    Ok(())
+}
+
+#[test]
+async fn block_break_animation() -> anyhow::Result<()> {
+    use crate::net_io::Position;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutBlockBreakAnimation};
+    use std::time::Duration;
+
+    let location = Position::new(1, 2, 3);
+    let mut buffer = vec![];
+    PacketPlayOutBlockBreakAnimation::new(4, location, 5)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x06, 0x04];
+    expected.extend(((1i64 << 38) | (3 << 12) | 2).to_be_bytes());
+    expected.push(0x05);
+    assert_eq!(buffer, expected);
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player
+        .animate_block_break(4, location, Duration::from_millis(10))
+        .await?;
+    let stages = rx
+        .drain()
+        .map(|packet| match packet {
+            PacketPlayOut::PacketPlayOutBlockBreakAnimation(anim) => *anim.stage(),
+            other => panic!("Expected block break animation, got {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stages, (0..=10).collect::<Vec<u8>>());
+    Ok(())
+}
+
+#[test]
+async fn reads_progress_under_saturated_writes() -> anyhow::Result<()> {
+    use crate::cfg::ChannelScheduling;
+    use crate::network::client::{run_channels, InboundPacketChannel, OutgoingPacketChannel};
+    use crate::protocol::client::play::{PacketPlayIn, PacketPlayInKeepAlive};
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutCloseContainer};
+    use std::time::Duration;
+
+    for scheduling in [ChannelScheduling::Shared, ChannelScheduling::Split] {
+        let addr = "127.0.0.1:25565".parse()?;
+        let (client, server) = tokio::io::duplex(1024);
+        let (reader, _) = tokio::io::split(server);
+        let (inbound_tx, inbound_rx) = flume::bounded(32);
+        let (outgoing_tx, outgoing_rx) = flume::unbounded();
+        for _ in 0..100_000 {
+            outgoing_tx.send(PacketPlayOut::PacketPlayOutCloseContainer(
+                PacketPlayOutCloseContainer::new(0),
+            ))?;
+        }
+
+        let task = tokio::spawn(run_channels(
+            InboundPacketChannel::new(Box::new(reader), inbound_tx, addr, None),
+            OutgoingPacketChannel::new(Box::new(tokio::io::sink()), outgoing_rx, addr),
+            scheduling,
+        ));
+
+        let mut client = TestClient {
+            stream: client,
+            enc: PacketEncoder::new(),
+            dec: PacketDecoder::new(),
+        };
+        client.send(&PacketPlayInKeepAlive::new(42)).await?;
+
+        let received =
+            tokio::time::timeout(Duration::from_secs(1), inbound_rx.recv_async()).await??;
+        assert!(matches!(received, PacketPlayIn::PacketPlayInKeepAlive(_)));
+        assert!(!outgoing_tx.is_empty());
+        task.abort();
+    }
+    Ok(())
+}
+
+#[test]
+async fn no_bytes_lost_after_handshake() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::{InLogin, PacketLoginInStart};
+
+    let (mut connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+
+    // both packets arrive in a single read
+    let mut buffer = vec![];
+    client
+        .enc
+        .consume(
+            &mut buffer,
+            &PacketHandshakeIn::new(759, "localhost".to_string(), 25565, HandshakeState::Login),
+        )
+        .await?;
+    client
+        .enc
+        .consume(
+            &mut buffer,
+            &PacketLoginInStart::new("Notch".to_string(), None),
+        )
+        .await?;
+    client.stream.write_all(&buffer).await?;
+
+    assert_eq!(
+        connection.peek_handshake().await?.next_state(),
+        &HandshakeState::Login
+    );
+    match connection.read_packet::<InLogin>().await? {
+        InLogin::PacketLoginInStart(start) => assert_eq!(start.name(), "Notch"),
+        other => panic!("Expected login start, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn auth_digest_vectors() -> anyhow::Result<()> {
+    use crate::network::auth::mc_auth_digest;
+
+    assert_eq!(
+        mc_auth_digest("Notch", &[], &[]),
+        "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+    );
+    assert_eq!(
+        mc_auth_digest("jeb_", &[], &[]),
+        "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+    );
+    assert_eq!(
+        mc_auth_digest("simon", &[], &[]),
+        "88e16a1019277b15d58faf0541e11910eb756f6"
+    );
+    // inputs are hashed as one continuous stream
+    assert_eq!(
+        mc_auth_digest("No", b"tc", b"h"),
+        mc_auth_digest("Notch", &[], &[])
+    );
+    Ok(())
+}
+
+#[test]
+async fn pack_write_into_bytes_mut() -> anyhow::Result<()> {
+    use crate::net_io::{PacketWriteBuf, VarInt};
+    use crate::protocol::server::play::PacketPlayOutSetPassengers;
+    use bytes::BytesMut;
+
+    let packet = PacketPlayOutSetPassengers::new(10, vec![VarInt(20), VarInt(30)]);
+
+    let mut vec = vec![];
+    packet.pack_write(&mut vec, PROTO_VERSION).await?;
+
+    // the staging buffer is reused for the second packet
+    let mut bytes = BytesMut::with_capacity(64);
+    let mut staging = vec![];
+    packet
+        .pack_write_buf(&mut bytes, &mut staging, PROTO_VERSION)
+        .await?;
+    packet
+        .pack_write_buf(&mut bytes, &mut staging, PROTO_VERSION)
+        .await?;
+    assert_eq!(&bytes[..], &[&vec[..], &vec[..]].concat()[..]);
+
+    // the encoder frames packets into any buffer
+    let mut framed = vec![];
+    PacketEncoder::new().consume(&mut framed, &packet).await?;
+    let mut bytes = BytesMut::new();
+    PacketEncoder::new().consume(&mut bytes, &packet).await?;
+    assert_eq!(&bytes[..], &framed[..]);
+    Ok(())
+}
+
+#[test]
+async fn tab_list_header_footer() -> anyhow::Result<()> {
+    use crate::chat::Component;
+    use crate::network::player::PlayerHandle;
+
+    async fn expected(header: &Component, footer: &Component) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = vec![0x60];
+        header.pack_write(&mut buffer, PROTO_VERSION).await?;
+        footer.pack_write(&mut buffer, PROTO_VERSION).await?;
+        Ok(buffer)
+    }
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    let header = Component::text("Soulflame".to_string());
+    let footer = Component::text("play.example.com".to_string());
+    let empty = Component::text(String::new());
+
+    player.set_tab_list(header.clone(), footer.clone()).await?;
+    player.set_tab_list(header.clone(), empty.clone()).await?;
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, expected(&header, &footer).await?);
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, expected(&header, &empty).await?);
+    Ok(())
+}
+
+fn png_with_chunks(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    for (kind, data) in chunks {
+        png.extend((data.len() as u32).to_be_bytes());
+        png.extend(*kind);
+        png.extend(*data);
+        png.extend([0u8; 4]);
+    }
+    png
+}
+
+#[test]
+async fn animated_favicon() -> anyhow::Result<()> {
+    use crate::cfg::{build_favicon, is_animated_png};
+    use crate::DEFAULT_FAVICON;
+
+    let ihdr = [0u8; 13];
+    let still = png_with_chunks(&[(b"IHDR", &ihdr), (b"IDAT", &[0u8; 4]), (b"IEND", &[])]);
+    let animated = png_with_chunks(&[
+        (b"IHDR", &ihdr),
+        (b"acTL", &[0u8; 8]),
+        (b"IDAT", &[0u8; 4]),
+        (b"IEND", &[]),
+    ]);
+    assert!(!is_animated_png(&still));
+    assert!(is_animated_png(&animated));
+    assert!(!is_animated_png(b"not a png"));
+
+    let path = temp_path("animated_favicon.png");
+    tokio::fs::write(&path, &animated).await?;
+    let mut config = SoulflameConfiguration {
+        favicon: path,
+        ..Default::default()
+    };
+    assert!(RuntimeConfiguration::from_cfg(&config)
+        .await?
+        .favicon
+        .is_some());
+
+    // rejected animations fall back to the default favicon
+    config.reject_animated_favicon = true;
+    assert_eq!(
+        RuntimeConfiguration::from_cfg(&config).await?.favicon,
+        Some(build_favicon(DEFAULT_FAVICON))
+    );
+    tokio::fs::remove_file(&config.favicon).await?;
+    Ok(())
+}
+
+#[test]
+async fn damage_event_packet() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{
+        DamagePosition, PacketPlayOut, DAMAGE_EVENT_PROTOCOL_VERSION,
+    };
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_protocol_version(DAMAGE_EVENT_PROTOCOL_VERSION);
+
+    player.show_damage(5, 1, Some(7), None, None).await?;
+    let packet = rx.recv_async().await?;
+    match &packet {
+        PacketPlayOut::PacketPlayOutDamageEvent(damage) => {
+            assert_eq!(*damage.entity_id(), 5);
+            assert_eq!(*damage.source_type_id(), 1);
+            assert_eq!(*damage.source_cause_id(), 8);
+            assert_eq!(*damage.source_direct_id(), 0);
+            assert!(damage.position().is_none());
+        }
+        other => panic!("Expected damage event, got {:?}", other),
+    }
+    // no supported version has the packet
+    assert!(packet.pack_write(&mut vec![], PROTO_VERSION).await.is_err());
+
+    player
+        .show_damage(5, 1, None, None, Some(DamagePosition::new(1.0, 2.0, 3.0)))
+        .await?;
+    let mut buffer = vec![];
+    match rx.recv_async().await? {
+        PacketPlayOut::PacketPlayOutDamageEvent(damage) => {
+            damage
+                .position()
+                .pack_write(&mut buffer, DAMAGE_EVENT_PROTOCOL_VERSION)
+                .await?
+        }
+        other => panic!("Expected damage event, got {:?}", other),
+    }
+    let mut expected = vec![0x01];
+    for v in [1.0f64, 2.0, 3.0] {
+        expected.extend(v.to_be_bytes());
+    }
+    assert_eq!(buffer, expected);
+
+    // older clients only play the hurt animation
+    player.set_protocol_version(PROTO_VERSION);
+    player.show_damage(5, 1, None, None, None).await?;
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x18, 0x00, 0x00, 0x00, 0x05, 0x02]);
+    Ok(())
+}
+
+#[test]
+async fn entity_effect_packets() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::EffectFlags;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+
+    player
+        .apply_effect(
+            3,
+            1,
+            2,
+            600,
+            EffectFlags::SHOW_PARTICLES | EffectFlags::SHOW_ICON,
+        )
+        .await?;
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x66, 0x03, 0x01, 0x02, 0xD8, 0x04, 0x06, 0x00]);
+
+    player.clear_effect(3, 1).await?;
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x39, 0x03, 0x01]);
+
+    assert!(player
+        .apply_effect(3, 1, 200, 600, EffectFlags::empty())
+        .await
+        .is_err());
+    assert!(player
+        .apply_effect(3, 1, 0, 0, EffectFlags::empty())
+        .await
+        .is_err());
+    assert!(rx.is_empty());
+    Ok(())
+}
+
+crate::action_packet! {
+    TestAction: VarInt {
+        Rename(0) {
+            name: String
+        },
+        Move(1) {
+            id: VarInt,
+            x: f64
+        }
+    }
+}
+
+#[test]
+async fn action_packet_round_trip() -> anyhow::Result<()> {
+    let mut buffer = vec![];
+    TestAction::Rename {
+        name: "hi".to_string(),
+    }
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+    assert_eq!(buffer, vec![0x00, 0x02, b'h', b'i']);
+    match TestAction::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await? {
+        TestAction::Rename { name } => assert_eq!(name, "hi"),
+        other => panic!("Expected rename action, got {:?}", other),
+    }
+
+    let mut buffer = vec![];
+    TestAction::Move { id: 300, x: 1.5 }
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x01, 0xAC, 0x02];
+    expected.extend(1.5f64.to_be_bytes());
+    assert_eq!(buffer, expected);
+    match TestAction::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await? {
+        TestAction::Move { id, x } => {
+            assert_eq!(id, 300);
+            assert_eq!(x, 1.5);
+        }
+        other => panic!("Expected move action, got {:?}", other),
+    }
+
+    assert!(
+        TestAction::pack_read(&mut Cursor::new(&[0x02][..]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn tcp_keepalive_enabled() -> anyhow::Result<()> {
+    use crate::cfg::TcpKeepaliveConfig;
+    use crate::network::configure_keepalive;
+    use socket2::SockRef;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let _client = TcpStream::connect(listener.local_addr()?).await?;
+    let (stream, _) = listener.accept().await?;
+
+    configure_keepalive(&stream, &TcpKeepaliveConfig::default())?;
+    assert!(SockRef::from(&stream).keepalive()?);
+
+    let disabled = TcpKeepaliveConfig {
+        enabled: false,
+        ..Default::default()
+    };
+    configure_keepalive(&stream, &disabled)?;
+    assert!(!SockRef::from(&stream).keepalive()?);
+    Ok(())
+}
+
+#[test]
+async fn empty_recipes_packets() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.send_empty_recipes().await?;
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x67, 0x00]);
+
+    let mut buffer = vec![];
+    rx.recv_async()
+        .await?
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x37, 0x00];
+    expected.extend([0x00; 8]);
+    expected.extend([0x00, 0x00]);
+    assert_eq!(buffer, expected);
+    Ok(())
+}
+
+#[test]
+async fn update_tags_packet() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOutUpdateTags, Tag, TagRegistry};
+    use crate::util::Identifier;
+
+    let mut buffer = vec![];
+    PacketPlayOutUpdateTags::new(vec![])
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x68, 0x00]);
+
+    let mut buffer = vec![];
+    PacketPlayOutUpdateTags::new(vec![TagRegistry::new(
+        Identifier::minecraft("block")?,
+        vec![Tag::new(
+            Identifier::minecraft("logs")?,
+            vec![VarInt(38), VarInt(300)],
+        )],
+    )])
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+
+    let mut expected = vec![0x68, 0x01];
+    Identifier::minecraft("block")?
+        .pack_write(&mut expected, PROTO_VERSION)
+        .await?;
+    expected.push(0x01);
+    Identifier::minecraft("logs")?
+        .pack_write(&mut expected, PROTO_VERSION)
+        .await?;
+    expected.extend([0x02, 38, 0xAC, 0x02]);
+    assert_eq!(buffer, expected);
+    Ok(())
+}
+
+/// Logger keeping every record, so tests can inspect what their own thread logged.
+struct CapturingLogger {
+    records: std::sync::Mutex<Vec<(std::thread::ThreadId, log::Level, String)>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.records.lock().unwrap().push((
+            std::thread::current().id(),
+            record.level(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+lazy_static::lazy_static! {
+    static ref CAPTURED_LOGS: CapturingLogger = CapturingLogger {
+        records: std::sync::Mutex::new(vec![]),
+    };
+}
+
+/// Installs the capturing logger, returning a function listing this thread's logs at `level` or above.
+fn capture_logs() -> impl Fn(log::Level) -> Vec<String> {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&*CAPTURED_LOGS).expect("Logger should not be installed yet");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+
+    let thread = std::thread::current().id();
+    CAPTURED_LOGS
+        .records
+        .lock()
+        .unwrap()
+        .retain(|(id, _, _)| *id != thread);
+
+    move |level| {
+        CAPTURED_LOGS
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, lvl, _)| *id == thread && *lvl <= level)
+            .map(|(_, _, message)| message.clone())
+            .collect()
+    }
+}
+
+#[test]
+async fn quiet_status_pings() -> anyhow::Result<()> {
+    use crate::protocol::server::status::OutStatus;
+
+    let logs = capture_logs();
+
+    // a scanner hanging up right after the handshake
+    let (connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+    client.stream.write_all(&STATUS_STREAM[..17]).await?;
+    drop(client);
+    connection.handle().await?;
+    assert!(!logs(log::Level::Info).is_empty());
+
+    let config = SoulflameConfiguration {
+        log_connections: false,
+        ..Default::default()
+    };
+    let logs = capture_logs();
+
+    let (connection, mut client) = test_connection(config.clone()).await;
+    client.stream.write_all(&STATUS_STREAM[..17]).await?;
+    drop(client);
+    connection.handle().await?;
+
+    let (connection, mut client) = test_connection(config).await;
+    client.stream.write_all(&STATUS_STREAM).await?;
+    let handle = tokio::spawn(connection.handle());
+    client.read::<OutStatus>().await?;
+    client.read::<OutStatus>().await?;
+    drop(client);
+    handle.await??;
+
+    assert_eq!(logs(log::Level::Info), Vec::<String>::new());
+    Ok(())
+}
+
+#[test]
+async fn swing_arm_animation() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::network::handler::{dispatch, AnimationHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::network::registry::PlayerRegistry;
+    use crate::protocol::client::play::{Hand, PacketPlayIn};
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutEntityAnimation};
+    use crate::world::block::Location;
+    use uuid::Uuid;
+
+    let packet = PacketPlayIn::pack_read(&mut Cursor::new(&[0x2E, 0x01]), PROTO_VERSION).await?;
+    let swing = match packet {
+        PacketPlayIn::PacketPlayInSwingArm(swing) => swing,
+        other => panic!("Expected swing arm packet, got {:?}", other),
+    };
+    assert_eq!(*swing.hand(), Hand::OffHand);
+
+    let mut buffer = vec![];
+    PacketPlayOutEntityAnimation::swing(300, Hand::OffHand)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    assert_eq!(buffer, vec![0x03, 0xAC, 0x02, 0x03]);
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (swinging_tx, swinging_rx) = flume::unbounded();
+    let swinging = PlayerHandle::new(addr, swinging_tx);
+    let (near_tx, near_rx) = flume::unbounded();
+    let near = PlayerHandle::new(addr, near_tx);
+    near.set_location(Location::simple(10.0f32, 0.0, 0.0));
+    let (far_tx, far_rx) = flume::unbounded();
+    let far = PlayerHandle::new(addr, far_tx);
+    far.set_location(Location::simple(1000.0f32, 0.0, 0.0));
+
+    let registry = PlayerRegistry::new();
+    for (id, player) in [&swinging, &near, &far].into_iter().enumerate() {
+        registry.register(
+            Uuid::from_u128(id as u128),
+            player.clone(),
+            DuplicateLogin::Deny,
+        )?;
+    }
+
+    dispatch(
+        &AnimationHandler::new(registry),
+        &swinging,
+        PacketPlayIn::PacketPlayInSwingArm(swing),
+    )
+    .await?;
+    match near_rx.try_recv()? {
+        PacketPlayOut::PacketPlayOutEntityAnimation(animation) => {
+            assert_eq!(*animation.entity_id(), swinging.entity_id());
+            assert_eq!(*animation.animation(), 3);
+        }
+        other => panic!("Expected animation packet, got {:?}", other),
+    }
+    assert!(swinging_rx.is_empty());
+    assert!(far_rx.is_empty());
+    Ok(())
+}
+
+#[test]
+async fn spawn_player_packet() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::net_io::Angle;
+    use crate::network::player::PlayerHandle;
+    use crate::network::registry::PlayerRegistry;
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutSpawnPlayer};
+    use crate::world::block::Location;
+    use uuid::Uuid;
+
+    let uuid = Uuid::from_u128(0x0102);
+    let mut buffer = vec![];
+    PacketPlayOutSpawnPlayer::new(
+        5,
+        uuid,
+        1.5,
+        64.0,
+        -2.0,
+        Angle::from_degrees(90.0),
+        Angle(0),
+    )
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+
+    let mut expected = vec![0x02, 0x05];
+    expected.extend(0x0102u128.to_be_bytes());
+    expected.extend(1.5f64.to_be_bytes());
+    expected.extend(64.0f64.to_be_bytes());
+    expected.extend((-2.0f64).to_be_bytes());
+    expected.extend([64, 0]);
+    assert_eq!(buffer, expected);
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (joining_tx, joining_rx) = flume::unbounded();
+    let joining = PlayerHandle::new(addr, joining_tx);
+    joining.set_location(Location::simple(1.5f32, 64.0, -2.0));
+    let (online_tx, online_rx) = flume::unbounded();
+    let online = PlayerHandle::new(addr, online_tx);
+    online.set_protocol_version(PROTO_VERSION);
+
+    let registry = PlayerRegistry::new();
+    registry.register(Uuid::from_u128(1), online, DuplicateLogin::Deny)?;
+    registry.register(uuid, joining.clone(), DuplicateLogin::Deny)?;
+    assert_ne!(joining.entity_id(), 0);
+
+    registry.spawn_for_others(&uuid).await?;
+    match online_rx.try_recv()? {
+        PacketPlayOut::PacketPlayOutSpawnPlayer(spawn) => {
+            assert_eq!(*spawn.entity_id(), joining.entity_id());
+            assert_eq!(*spawn.uuid(), uuid);
+        }
+        other => panic!("Expected spawn player packet, got {:?}", other),
+    }
+    assert!(joining_rx.is_empty());
+    Ok(())
+}
+
+#[test]
+async fn bungeecord_forwarding() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use uuid::Uuid;
+
+    let address = "play.example.com\0203.0.113.7\0069a79f444e94726a5befca90e38aaf5\0\
+        [{\"name\":\"textures\",\"value\":\"abc\",\"signature\":\"def\"}]";
+    let handshake = PacketHandshakeIn::new(759, address.into(), 25565, HandshakeState::Login);
+    let forward = handshake.bungee_forward()?;
+    assert_eq!(forward.host, "play.example.com");
+    assert_eq!(forward.address, "203.0.113.7".parse::<std::net::IpAddr>()?);
+    assert_eq!(
+        forward.uuid,
+        Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5")?
+    );
+    assert_eq!(forward.properties.len(), 1);
+    assert_eq!(forward.properties[0].name(), "textures");
+    assert_eq!(forward.properties[0].signature().as_deref(), Some("def"));
+
+    let without_properties = PacketHandshakeIn::new(
+        759,
+        "localhost\0127.0.0.1\0069a79f444e94726a5befca90e38aaf5".into(),
+        25565,
+        HandshakeState::Login,
+    );
+    assert!(without_properties.bungee_forward()?.properties.is_empty());
+
+    for malformed in ["localhost", "localhost\0127.0.0.1", "localhost\0nope\0nope"] {
+        let handshake = PacketHandshakeIn::new(759, malformed.into(), 25565, HandshakeState::Login);
+        assert!(handshake.bungee_forward().is_err());
+    }
+    Ok(())
+}
+
+#[test]
+async fn queued_packets_precede_disconnect() -> anyhow::Result<()> {
+    use crate::chat::Component;
+    use crate::network::client::OutgoingPacketChannel;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{
+        PacketPlayOut, PacketPlayOutCloseContainer, PacketPlayOutDisconnect,
+    };
+    use std::time::Duration;
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let (_reader, writer) = tokio::io::split(server);
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new(addr, tx);
+
+    for window in 1..=3 {
+        player
+            .send_packet(PacketPlayOutCloseContainer::new(window))
+            .await?;
+    }
+    player
+        .kick(Component::text("Server closed".to_string()))
+        .await?;
+    // never reaches the client, the connection is closed after the disconnect
+    player
+        .send_packet(PacketPlayOutCloseContainer::new(4))
+        .await?;
+
+    let task = tokio::spawn(OutgoingPacketChannel::new(Box::new(writer), rx, addr).start());
+    let mut written = vec![];
+    tokio::time::timeout(Duration::from_secs(1), client.read_to_end(&mut written)).await??;
+    task.await??;
+
+    let names = decode_stream::<PacketPlayOut>(&written)
+        .await?
+        .iter()
+        .map(|p| p.packet_name())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "PacketPlayOutCloseContainer",
+            "PacketPlayOutCloseContainer",
+            "PacketPlayOutCloseContainer",
+            "PacketPlayOutDisconnect"
+        ]
+    );
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let (_reader, writer) = tokio::io::split(server);
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new(addr, tx);
+    player
+        .send_packet(PacketPlayOutCloseContainer::new(1))
+        .await?;
+
+    let mut outgoing = OutgoingPacketChannel::new(Box::new(writer), rx, addr);
+    outgoing.drain(Duration::from_secs(1)).await?;
+    outgoing.close().await?;
+    let mut written = vec![];
+    client.read_to_end(&mut written).await?;
+    assert_eq!(decode_stream::<PacketPlayOut>(&written).await?.len(), 1);
+
+    // past the timeout nothing more is drained, and the disconnect is written as a whole frame
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let (_reader, writer) = tokio::io::split(server);
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new(addr, tx);
+    for window in 1..=3 {
+        player
+            .send_packet(PacketPlayOutCloseContainer::new(window))
+            .await?;
+    }
+
+    let mut outgoing = OutgoingPacketChannel::new(Box::new(writer), rx, addr);
+    outgoing.drain(Duration::ZERO).await?;
+    outgoing
+        .send_packet(PacketPlayOutDisconnect::new(Component::text(
+            "Server closed".to_string(),
+        )))
+        .await?;
+    outgoing.close().await?;
+    let mut written = vec![];
+    client.read_to_end(&mut written).await?;
+    assert!(matches!(
+        decode_stream::<PacketPlayOut>(&written).await?[..],
+        [PacketPlayOut::PacketPlayOutDisconnect(_)]
+    ));
+    Ok(())
+}
+
+#[test]
+async fn compressed_round_trip() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutSystemChat};
+
+    let mut enc = PacketEncoder::new();
+    enc.set_compression(64);
+    let mut dec = PacketDecoder::new();
+    dec.set_compression(64);
+
+    // one packet below the threshold, sent with a data length of 0, and one above it
+    let short = PacketPlayOutSystemChat::new(crate::chat::Component::text("hi".into()), 0);
+    let long = PacketPlayOutSystemChat::new(crate::chat::Component::text("a".repeat(500)), 0);
+
+    let mut buffer = vec![];
+    enc.consume(&mut buffer, &short).await?;
+    let short_len = buffer.len();
+    enc.consume(&mut buffer, &long).await?;
+
+    // packet length, then a single data length of 0, then the uncompressed body
+    let mut uncompressed = vec![];
+    short.pack_write(&mut uncompressed, PROTO_VERSION).await?;
+    assert_eq!(buffer[0] as usize, uncompressed.len() + 1);
+    assert_eq!(buffer[1], 0x00);
+    assert_eq!(&buffer[2..short_len], &uncompressed[..]);
+    assert!(buffer.len() - short_len < 500);
+
+    dec.digest(&buffer);
+    let packets = drain::<PacketPlayOut>(&mut dec).await?;
+    assert_eq!(packets.len(), 2);
+    for (packet, expected) in packets.iter().zip([&short, &long]) {
+        match packet {
+            PacketPlayOut::PacketPlayOutSystemChat(chat) => {
+                let mut written = vec![];
+                chat.pack_write(&mut written, PROTO_VERSION).await?;
+                let mut original = vec![];
+                expected.pack_write(&mut original, PROTO_VERSION).await?;
+                assert_eq!(written, original);
+            }
+            other => panic!("Expected system chat packet, got {:?}", other),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+async fn packet_hex_dump() -> anyhow::Result<()> {
+    use crate::net_io::hexdump::{annotated_hex_dump, hex_dump};
+
+    let mut enc = PacketEncoder::new();
+    let mut framed = vec![];
+    enc.consume(
+        &mut framed,
+        &crate::protocol::server::play::PacketPlayOutCloseContainer::new(1),
+    )
+    .await?;
+    assert_eq!(framed, vec![0x02, 0x10, 0x01]);
+
+    let dump = annotated_hex_dump(&framed, true);
+    assert!(dump.starts_with("length prefix: 2 (1 byte, 2 bytes follow)\n"));
+    assert_eq!(
+        dump.lines().nth(1),
+        Some(format!("00000000  02 10 01 {} |...|", " ".repeat(13 * 3)).as_str())
+    );
+
+    let dump = hex_dump(b"soulflame packet dump");
+    assert_eq!(dump.lines().count(), 2);
+    assert!(dump
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with("00000010  20 64 75 6d 70"));
+    assert!(dump.contains("|soulflame packet|"));
+
+    assert!(annotated_hex_dump(&[0xFF; 6], true).starts_with("length prefix: invalid\n"));
+    Ok(())
+}
+
+#[test]
+async fn encrypted_stream_in_splits() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutCloseContainer};
+
+    let key = [7u8; 16];
+    let mut enc = PacketEncoder::new();
+    enc.set_encryption(key);
+    let mut stream = vec![];
+    for window in 1..=20 {
+        enc.consume(&mut stream, &PacketPlayOutCloseContainer::new(window))
+            .await?;
+    }
+
+    for split in [1, 2, 5, 7, 13, stream.len()] {
+        let mut dec = PacketDecoder::new();
+        dec.set_encryption(key);
+        let mut windows = vec![];
+        for chunk in stream.chunks(split) {
+            dec.digest(chunk);
+            for packet in drain::<PacketPlayOut>(&mut dec).await? {
+                match packet {
+                    PacketPlayOut::PacketPlayOutCloseContainer(close) => {
+                        windows.push(*close.window_id())
+                    }
+                    other => panic!("Expected close container packet, got {:?}", other),
+                }
+            }
+        }
+        assert_eq!(windows, (1..=20).collect::<Vec<u8>>());
+    }
+    Ok(())
+}
+
+#[test]
+async fn handshake_address_length() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+
+    let handshake =
+        |address: String| PacketHandshakeIn::new(759, address, 25565, HandshakeState::Login);
+    let vanilla = SoulflameConfiguration::default();
+    let forwarding = SoulflameConfiguration {
+        bungeecord_forwarding: true,
+        ..Default::default()
+    };
+
+    let normal = handshake("play.example.com".into());
+    assert!(normal
+        .validate_address(vanilla.max_hostname_length())
+        .is_ok());
+
+    let forwarded = handshake(format!(
+        "play.example.com\01.2.3.4\0069a79f444e94726a5befca90e38aaf5\0[{{\"name\":\"textures\",\"value\":\"{}\"}}]",
+        "a".repeat(1024)
+    ));
+    assert!(forwarded
+        .validate_address(vanilla.max_hostname_length())
+        .is_err());
+    assert!(forwarded
+        .validate_address(forwarding.max_hostname_length())
+        .is_ok());
+
+    let absurd = handshake("a".repeat(20_000));
+    assert!(absurd
+        .validate_address(forwarding.max_hostname_length())
+        .is_err());
+
+    let explicit = SoulflameConfiguration {
+        max_hostname_length: Some(8),
+        ..Default::default()
+    };
+    assert!(normal
+        .validate_address(explicit.max_hostname_length())
+        .is_err());
+    Ok(())
+}
+
+#[test]
+async fn encrypted_round_trip() -> anyhow::Result<()> {
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutCloseContainer};
+
+    let key = [42u8; 16];
+    let mut enc = PacketEncoder::new();
+    enc.set_encryption(key);
+    let mut dec = PacketDecoder::new();
+    dec.set_encryption(key);
+
+    // the first packet stays in the buffer, it must not be encrypted a second time
+    let mut buffer = vec![];
+    enc.consume(&mut buffer, &PacketPlayOutCloseContainer::new(1))
+        .await?;
+    for window in 2..=5 {
+        enc.consume(&mut buffer, &PacketPlayOutCloseContainer::new(window))
+            .await?;
+    }
+    assert_ne!(&buffer[..3], &[0x02, 0x10, 0x01]);
+
+    dec.digest(&buffer);
+    let windows = drain::<PacketPlayOut>(&mut dec)
+        .await?
+        .into_iter()
+        .map(|packet| match packet {
+            PacketPlayOut::PacketPlayOutCloseContainer(close) => *close.window_id(),
+            other => panic!("Expected close container packet, got {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(windows, vec![1, 2, 3, 4, 5]);
+    Ok(())
+}
+
+#[test]
+async fn identifier_validation() -> anyhow::Result<()> {
+    use crate::util::Identifier;
+
+    assert!(Identifier::new("UPPER", "stone").is_err());
+    assert!(Identifier::new("has space", "stone").is_err());
+    assert!(Identifier::new("bad space!", "x").is_err());
+    assert!(Identifier::new("minecraft", "stone!").is_err());
+    assert!(Identifier::minecraft("has space").is_err());
+    assert!(Identifier::soulflame("").is_err());
+
+    let stone = Identifier::new("minecraft", "stone")?;
+    assert_eq!(stone.to_string(), "minecraft:stone");
+    assert_eq!(Identifier::soulflame("foo/bar/baz")?.path(), "foo/bar/baz");
+    Ok(())
+}
+
+#[test]
+async fn server_links_packet() -> anyhow::Result<()> {
+    use crate::cfg::ServerLinkConfig;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{
+        PacketPlayOut, PacketPlayOutServerLinks, ServerLink, ServerLinkLabel,
+        SERVER_LINKS_PROTOCOL_VERSION,
+    };
+
+    let config = SoulflameConfiguration {
+        server_links: vec![
+            ServerLinkConfig {
+                label: "bug_report".into(),
+                url: "https://a.io".into(),
+            },
+            ServerLinkConfig {
+                label: "Map".into(),
+                url: "https://b.io".into(),
+            },
+        ],
+        ..Default::default()
+    };
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_protocol_version(PROTO_VERSION);
+    assert!(!player.send_server_links(config.server_links()).await?);
+    assert!(rx.is_empty());
+
+    player.set_protocol_version(SERVER_LINKS_PROTOCOL_VERSION);
+    assert!(player.send_server_links(config.server_links()).await?);
+    let links = match rx.try_recv()? {
+        PacketPlayOut::PacketPlayOutServerLinks(packet) => packet.links().clone(),
+        other => panic!("Expected server links, got {:?}", other),
+    };
+    let mut buffer = vec![];
+    links
+        .pack_write(&mut buffer, SERVER_LINKS_PROTOCOL_VERSION)
+        .await?;
+
+    let mut expected = vec![0x02];
+    expected.extend([0x01, 0x00, 12]);
+    expected.extend(b"https://a.io");
+    expected.extend([0x00, 0x08, 0x00, 0x03]);
+    expected.extend(b"Map");
+    expected.push(12);
+    expected.extend(b"https://b.io");
+    assert_eq!(buffer, expected);
+
+    let read =
+        Vec::<ServerLink>::pack_read(&mut Cursor::new(&buffer[..]), SERVER_LINKS_PROTOCOL_VERSION)
+            .await?;
+    assert_eq!(read.len(), 2);
+    // no supported version has the packet
+    assert!(PacketPlayOutServerLinks::new(links)
+        .pack_write(&mut vec![], PROTO_VERSION)
+        .await
+        .is_err());
+
+    // the length of a plain text label is written as an unsigned short
+    let label = ServerLinkLabel::Custom("a".repeat(u16::MAX as usize + 1));
+    assert!(label
+        .pack_write(&mut vec![], SERVER_LINKS_PROTOCOL_VERSION)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[test]
+async fn identifier_character_classes() -> anyhow::Result<()> {
+    use crate::util::Identifier;
+
+    // everything between '.' and '_' used to be accepted as a range
+    for namespace in ["A", "UPPER", "a@b", "a:b", "a/b", "a=b"] {
+        assert!(
+            Identifier::new(namespace, "stone").is_err(),
+            "{}",
+            namespace
+        );
+    }
+    assert!(Identifier::minecraft("Stone").is_err());
+    // only ASCII digits, not every Unicode digit
+    assert!(Identifier::new("mod٣", "stone").is_err());
+    assert!(Identifier::minecraft("stone_٣").is_err());
+
+    assert!(Identifier::new("my-mod_2.0", "stone").is_ok());
+    assert!(Identifier::new("minecraft", "block/oak-log_0.json").is_ok());
+    Ok(())
+}
+
+#[test]
+async fn login_username_validation() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+
+    async fn login_reason(name: &str) -> anyhow::Result<String> {
+        let (connection, mut client) = test_connection(SoulflameConfiguration::default()).await;
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        client
+            .send(&PacketLoginInStart::new(name.to_string(), None))
+            .await?;
+        connection.handle().await?;
+        match client.read::<OutLogin>().await? {
+            OutLogin::PacketLoginOutDisconnect(disconnect) => {
+                Ok(disconnect.reason().clone().flatten())
+            }
+            other => panic!("Expected disconnect, got {:?}", other),
+        }
+    }
+
+    for name in [
+        "",
+        "   ",
+        "ab",
+        "seventeen_chars__",
+        "bad name",
+        "name!",
+        "näme",
+    ] {
+        assert!(
+            login_reason(name).await?.contains("Usernames must be"),
+            "{:?}",
+            name
+        );
+        let start = PacketLoginInStart::new(name.to_string(), None);
+        assert!(start.validate_name().is_err());
+    }
+
+    let valid = PacketLoginInStart::new("Notch_2009".to_string(), None);
+    assert!(valid.validate_name().is_ok());
+    assert!(!login_reason("Notch_2009")
+        .await?
+        .contains("Usernames must be"));
+    Ok(())
+}
+
+#[test]
+async fn identifier_parse() -> anyhow::Result<()> {
+    use crate::util::Identifier;
+
+    let stone = Identifier::parse("minecraft:stone")?;
+    assert_eq!(stone.namespace(), "minecraft");
+    assert_eq!(stone.path(), "stone");
+    assert_eq!(stone, Identifier::minecraft("stone")?);
+
+    let nested = Identifier::parse("soulflame:block/oak_log")?;
+    assert_eq!(nested.namespace(), "soulflame");
+    assert_eq!(nested.path(), "block/oak_log");
+
+    assert!(Identifier::parse("stone").is_err());
+    assert!(Identifier::parse("minecraft:stone extra").is_err());
+    assert!(Identifier::parse("a:b:c").is_err());
+    Ok(())
+}
+
+#[test]
+async fn offline_login() -> anyhow::Result<()> {
+    use crate::network::auth::offline_uuid;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+    use uuid::Uuid;
+
+    assert_eq!(
+        offline_uuid("Notch"),
+        Uuid::parse_str("b50ad385-829d-3141-a216-7e7d7539ba7f")?
+    );
+
+    async fn login(config: SoulflameConfiguration) -> anyhow::Result<OutLogin> {
+        let state = SharedState::new(&config, &test_runtime());
+        let players = state.players.clone();
+        let (connection, mut client) = test_connection_with(config, state, test_runtime()).await;
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        client
+            .send(&PacketLoginInStart::new("Notch".to_string(), None))
+            .await?;
+        let task = tokio::spawn(connection.handle());
+        let packet = client.read::<OutLogin>().await?;
+        if matches!(packet, OutLogin::PacketLoginOutSuccess(_)) {
+            client.read_join().await?;
+        }
+
+        // leaving the play state frees the player slot again
+        drop(client);
+        task.await??;
+        assert_eq!(players.get(), 0);
+        Ok(packet)
+    }
+
+    let offline = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    match login(offline.clone()).await? {
+        OutLogin::PacketLoginOutSuccess(success) => {
+            assert_eq!(*success.player_uuid(), offline_uuid("Notch"));
+            assert_eq!(success.username(), "Notch");
+            assert!(success.properties().is_empty());
+        }
+        other => panic!("Expected login success, got {:?}", other),
+    }
+
+    let full = SoulflameConfiguration {
+        max_players: 0,
+        ..offline
+    };
+    assert!(matches!(
+        login(full).await?,
+        OutLogin::PacketLoginOutDisconnect(_)
+    ));
+    assert!(matches!(
+        login(SoulflameConfiguration::default()).await?,
+        OutLogin::PacketLoginOutDisconnect(_)
+    ));
+    Ok(())
+}
+
+#[test]
+async fn dropped_receiver_tears_down_connection() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::client::play::PacketPlayInKeepAlive;
+    use crate::protocol::server::login::OutLogin;
+    use std::time::Duration;
+
+    for idle in [false, true] {
+        let config = SoulflameConfiguration {
+            online_mode: false,
+            compression_threshold: -1,
+            ..Default::default()
+        };
+        let state = SharedState::new(&config, &test_runtime());
+        let players = state.players.clone();
+        let (mut connection, mut client) =
+            test_connection_with(config, state, test_runtime()).await;
+
+        let packets = connection
+            .take_packets()
+            .expect("Packets should not be taken yet");
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        client
+            .send(&PacketLoginInStart::new("Notch".to_string(), None))
+            .await?;
+        let task = tokio::spawn(connection.handle());
+        assert!(matches!(
+            client.read::<OutLogin>().await?,
+            OutLogin::PacketLoginOutSuccess(_)
+        ));
+        assert_eq!(players.get(), 1);
+
+        drop(packets);
+        // an idle connection notices the dropped receiver without waiting for the client
+        if !idle {
+            client.send(&PacketPlayInKeepAlive::new(1)).await?;
+        }
+
+        assert!(tokio::time::timeout(Duration::from_secs(1), task)
+            .await??
+            .is_err());
+        assert_eq!(players.get(), 0);
+        let mut rest = vec![];
+        tokio::time::timeout(Duration::from_secs(1), client.stream.read_to_end(&mut rest))
+            .await??;
+    }
+    Ok(())
+}
+
+#[test]
+async fn join_sequence() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::play::{GameMode, PacketPlayOut};
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        default_gamemode: GameMode::Creative,
+        ..Default::default()
+    };
+    let (connection, mut client) = test_connection(config).await;
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+
+    let packets = client.read_join().await?;
+    match &packets[0] {
+        PacketPlayOut::PacketPlayOutLogin(login) => {
+            assert_eq!(*login.gamemode(), GameMode::Creative);
+            assert_eq!(login.dimension_name().to_string(), "minecraft:overworld");
+        }
+        other => panic!("Expected join game, got {:?}", other),
+    }
+    assert!(matches!(
+        packets[1],
+        PacketPlayOut::PacketPlayOutUpdateRecipes(_)
+    ));
+    match &packets[3] {
+        PacketPlayOut::PacketPlayOutUpdateTags(tags) => assert!(tags.registries().is_empty()),
+        other => panic!("Expected tags, got {:?}", other),
+    }
+    assert_eq!(packets.len(), 4);
+
+    drop(client);
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn unsupported_protocol_login() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::play::SERVER_LINKS_PROTOCOL_VERSION;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    for (version, reason) in [
+        (758, "Outdated client"),
+        (SERVER_LINKS_PROTOCOL_VERSION, "Outdated server"),
+    ] {
+        let state = SharedState::new(&config, &test_runtime());
+        let players = state.players.clone();
+        let (connection, mut client) =
+            test_connection_with(config.clone(), state, test_runtime()).await;
+        client
+            .send(&PacketHandshakeIn::new(
+                version as i32,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        let task = tokio::spawn(connection.handle());
+
+        // refused before the login start is read
+        match client.read::<OutLogin>().await? {
+            OutLogin::PacketLoginOutDisconnect(disconnect) => {
+                assert!(disconnect.reason().clone().flatten().contains(reason))
+            }
+            other => panic!("Expected disconnect, got {:?}", other),
+        }
+        task.await??;
+        assert_eq!(players.get(), 0);
+    }
+    Ok(())
+}
+
+#[test]
+async fn failed_login_releases_slot() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    let state = SharedState::new(&config, &test_runtime());
+    let (players, registry) = (state.players.clone(), state.registry.clone());
+    let (connection, mut client) = test_connection_with(config, state, test_runtime()).await;
+
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    // the player is admitted, but sending the login success fails
+    drop(client);
+
+    assert!(connection.handle().await.is_err());
+    assert_eq!(players.get(), 0);
+    assert!(registry.is_empty());
+    Ok(())
+}
+
+#[test]
+async fn verify_token_layout() -> anyhow::Result<()> {
+    use crate::protocol::client::login::VerifyToken;
+
+    let encrypted = VerifyToken::Encrypted(vec![5, 6]);
+    let mut buffer = Vec::new();
+    encrypted.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, [1, 2, 5, 6]);
+    let got = VerifyToken::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(got, encrypted);
+
+    // without a verify token the salt and signature follow as plain fields
+    let signed = VerifyToken::Signed {
+        salt: 1,
+        signature: vec![7, 8],
+    };
+    let mut buffer = Vec::new();
+    signed.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, [0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 7, 8]);
+    let got = VerifyToken::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(got, signed);
+    Ok(())
+}
+
+#[test]
+async fn online_mode_encryption_exchange() -> anyhow::Result<()> {
+    use crate::network::auth::ServerKeys;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::{PacketLoginInEncryptionResponse, PacketLoginInStart};
+    use crate::protocol::client::login::{PlayerPublicKey, VerifyToken};
+    use crate::protocol::server::login::OutLogin;
+    use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+    use rsa::{Hash, PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    let keys = Arc::new(ServerKeys::generate()?);
+    let secret = [9u8; 16];
+    // chat signing key of the client, which signs the verify token instead of encrypting it
+    let chat_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+    let player_key = PlayerPublicKey::new(
+        0,
+        RsaPublicKey::from(&chat_key)
+            .to_public_key_der()?
+            .as_ref()
+            .to_vec(),
+        Vec::new(),
+    );
+
+    for (signed, tamper) in [(false, true), (false, false), (true, true), (true, false)] {
+        let config = SoulflameConfiguration::default();
+        let runtime = RuntimeConfiguration {
+            keys: Some(keys.clone()),
+            ..test_runtime()
+        };
+        let state = SharedState::new(&config, &runtime);
+        let (connection, mut client) = test_connection_with(config, state, runtime).await;
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        client
+            .send(&PacketLoginInStart::new(
+                "Notch".to_string(),
+                signed.then(|| player_key.clone()),
+            ))
+            .await?;
+        let task = tokio::spawn(connection.handle());
+
+        let request = match client.read::<OutLogin>().await? {
+            OutLogin::PacketLoginOutEncryptionRequest(request) => request,
+            other => panic!("Expected encryption request, got {:?}", other),
+        };
+        assert_eq!(request.public_key(), &keys.public_der().to_vec());
+        assert_eq!(request.verify_token().len(), 4);
+
+        let public = RsaPublicKey::from_public_key_der(request.public_key())?;
+        let mut token = request.verify_token().clone();
+        if tamper {
+            token[0] ^= 0xFF;
+        }
+        let mut rng = rand::thread_rng();
+        let encrypted_secret =
+            public.encrypt(&mut rng, PaddingScheme::new_pkcs1v15_encrypt(), &secret)?;
+        let verification = if signed {
+            let salt = 0x1234_5678_i64;
+            let mut hasher = Sha256::new();
+            hasher.update(&token);
+            hasher.update(salt.to_be_bytes());
+            let signature = chat_key.sign(
+                PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+                &hasher.finalize(),
+            )?;
+            VerifyToken::Signed { salt, signature }
+        } else {
+            VerifyToken::Encrypted(public.encrypt(
+                &mut rng,
+                PaddingScheme::new_pkcs1v15_encrypt(),
+                &token,
+            )?)
+        };
+        client
+            .send(&PacketLoginInEncryptionResponse::new(
+                encrypted_secret,
+                verification,
+            ))
+            .await?;
+        task.await??;
+
+        // the disconnect is already encrypted with the shared secret, unless the exchange failed
+        if !tamper {
+            client.dec.set_encryption(secret);
+        }
+        let reason = match client.read::<OutLogin>().await? {
+            OutLogin::PacketLoginOutDisconnect(disconnect) => disconnect.reason().clone().flatten(),
+            other => panic!("Expected disconnect, got {:?}", other),
+        };
+        if tamper {
+            assert!(reason.contains("Failed to verify username"));
+        } else {
+            #[cfg(not(feature = "online"))]
+            assert!(reason.contains("Could not reach the authentication servers"));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+async fn compressed_login() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        bungeecord_forwarding: true,
+        compression_threshold: 256,
+        ..Default::default()
+    };
+    let (connection, mut client) = test_connection(config).await;
+
+    // forwarded properties make the login success exceed the threshold
+    let texture = "a".repeat(2048);
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            format!(
+                "localhost\0127.0.0.1\0069a79f444e94726a5befca90e38aaf5\0[{{\"name\":\"textures\",\"value\":\"{}\"}}]",
+                texture
+            ),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+
+    match client.read::<OutLogin>().await? {
+        OutLogin::PacketLoginOutCompression(packet) => assert_eq!(*packet.threshold(), 256),
+        other => panic!("Expected compression packet, got {:?}", other),
+    }
+    client.dec.set_compression(256);
+    match client.read::<OutLogin>().await? {
+        OutLogin::PacketLoginOutSuccess(success) => {
+            assert_eq!(success.username(), "Notch");
+            assert_eq!(success.properties().len(), 1);
+            assert_eq!(success.properties()[0].value(), &texture);
+        }
+        other => panic!("Expected login success, got {:?}", other),
+    }
+
+    client.read_join().await?;
+    drop(client);
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn forwarded_address() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+    use uuid::Uuid;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        bungeecord_forwarding: true,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    let state = SharedState::new(&config, &test_runtime());
+    let registry = state.registry.clone();
+    let (connection, mut client) = test_connection_with(config, state, test_runtime()).await;
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost\01.2.3.4\0069a79f444e94726a5befca90e38aaf5\0[]".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+    client.read_join().await?;
+
+    // the player is known by the address forwarded by the proxy
+    let player = registry
+        .get(&Uuid::parse_str("069a79f444e94726a5befca90e38aaf5")?)
+        .expect("Player should be registered");
+    assert_eq!(player.addr(), "1.2.3.4:25565".parse()?);
+
+    drop(client);
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn pending_connections_per_ip() -> anyhow::Result<()> {
+    use crate::network::PendingConnections;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+    use std::net::IpAddr;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    let pending = PendingConnections::new(config.max_pending_per_ip);
+    let ip: IpAddr = "127.0.0.1".parse()?;
+
+    let mut lingering = vec![];
+    for _ in 0..5 {
+        let (mut connection, client) = test_connection(config.clone()).await;
+        connection.set_pending_slot(pending.try_acquire(ip).expect("Slot should be free"));
+        lingering.push((connection, client));
+    }
+    assert_eq!(pending.get(ip), 5);
+    assert!(pending.try_acquire(ip).is_none());
+    assert!(pending.try_acquire("127.0.0.2".parse()?).is_some());
+
+    // reaching login frees the slot
+    let (connection, mut client) = lingering.remove(0);
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+    assert_eq!(pending.get(ip), 4);
+    let slot = pending.try_acquire(ip).expect("Slot should be free");
+    assert!(pending.try_acquire(ip).is_none());
+
+    // so does closing a pending connection
+    lingering.pop();
+    assert!(pending.try_acquire(ip).is_some());
+    drop(slot);
+    assert_eq!(pending.get(ip), 3);
+
+    client.read_join().await?;
+    drop(client);
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn nbt_round_trip() -> anyhow::Result<()> {
+    use crate::net_io::nbt::{Nbt, NAMELESS_ROOT_PROTOCOL_VERSION};
+    use std::collections::BTreeMap;
+
+    let item = |id: &str, count: i8| {
+        Nbt::Compound(BTreeMap::from([
+            ("id".to_string(), Nbt::String(id.to_string())),
+            ("Count".to_string(), Nbt::Byte(count)),
+        ]))
+    };
+    let nbt = Nbt::Compound(BTreeMap::from([
+        (
+            "Items".to_string(),
+            Nbt::List(vec![item("minecraft:stone", 64), item("minecraft:dirt", 1)]),
+        ),
+        (
+            "display".to_string(),
+            Nbt::Compound(BTreeMap::from([
+                ("Name".to_string(), Nbt::String("Chest".to_string())),
+                (
+                    "Lore".to_string(),
+                    Nbt::Compound(BTreeMap::from([("Level".to_string(), Nbt::Short(-3))])),
+                ),
+            ])),
+        ),
+        ("Seed".to_string(), Nbt::Long(i64::MIN)),
+        ("Health".to_string(), Nbt::Float(19.5)),
+        ("Scale".to_string(), Nbt::Double(0.25)),
+        ("Flags".to_string(), Nbt::ByteArray(vec![-1, 0, 1])),
+        ("Ids".to_string(), Nbt::IntArray(vec![i32::MAX, 7])),
+        ("Bits".to_string(), Nbt::LongArray(vec![])),
+        ("Empty".to_string(), Nbt::List(vec![])),
+    ]));
+
+    for version in [PROTO_VERSION, NAMELESS_ROOT_PROTOCOL_VERSION] {
+        let mut buffer = vec![];
+        nbt.pack_write(&mut buffer, version).await?;
+        let mut cursor = Cursor::new(&buffer[..]);
+        assert_eq!(Nbt::pack_read(&mut cursor, version).await?, nbt);
+        assert_eq!(cursor.position() as usize, buffer.len());
+    }
+
+    // the root tag loses its name from 1.20.2 on
+    let mut named = vec![];
+    Nbt::Int(5).pack_write(&mut named, PROTO_VERSION).await?;
+    assert_eq!(named, vec![0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05]);
+    let mut nameless = vec![];
+    Nbt::Int(5)
+        .pack_write(&mut nameless, NAMELESS_ROOT_PROTOCOL_VERSION)
+        .await?;
+    assert_eq!(nameless, vec![0x03, 0x00, 0x00, 0x00, 0x05]);
+
+    assert!(Nbt::List(vec![Nbt::Int(1), Nbt::Byte(1)])
+        .pack_write(&mut vec![], PROTO_VERSION)
+        .await
+        .is_err());
+    let mut nested = vec![0x09, 0x00, 0x00];
+    for _ in 0..1024 {
+        nested.extend([0x09, 0x00, 0x00, 0x00, 0x01]);
+    }
+    assert!(Nbt::pack_read(&mut Cursor::new(&nested[..]), PROTO_VERSION)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[test]
+async fn sign_editing() -> anyhow::Result<()> {
+    use crate::net_io::Position;
+    use crate::network::handler::{dispatch, NoopHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::{
+        PacketPlayIn, PacketPlayInUpdateSign, SignSide, MAX_SIGN_LINE_LENGTH,
+        SIGN_SIDES_PROTOCOL_VERSION,
+    };
+    use crate::protocol::server::play::PacketPlayOutOpenSignEditor;
+
+    let location = Position::new(-1, 64, 2);
+    let mut buffer = vec![];
+    PacketPlayOutOpenSignEditor::new(location, SignSide::Back)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x2C];
+    expected.extend(location.packed().to_be_bytes());
+    assert_eq!(buffer, expected);
+
+    // the side is only known to 1.20+ clients
+    let mut buffer = vec![];
+    PacketPlayOutOpenSignEditor::new(location, SignSide::Back)
+        .pack_write(&mut buffer, SIGN_SIDES_PROTOCOL_VERSION)
+        .await?;
+    expected.push(0x00);
+    assert_eq!(buffer, expected);
+
+    let mut buffer = vec![0x2D];
+    location.pack_write(&mut buffer, PROTO_VERSION).await?;
+    for line in ["Welcome", "to", "", "Soulflame"] {
+        line.to_string()
+            .pack_write(&mut buffer, PROTO_VERSION)
+            .await?;
+    }
+    let sign = match PacketPlayIn::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await? {
+        PacketPlayIn::PacketPlayInUpdateSign(sign) => sign,
+        other => panic!("Expected sign update, got {:?}", other),
+    };
+    assert_eq!(*sign.location(), location);
+    assert_eq!(*sign.side(), SignSide::Front);
+    assert_eq!(
+        sign.lines().map(|line| line.as_str()),
+        ["Welcome", "to", "", "Soulflame"]
+    );
+    assert!(sign.validate_lines().is_ok());
+
+    let (tx, _rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    let long = PacketPlayInUpdateSign::new(
+        location,
+        SignSide::Front,
+        "a".repeat(MAX_SIGN_LINE_LENGTH + 1),
+        String::new(),
+        String::new(),
+        String::new(),
+    );
+    assert!(long.validate_lines().is_err());
+    assert!(dispatch(
+        &NoopHandler,
+        &player,
+        PacketPlayIn::PacketPlayInUpdateSign(long)
+    )
+    .await
+    .is_err());
+    Ok(())
+}
+
+#[test]
+async fn world_border_packets() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::PacketPlayOut;
+    use crate::world::border::WorldBorder;
+
+    let border = WorldBorder::new()
+        .center(8.5, -16.0)
+        .diameter(1000.0)
+        .warning_blocks(10)
+        .warning_time(20);
+
+    let mut buffer = vec![];
+    border
+        .initialize()
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x1D];
+    expected.extend(8.5f64.to_be_bytes());
+    expected.extend((-16.0f64).to_be_bytes());
+    expected.extend(1000.0f64.to_be_bytes());
+    expected.extend(1000.0f64.to_be_bytes());
+    // speed 0, portal boundary 29999984, 10 blocks, 20 seconds
+    expected.extend([0x00, 0xF0, 0x86, 0xA7, 0x0E, 0x0A, 0x14]);
+    assert_eq!(buffer, expected);
+
+    let mut buffer = vec![];
+    border
+        .lerp_size(500.0, 60_000)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x42];
+    expected.extend(1000.0f64.to_be_bytes());
+    expected.extend(500.0f64.to_be_bytes());
+    expected.extend([0xE0, 0xD4, 0x03]);
+    assert_eq!(buffer, expected);
+
+    let mut buffer = vec![];
+    border
+        .diameter(250.0)
+        .set_size()
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    let mut expected = vec![0x43];
+    expected.extend(250.0f64.to_be_bytes());
+    assert_eq!(buffer, expected);
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.send_world_border(&WorldBorder::new()).await?;
+    match rx.try_recv()? {
+        PacketPlayOut::PacketPlayOutInitializeWorldBorder(init) => {
+            assert_eq!(*init.new_diameter(), 59_999_968.0);
+            assert_eq!(*init.warning_blocks(), 5);
+        }
+        other => panic!("Expected world border, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+async fn missing_registry_codec() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::client::status::PacketStatusInRequest;
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::status::OutStatus;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        registry_codec: temp_path("broken_codec.nbt"),
+        ..Default::default()
+    };
+    tokio::fs::write(&config.registry_codec, b"not nbt").await?;
+    let runtime = RuntimeConfiguration::from_cfg(&config).await?;
+    assert!(runtime.registry_codec.is_none());
+
+    async fn connect(
+        config: &SoulflameConfiguration,
+        runtime: &RuntimeConfiguration,
+        intent: HandshakeState,
+    ) -> anyhow::Result<TestClient> {
+        let (connection, mut client) = test_connection_with(
+            config.clone(),
+            SharedState::new(config, runtime),
+            runtime.clone(),
+        )
+        .await;
+        tokio::spawn(connection.handle());
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                intent,
+            ))
+            .await?;
+        Ok(client)
+    }
+
+    let mut client = connect(&config, &runtime, HandshakeState::Login).await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    match client.read::<OutLogin>().await? {
+        OutLogin::PacketLoginOutDisconnect(disconnect) => {
+            assert!(disconnect
+                .reason()
+                .clone()
+                .flatten()
+                .contains("misconfigured"))
+        }
+        other => panic!("Expected disconnect, got {:?}", other),
+    }
+
+    let mut client = connect(&config, &runtime, HandshakeState::Status).await?;
+    client.send(&PacketStatusInRequest::new()).await?;
+    assert!(matches!(
+        client.read::<OutStatus>().await?,
+        OutStatus::PacketStatusOutResponse(_)
+    ));
+    Ok(())
+}
+
+#[test]
+async fn fixed_byte_array() -> anyhow::Result<()> {
+    use crate::net_io::FixedByteArray;
+    use crate::packet_struct;
+
+    let mut bytes = (0u8..16).collect::<Vec<_>>();
+    bytes.extend([0xAA, 0xBB]);
+    let mut cursor = Cursor::new(&bytes[..]);
+    let array = FixedByteArray::<16>::pack_read(&mut cursor, PROTO_VERSION).await?;
+    assert_eq!(array.0.to_vec(), (0u8..16).collect::<Vec<_>>());
+    assert_eq!(cursor.position(), 16);
+    assert_eq!(u8::pack_read(&mut cursor, PROTO_VERSION).await?, 0xAA);
+
+    // too few bytes fail instead of padding the array
+    let mut cursor = Cursor::new(&bytes[..8]);
+    assert!(FixedByteArray::<16>::pack_read(&mut cursor, PROTO_VERSION)
+        .await
+        .is_err());
+
+    packet_struct! {
+        FixedToken {
+            token: FixedByteArray<4>,
+            trailer: u8
+        }
+    }
+    let token = FixedToken::new([1, 2, 3, 4], 5);
+    let mut buffer = vec![];
+    token.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, vec![1, 2, 3, 4, 5]);
+    let read = FixedToken::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?;
+    assert_eq!(read.token(), &[1, 2, 3, 4]);
+    assert_eq!(*read.trailer(), 5);
+    Ok(())
+}
+
+#[test]
+async fn bit_sets() -> anyhow::Result<()> {
+    use crate::net_io::{BitSet, FixedBitSet};
+
+    let mut set = BitSet::new();
+    for idx in [0, 63, 64, 130] {
+        set.set(idx, true);
+    }
+    assert!(set.get(63) && set.get(64) && set.get(130));
+    assert!(!set.get(1) && !set.get(65) && !set.get(10_000));
+    assert_eq!(set.words(), &[1u64 | 1 << 63, 1, 1 << 2]);
+
+    let mut buffer = vec![];
+    set.pack_write(&mut buffer, PROTO_VERSION).await?;
+    let mut expected = vec![0x03];
+    expected.extend((1u64 | 1 << 63).to_be_bytes());
+    expected.extend(1u64.to_be_bytes());
+    expected.extend(4u64.to_be_bytes());
+    assert_eq!(buffer, expected);
+    assert_eq!(
+        BitSet::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?,
+        set
+    );
+
+    // clearing the highest bit drops the empty long
+    set.set(130, false);
+    set.set(500, false);
+    assert_eq!(set.words().len(), 2);
+    assert_eq!(BitSet::from_words(vec![1, 0, 0]), {
+        let mut single = BitSet::new();
+        single.set(0, true);
+        single
+    });
+
+    let mut fixed = FixedBitSet::<20>::new();
+    fixed.set(0, true);
+    fixed.set(9, true);
+    fixed.set(19, true);
+    let mut buffer = vec![];
+    fixed.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, vec![0x01, 0x02, 0x08]);
+    assert_eq!(
+        FixedBitSet::<20>::pack_read(&mut Cursor::new(&[0x01, 0x02, 0xF8][..]), PROTO_VERSION)
+            .await?,
+        fixed
+    );
+    assert!(!fixed.get(20));
+    assert!(
+        FixedBitSet::<20>::pack_read(&mut Cursor::new(&[0x01][..]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn explosion_packet() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{
+        ExplosionRecord, PacketPlayOutExplosion, PlayerMotion, EXPLOSION_EFFECTS_PROTOCOL_VERSION,
+    };
+
+    let mut buffer = vec![];
+    PacketPlayOutExplosion::new(
+        1.0,
+        64.0,
+        -2.5,
+        4.0,
+        vec![
+            ExplosionRecord::new(0, -1, 0),
+            ExplosionRecord::new(1, 0, -2),
+        ],
+        PlayerMotion::new(0.5, 0.25, -1.0),
+    )
+    .pack_write(&mut buffer, PROTO_VERSION)
+    .await?;
+    let mut expected = vec![0x19];
+    expected.extend(1.0f32.to_be_bytes());
+    expected.extend(64.0f32.to_be_bytes());
+    expected.extend((-2.5f32).to_be_bytes());
+    expected.extend(4.0f32.to_be_bytes());
+    expected.extend([0x02, 0x00, 0xFF, 0x00, 0x01, 0x00, 0xFE]);
+    expected.extend(0.5f32.to_be_bytes());
+    expected.extend(0.25f32.to_be_bytes());
+    expected.extend((-1.0f32).to_be_bytes());
+    assert_eq!(buffer, expected);
+
+    let records = PacketPlayOutExplosion::records_in_radius(1.0);
+    assert_eq!(records.len(), 7);
+    assert!(records
+        .iter()
+        .all(|record| record.x().abs() + record.y().abs() + record.z().abs() <= 1));
+    assert_eq!(PacketPlayOutExplosion::records_in_radius(0.5).len(), 1);
+    assert_eq!(PacketPlayOutExplosion::records_in_radius(-1.0).len(), 1);
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    assert!(
+        player
+            .show_explosion(0.0, 0.0, 0.0, 2.0, PlayerMotion::new(0.0, 0.0, 0.0))
+            .await?
+    );
+    assert_eq!(rx.len(), 1);
+    player.set_protocol_version(EXPLOSION_EFFECTS_PROTOCOL_VERSION);
+    assert!(
+        !player
+            .show_explosion(0.0, 0.0, 0.0, 2.0, PlayerMotion::new(0.0, 0.0, 0.0))
+            .await?
+    );
+    assert_eq!(rx.len(), 1);
+    Ok(())
+}
+
+#[test]
+async fn branding_reload() -> anyhow::Result<()> {
+    use crate::network::status::{Branding, DefaultStatusProvider};
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::status::{PacketStatusInPing, PacketStatusInRequest};
+    use crate::protocol::server::status::OutStatus;
+
+    let config = SoulflameConfiguration {
+        motd: "Old MOTD".to_string(),
+        ..Default::default()
+    };
+    let status = Arc::new(DefaultStatusProvider::new(
+        config.clone(),
+        RuntimeConfiguration {
+            favicon: None,
+            keys: None,
+            registry_codec: None,
+            read_timeout: None,
+        },
+    ));
+
+    async fn motd(client: &mut TestClient) -> anyhow::Result<String> {
+        client.send(&PacketStatusInRequest::new()).await?;
+        match client.read::<OutStatus>().await? {
+            OutStatus::PacketStatusOutResponse(response) => {
+                Ok(serde_json::to_string(response.response())?)
+            }
+            other => panic!("Expected status response, got {:?}", other),
+        }
+    }
+
+    let mut clients = vec![];
+    for _ in 0..2 {
+        let (mut connection, mut client) = test_connection(config.clone()).await;
+        connection.set_status_provider(status.clone());
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Status,
+            ))
+            .await?;
+        connection.start();
+        clients.push(client);
+    }
+
+    // the first client is still waiting for its pong while the branding changes
+    assert!(motd(&mut clients[0]).await?.contains("Old MOTD"));
+    status.set_branding(Branding {
+        motd: "New MOTD".to_string(),
+        favicon: None,
+    });
+    clients[0].send(&PacketStatusInPing::new(7)).await?;
+    match clients[0].read::<OutStatus>().await? {
+        OutStatus::PacketStatusOutPong(pong) => assert_eq!(*pong.payload(), 7),
+        other => panic!("Expected pong, got {:?}", other),
+    }
+    assert!(motd(&mut clients[1]).await?.contains("New MOTD"));
+
+    let path = temp_path("reload_favicon.png");
+    tokio::fs::write(&path, b"\x89PNG\r\n\x1a\n").await?;
+    let mut reloaded = SoulflameConfiguration {
+        motd: "Reloaded MOTD".to_string(),
+        favicon: path.clone(),
+        ..config
+    };
+    status.reload(&reloaded).await;
+    let branding = status.branding();
+    assert_eq!(branding.motd, "Reloaded MOTD");
+    let favicon = branding.favicon.expect("Favicon should be loaded");
+
+    // an invalid favicon keeps the current one, but still updates the MOTD
+    tokio::fs::write(&path, b"not a png").await?;
+    reloaded.motd = "Only the MOTD".to_string();
+    status.reload(&reloaded).await;
+    let branding = status.branding();
+    assert_eq!(branding.motd, "Only the MOTD");
+    assert_eq!(branding.favicon, Some(favicon));
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[test]
+async fn keep_alive_timeout() -> anyhow::Result<()> {
+    use crate::network::keepalive::KeepAlive;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::PacketPlayOut;
+    use std::time::Duration;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    let keep_alive = KeepAlive::new();
+    let task = tokio::spawn(keep_alive.clone().run(
+        player,
+        Duration::from_millis(20),
+        Duration::from_millis(100),
+    ));
+
+    // answered keep-alives keep the player connected
+    let started = Instant::now();
+    for _ in 0..8 {
+        match rx.recv_async().await? {
+            PacketPlayOut::PacketPlayOutKeepAlive(packet) => {
+                assert!(!keep_alive.acknowledge(packet.keep_alive_id().wrapping_add(1)));
+                assert!(keep_alive.acknowledge(*packet.keep_alive_id()));
+            }
+            other => panic!("Expected keep-alive, got {:?}", other),
+        }
+    }
+    assert!(started.elapsed() > Duration::from_millis(100));
+
+    // once they stay unanswered, the player is disconnected
+    let reason = loop {
+        match rx.recv_async().await? {
+            PacketPlayOut::PacketPlayOutKeepAlive(_) => {}
+            PacketPlayOut::PacketPlayOutDisconnect(disconnect) => {
+                break disconnect.reason().clone().flatten()
+            }
+            other => panic!("Expected keep-alive or disconnect, got {:?}", other),
+        }
+    };
+    assert!(reason.contains("Timed out"));
+    assert!(keep_alive.since_ack() > Duration::from_millis(100));
+    tokio::time::timeout(Duration::from_secs(1), task).await???;
+    Ok(())
+}
+
+#[test]
+async fn stop_sound_packet() -> anyhow::Result<()> {
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::server::play::{PacketPlayOut, SoundCategory};
+    use crate::util::Identifier;
+
+    let sound = Identifier::minecraft("music.game")?;
+    let mut name = vec![];
+    sound.pack_write(&mut name, PROTO_VERSION).await?;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    for (source, sound, prefix) in [
+        (None, None, vec![0x00]),
+        (Some(SoundCategory::Music), None, vec![0x01, 0x01]),
+        (None, Some(sound.clone()), vec![0x02]),
+        (
+            Some(SoundCategory::Ambient),
+            Some(sound.clone()),
+            vec![0x03, 0x08],
+        ),
+    ] {
+        let has_name = sound.is_some();
+        player.stop_sound(source, sound).await?;
+        let packet = rx.try_recv()?;
+
+        let mut buffer = vec![];
+        packet.pack_write(&mut buffer, PROTO_VERSION).await?;
+        let mut expected = vec![0x5E];
+        expected.extend(prefix);
+        if has_name {
+            expected.extend(&name);
+        }
+        assert_eq!(buffer, expected);
+
+        let read = PacketPlayOut::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?;
+        match (read, packet) {
+            (
+                PacketPlayOut::PacketPlayOutStopSound(read),
+                PacketPlayOut::PacketPlayOutStopSound(sent),
+            ) => assert_eq!(read.filter(), sent.filter()),
+            other => panic!("Expected stop sound packets, got {:?}", other),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+async fn play_in_decoding() -> anyhow::Result<()> {
+    use crate::network::handler::{dispatch, NoopHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::PacketPlayIn;
+    use crate::world::block::Location;
+
+    async fn decode(bytes: &[u8]) -> anyhow::Result<PacketPlayIn> {
+        let mut cursor = Cursor::new(bytes);
+        let packet = PacketPlayIn::pack_read(&mut cursor, PROTO_VERSION).await?;
+        assert_eq!(cursor.position() as usize, bytes.len());
+        Ok(packet)
+    }
+
+    let mut keep_alive = vec![0x11];
+    keep_alive.extend(1234i64.to_be_bytes());
+    match decode(&keep_alive).await? {
+        PacketPlayIn::PacketPlayInKeepAlive(packet) => assert_eq!(*packet.keep_alive_id(), 1234),
+        other => panic!("Expected keep-alive, got {:?}", other),
+    }
+
+    let mut chat = vec![0x04];
+    "hi".to_string()
+        .pack_write(&mut chat, PROTO_VERSION)
+        .await?;
+    chat.extend(5i64.to_be_bytes());
+    chat.extend(6i64.to_be_bytes());
+    chat.extend([0x02, 0xAB, 0xCD, 0x00]);
+    match decode(&chat).await? {
+        PacketPlayIn::PacketPlayInChatMessage(packet) => {
+            assert_eq!(packet.message(), "hi");
+            assert_eq!(*packet.timestamp(), 5);
+            assert_eq!(*packet.salt(), 6);
+            assert_eq!(packet.signature(), &vec![0xAB, 0xCD]);
+            assert!(!*packet.signed_preview());
+        }
+        other => panic!("Expected chat message, got {:?}", other),
+    }
+
+    let mut position = vec![0x13];
+    position.extend(1.5f64.to_be_bytes());
+    position.extend(64.0f64.to_be_bytes());
+    position.extend((-3.25f64).to_be_bytes());
+    position.push(0x01);
+    let packet = decode(&position).await?;
+    match &packet {
+        PacketPlayIn::PacketPlayInSetPlayerPosition(packet) => {
+            assert_eq!(*packet.x(), 1.5);
+            assert_eq!(*packet.feet_y(), 64.0);
+            assert_eq!(*packet.z(), -3.25);
+            assert!(*packet.on_ground());
+        }
+        other => panic!("Expected player position, got {:?}", other),
+    }
+
+    // moving keeps the rotation
+    let (tx, _rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_location(Location::full(0.0f32, 0.0, 0.0, 90.0, 10.0));
+    dispatch(&NoopHandler, &player, packet).await?;
+    let location = player.location();
+    assert_eq!(
+        (location.x(), location.y(), location.z()),
+        (1.5, 64.0, -3.25)
+    );
+    assert_eq!((location.yaw(), location.pitch()), (90.0, 10.0));
+    Ok(())
+}
+
+#[test]
+async fn client_command() -> anyhow::Result<()> {
+    use crate::network::handler::{dispatch, NoopHandler};
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::play::{ClientCommandAction, PacketPlayIn};
+    use crate::protocol::server::play::{GameMode, PacketPlayOut};
+    use crate::world::block::Location;
+
+    let (tx, rx) = flume::unbounded();
+    let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+    player.set_location(Location::full(8.0f32, 70.0, -8.0, 45.0, 0.0));
+    player.set_gamemode(GameMode::Creative);
+
+    let respawn =
+        PacketPlayIn::pack_read(&mut Cursor::new(&[0x06, 0x00][..]), PROTO_VERSION).await?;
+    match &respawn {
+        PacketPlayIn::PacketPlayInClientCommand(command) => {
+            assert_eq!(*command.action(), ClientCommandAction::PerformRespawn)
+        }
+        other => panic!("Expected client command, got {:?}", other),
+    }
+    dispatch(&NoopHandler, &player, respawn).await?;
+    match &rx.drain().collect::<Vec<_>>()[..] {
+        [PacketPlayOut::PacketPlayOutRespawn(respawn), PacketPlayOut::PacketPlayOutSynchronizePosition(position)] =>
+        {
+            assert_eq!(respawn.dimension_name().to_string(), "minecraft:overworld");
+            // the player respawns in the game mode it was in
+            assert_eq!(*respawn.gamemode(), GameMode::Creative);
+            assert_eq!(
+                (*position.x(), *position.y(), *position.z(), *position.yaw()),
+                (8.0, 70.0, -8.0, 45.0)
+            );
+        }
+        other => panic!("Expected respawn and position, got {:?}", other),
+    }
+
+    let stats = PacketPlayIn::pack_read(&mut Cursor::new(&[0x06, 0x01][..]), PROTO_VERSION).await?;
+    match &stats {
+        PacketPlayIn::PacketPlayInClientCommand(command) => {
+            assert_eq!(*command.action(), ClientCommandAction::RequestStats)
+        }
+        other => panic!("Expected client command, got {:?}", other),
+    }
+    dispatch(&NoopHandler, &player, stats).await?;
+    match &rx.drain().collect::<Vec<_>>()[..] {
+        [PacketPlayOut::PacketPlayOutAwardStatistics(stats)] => {
+            assert!(stats.statistics().is_empty())
+        }
+        other => panic!("Expected statistics, got {:?}", other),
+    }
+
+    assert!(
+        PacketPlayIn::pack_read(&mut Cursor::new(&[0x06, 0x02][..]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn play_packets_reach_handler() -> anyhow::Result<()> {
+    use crate::network::handler::PacketHandler;
+    use crate::network::player::PlayerHandle;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::client::play::{
+        ClientCommandAction, PacketPlayInChatMessage, PacketPlayInClientCommand,
+    };
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::play::PacketPlayOut;
+    use async_trait::async_trait;
+
+    struct ChatHandler(flume::Sender<String>);
+
+    #[async_trait]
+    impl PacketHandler for ChatHandler {
+        async fn on_chat(
+            &self,
+            _player: &PlayerHandle,
+            packet: &PacketPlayInChatMessage,
+        ) -> anyhow::Result<()> {
+            self.0.send_async(packet.message().clone()).await?;
+            Ok(())
+        }
+    }
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        ..Default::default()
+    };
+    let state = SharedState::new(&config, &test_runtime());
+    let players = state.players.clone();
+    let (mut connection, mut client) = test_connection_with(config, state, test_runtime()).await;
+    let (chat_tx, chat_rx) = flume::unbounded();
+    connection.set_handler(Arc::new(ChatHandler(chat_tx)));
+
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+    client.read_join().await?;
+
+    client
+        .send(&PacketPlayInChatMessage::new(
+            "hello".to_string(),
+            0,
+            0,
+            vec![],
+            false,
+        ))
+        .await?;
+    assert_eq!(chat_rx.recv_async().await?, "hello");
+
+    // hooks the handler does not override keep their default behavior
+    client
+        .send(&PacketPlayInClientCommand::new(
+            ClientCommandAction::RequestStats,
+        ))
+        .await?;
+    assert!(matches!(
+        client.read::<PacketPlayOut>().await?,
+        PacketPlayOut::PacketPlayOutAwardStatistics(_)
+    ));
+
+    drop(client);
+    task.await??;
+    assert_eq!(players.get(), 0);
+    Ok(())
+}
+
+#[test]
+async fn session_server_retries() -> anyhow::Result<()> {
+    use crate::cfg::SessionServerConfig;
+    use crate::network::auth::{has_joined, SessionResponse, SessionServer};
+    use async_trait::async_trait;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    struct MockSessionServer {
+        statuses: Mutex<VecDeque<u16>>,
+        requests: Mutex<u32>,
+        available: bool,
+    }
+
+    impl MockSessionServer {
+        fn new(statuses: &[u16]) -> Self {
+            Self {
+                statuses: Mutex::new(statuses.iter().copied().collect()),
+                requests: Mutex::new(0),
+                available: true,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SessionServer for MockSessionServer {
+        async fn has_joined(
+            &self,
+            username: &str,
+            _server_hash: &str,
+        ) -> anyhow::Result<SessionResponse> {
+            *self.requests.lock().unwrap() += 1;
+            let status = match self.statuses.lock().unwrap().pop_front() {
+                Some(status) => status,
+                None => bail!("Connection refused"),
+            };
+            Ok(SessionResponse {
+                status,
+                body: format!(
+                    r#"{{"id":"b50ad385829d3141a2167e7d7539ba7f","name":"{}"}}"#,
+                    username
+                ),
+            })
+        }
+
+        fn is_available(&self) -> bool {
+            self.available
+        }
+    }
+
+    let cfg = SessionServerConfig {
+        attempts: 3,
+        request_timeout_ms: 1000,
+        initial_backoff_ms: 10,
+        total_timeout_ms: 5000,
+    };
+
+    let flaky = MockSessionServer::new(&[503, 200]);
+    let profile = has_joined(&flaky, "Notch", "hash", &cfg)
+        .await?
+        .expect("Player should be authenticated");
+    assert_eq!(profile.name, "Notch");
+    assert_eq!(*flaky.requests.lock().unwrap(), 2);
+
+    // not having joined is definitive
+    let unauthenticated = MockSessionServer::new(&[204, 200]);
+    assert!(has_joined(&unauthenticated, "Notch", "hash", &cfg)
+        .await?
+        .is_none());
+    assert_eq!(*unauthenticated.requests.lock().unwrap(), 1);
+
+    let forbidden = MockSessionServer::new(&[403, 200]);
+    assert!(has_joined(&forbidden, "Notch", "hash", &cfg).await.is_err());
+    assert_eq!(*forbidden.requests.lock().unwrap(), 1);
+
+    // transport errors are retried until the attempts run out
+    let down = MockSessionServer::new(&[]);
+    assert!(has_joined(&down, "Notch", "hash", &cfg).await.is_err());
+    assert_eq!(*down.requests.lock().unwrap(), 3);
+
+    // an unavailable session server fails right away
+    let unavailable = MockSessionServer {
+        available: false,
+        ..MockSessionServer::new(&[])
+    };
+    assert!(has_joined(&unavailable, "Notch", "hash", &cfg)
+        .await
+        .is_err());
+    assert_eq!(*unavailable.requests.lock().unwrap(), 1);
+
+    // retries stop once the next one would start after the total timeout
+    let slow = MockSessionServer::new(&[503, 503, 200]);
+    let short = SessionServerConfig {
+        initial_backoff_ms: 100,
+        total_timeout_ms: 150,
+        ..cfg
+    };
+    assert!(has_joined(&slow, "Notch", "hash", &short).await.is_err());
+    assert_eq!(*slow.requests.lock().unwrap(), 2);
+    Ok(())
+}
+
+#[test]
+async fn configuration_file() -> anyhow::Result<()> {
+    let dir = temp_path("configuration_file");
+    let path = dir.join("soulflame.toml");
+
+    // a missing file is created with the defaults, which read back the same
+    let created = SoulflameConfiguration::load_or_create(&path).await?;
+    let written = tokio::fs::read_to_string(&path).await?;
+    assert_eq!(written, created.to_toml()?);
+    let loaded = SoulflameConfiguration::load_or_create(&path).await?;
+    assert_eq!(loaded.to_toml()?, written);
+
+    tokio::fs::write(&path, "max_players = 5\n[session_server]\nattempts = 1\n").await?;
+    let partial = SoulflameConfiguration::load_or_create(&path).await?;
+    assert_eq!(partial.max_players, 5);
+    assert_eq!(partial.session_server.attempts, 1);
+    assert_eq!(partial.motd, SoulflameConfiguration::default().motd);
+
+    // a malformed file falls back to the defaults and is kept for the user to fix
+    tokio::fs::write(&path, "max_players = \"five").await?;
+    let fallback = SoulflameConfiguration::load_or_create(&path).await?;
+    assert_eq!(
+        fallback.max_players,
+        SoulflameConfiguration::default().max_players
+    );
+    assert_eq!(
+        tokio::fs::read_to_string(&path).await?,
+        "max_players = \"five"
+    );
+
+    tokio::fs::remove_dir_all(&dir).await?;
+    Ok(())
+}
+
+#[test]
+async fn maintenance_mode() -> anyhow::Result<()> {
+    use crate::network::auth::offline_uuid;
+    use crate::network::status::{DefaultStatusProvider, StatusProvider};
+    use crate::network::MaintenanceMode;
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+
+    async fn login(
+        config: SoulflameConfiguration,
+        maintenance: &MaintenanceMode,
+        name: &str,
+    ) -> anyhow::Result<OutLogin> {
+        let (mut connection, mut client) = test_connection(config).await;
+        connection.set_maintenance(maintenance.clone());
+        client
+            .send(&PacketHandshakeIn::new(
+                759,
+                "localhost".to_string(),
+                25565,
+                HandshakeState::Login,
+            ))
+            .await?;
+        client
+            .send(&PacketLoginInStart::new(name.to_string(), None))
+            .await?;
+        connection.start();
+        client.read::<OutLogin>().await
+    }
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        maintenance: true,
+        maintenance_kick_message: "Down for maintenance".to_string(),
+        maintenance_bypass: vec!["Admin".to_string(), offline_uuid("Moderator").to_string()],
+        ..Default::default()
+    };
+    let maintenance = MaintenanceMode::new(config.maintenance);
+
+    match login(config.clone(), &maintenance, "Notch").await? {
+        OutLogin::PacketLoginOutDisconnect(disconnect) => {
+            assert!(disconnect
+                .reason()
+                .clone()
+                .flatten()
+                .contains("Down for maintenance"))
+        }
+        other => panic!("Expected disconnect, got {:?}", other),
+    }
+    for name in ["admin", "Moderator"] {
+        assert!(matches!(
+            login(config.clone(), &maintenance, name).await?,
+            OutLogin::PacketLoginOutSuccess(_)
+        ));
+    }
+
+    maintenance.set_enabled(false);
+    assert!(matches!(
+        login(config.clone(), &maintenance, "Notch").await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+
+    let runtime = test_runtime();
+    let mut status = DefaultStatusProvider::new(
+        SoulflameConfiguration {
+            motd: "Regular MOTD".to_string(),
+            maintenance_motd: "Maintenance MOTD".to_string(),
+            ..config
+        },
+        runtime,
+    );
+    status.set_maintenance(maintenance.clone());
+    assert!(serde_json::to_string(&status.status(759).await)?.contains("Regular MOTD"));
+    maintenance.set_enabled(true);
+    assert!(serde_json::to_string(&status.status(759).await)?.contains("Maintenance MOTD"));
+    Ok(())
+}
+
+#[test]
+async fn legacy_ping() -> anyhow::Result<()> {
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::status::PacketStatusInRequest;
+    use crate::protocol::server::status::OutStatus;
+
+    let config = SoulflameConfiguration {
+        motd: "<aqua>Legacy MOTD".to_string(),
+        max_players: 42,
+        ..Default::default()
+    };
+
+    // 1.6 clients follow the ping with a plugin message, which is never read
+    let (connection, mut client) = test_connection(config.clone()).await;
+    client
+        .stream
+        .write_all(&[0xFE, 0x01, 0xFA, 0x00, 0x0B])
+        .await?;
+    connection.start();
+    let mut response = vec![];
+    client.stream.read_to_end(&mut response).await?;
+
+    assert_eq!(response[0], 0xFF);
+    let len = u16::from_be_bytes([response[1], response[2]]) as usize;
+    let chars = response[3..]
+        .chunks(2)
+        .map(|char| u16::from_be_bytes([char[0], char[1]]))
+        .collect::<Vec<_>>();
+    assert_eq!(chars.len(), len);
+    let text = String::from_utf16(&chars)?;
+    let fields = text.split('\0').collect::<Vec<_>>();
+    let version = format!("Requires {}", config.version_name());
+    assert_eq!(fields, ["§1", "127", &version, "Legacy MOTD", "0", "42"]);
+
+    // peeking for the legacy ping keeps the handshake intact
+    let (connection, mut client) = test_connection(config).await;
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Status,
+        ))
+        .await?;
+    client.send(&PacketStatusInRequest::new()).await?;
+    connection.start();
+    assert!(matches!(
+        client.read::<OutStatus>().await?,
+        OutStatus::PacketStatusOutResponse(_)
+    ));
+    Ok(())
+}
+
+#[test]
+async fn status_protocol_version() -> anyhow::Result<()> {
+    use crate::network::status::{DefaultStatusProvider, StatusProvider};
+
+    let config = SoulflameConfiguration {
+        spoof_version_name: Some("Custom 1.19".to_string()),
+        ..Default::default()
+    };
+    let status = DefaultStatusProvider::new(
+        config,
+        RuntimeConfiguration {
+            favicon: None,
+            keys: None,
+            registry_codec: None,
+            read_timeout: None,
+        },
+    );
+
+    let matching = serde_json::to_value(status.status(PROTO_VERSION).await)?;
+    assert_eq!(matching["version"]["protocol"], PROTO_VERSION);
+    assert_eq!(matching["version"]["name"], "Custom 1.19");
+
+    // clients on other versions are shown our version as the one to use
+    let outdated = serde_json::to_value(status.status(47).await)?;
+    assert_eq!(
+        outdated["version"]["protocol"],
+        crate::LATEST_PROTOCOL_VERSION
+    );
+    assert_eq!(outdated["version"]["name"], "Requires Custom 1.19");
+    Ok(())
+}
+
+#[test]
+async fn rcon_sessions() -> anyhow::Result<()> {
+    use crate::network::rcon::{
+        handle_rcon, RconHandler, RconPacket, RCON_AUTH_FAILURE, RCON_AUTH_RESPONSE, RCON_COMMAND,
+        RCON_LOGIN, RCON_RESPONSE,
+    };
+    use async_trait::async_trait;
+
+    struct Echo;
+
+    #[async_trait]
+    impl RconHandler for Echo {
+        async fn execute(&self, command: &str) -> String {
+            format!("ran {}", command)
+        }
+    }
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let session = tokio::spawn(async move {
+        handle_rcon(server, "127.0.0.1:25575".parse()?, "secret", &Echo).await
+    });
+
+    // commands are refused until the client logged in
+    RconPacket::new(1, RCON_COMMAND, "list")
+        .write(&mut client)
+        .await?;
+    assert_eq!(
+        RconPacket::read(&mut client).await?,
+        Some(RconPacket::new(RCON_AUTH_FAILURE, RCON_AUTH_RESPONSE, ""))
+    );
+
+    RconPacket::new(2, RCON_LOGIN, "wrong")
+        .write(&mut client)
+        .await?;
+    assert_eq!(
+        RconPacket::read(&mut client).await?,
+        Some(RconPacket::new(RCON_AUTH_FAILURE, RCON_AUTH_RESPONSE, ""))
+    );
+
+    RconPacket::new(3, RCON_LOGIN, "secret")
+        .write(&mut client)
+        .await?;
+    assert_eq!(
+        RconPacket::read(&mut client).await?,
+        Some(RconPacket::new(3, RCON_AUTH_RESPONSE, ""))
+    );
+
+    RconPacket::new(4, RCON_COMMAND, "list")
+        .write(&mut client)
+        .await?;
+    assert_eq!(
+        RconPacket::read(&mut client).await?,
+        Some(RconPacket::new(4, RCON_RESPONSE, "ran list"))
+    );
+
+    drop(client);
+    session.await??;
+
+    // a declared length beyond the limit ends the session before anything is allocated
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let session = tokio::spawn(async move {
+        handle_rcon(server, "127.0.0.1:25575".parse()?, "secret", &Echo).await
+    });
+    client.write_all(&i32::MAX.to_le_bytes()).await?;
+    assert!(session.await?.is_err());
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let session = tokio::spawn(async move {
+        handle_rcon(server, "127.0.0.1:25575".parse()?, "secret", &Echo).await
+    });
+    client.write_all(&4i32.to_le_bytes()).await?;
+    assert!(session.await?.is_err());
+    Ok(())
+}
+
+#[test]
+async fn query_protocol() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::network::player::PlayerHandle;
+    use crate::network::query::{
+        answer_query, ChallengeTokens, QueryStatus, CHALLENGE_TOKEN_LIFETIME,
+    };
+    use crate::network::registry::PlayerRegistry;
+    use lobstermessage::lobster;
+    use std::time::Duration;
+
+    let addr = "127.0.0.1:40000".parse()?;
+    let other = "127.0.0.2:40000".parse()?;
+
+    let mut tokens = ChallengeTokens::new(CHALLENGE_TOKEN_LIFETIME);
+    let token = tokens.issue(addr);
+    assert!(token >= 0);
+    assert!(tokens.validate(addr, token));
+    assert!(!tokens.validate(addr, token.wrapping_add(1)));
+    assert!(!tokens.validate(other, token));
+
+    let mut expiring = ChallengeTokens::new(Duration::from_millis(10));
+    let token = expiring.issue(addr);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!expiring.validate(addr, token));
+
+    let registry = PlayerRegistry::new();
+    let mut players = PlayerCount::new(42);
+    for (name, id) in [("jeb_", 1), ("Notch", 2)] {
+        let (tx, _) = flume::unbounded();
+        let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+        player.set_name(name.to_string());
+        registry.register(uuid::Uuid::from_u128(id), player, DuplicateLogin::Deny)?;
+        players.try_add()?;
+    }
+    let status = QueryStatus::from_cfg(
+        &SoulflameConfiguration {
+            max_players: 42,
+            ..Default::default()
+        },
+        &lobster("<aqua>Query MOTD"),
+        &players,
+        &registry,
+        "127.0.0.1".to_string(),
+        25565,
+    );
+
+    // the handshake answers with the token as a decimal string
+    let handshake = answer_query(
+        &[0xFE, 0xFD, 0x09, 0x00, 0x00, 0x00, 0x01],
+        addr,
+        &mut tokens,
+        &status,
+    )
+    .expect("Handshake should be answered");
+    assert_eq!(handshake[..5], [0x09, 0x00, 0x00, 0x00, 0x01]);
+    assert_eq!(handshake.last(), Some(&0));
+    let token: i32 = std::str::from_utf8(&handshake[5..handshake.len() - 1])?.parse()?;
+
+    let mut request = vec![0xFE, 0xFD, 0x00, 0x00, 0x00, 0x00, 0x01];
+    request.extend(token.wrapping_add(1).to_be_bytes());
+    assert!(answer_query(&request, addr, &mut tokens, &status).is_none());
+
+    let mut request = vec![0xFE, 0xFD, 0x00, 0x00, 0x00, 0x00, 0x01];
+    request.extend(token.to_be_bytes());
+    let basic =
+        answer_query(&request, addr, &mut tokens, &status).expect("Basic stat should be answered");
+    let mut expected = vec![0x00, 0x00, 0x00, 0x00, 0x01];
+    expected.extend(b"Query MOTD\0SMP\0world\02\042\0");
+    expected.extend(25565u16.to_le_bytes());
+    expected.extend(b"127.0.0.1\0");
+    assert_eq!(basic, expected);
+
+    request.extend([0x00; 4]);
+    let full =
+        answer_query(&request, addr, &mut tokens, &status).expect("Full stat should be answered");
+    let mut expected = vec![0x00, 0x00, 0x00, 0x00, 0x01];
+    expected.extend(b"splitnum\0\x80\0");
+    expected.extend(b"hostname\0Query MOTD\0gametype\0SMP\0game_id\0MINECRAFT\0");
+    expected.extend(
+        format!(
+            "version\0{}\0",
+            SoulflameConfiguration::default().version_name()
+        )
+        .as_bytes(),
+    );
+    expected.extend(b"plugins\0\0map\0world\0numplayers\02\0maxplayers\042\0");
+    expected.extend(b"hostport\025565\0hostip\0127.0.0.1\0\0");
+    expected.extend(b"\x01player_\0\0Notch\0jeb_\0\0");
+    assert_eq!(full, expected);
+
+    assert!(answer_query(&[0xFE, 0xFD, 0x00], addr, &mut tokens, &status).is_none());
+    Ok(())
+}
+
+#[test]
+async fn maintenance_over_rcon() -> anyhow::Result<()> {
+    use crate::network::rcon::{MaintenanceCommand, RconHandler};
+    use crate::network::NetworkListener;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        ..Default::default()
+    };
+    let listener = NetworkListener::bind("127.0.0.1".into(), 0, config).await?;
+    let maintenance = listener.maintenance();
+    let command = MaintenanceCommand::new(listener.maintenance());
+
+    assert_eq!(
+        command.execute("maintenance on").await,
+        "Maintenance mode is enabled"
+    );
+    assert!(maintenance.is_enabled());
+    assert_eq!(
+        command.execute("maintenance off").await,
+        "Maintenance mode is disabled"
+    );
+    assert!(!maintenance.is_enabled());
+    assert_eq!(
+        command.execute("maintenance").await,
+        "Maintenance mode is disabled"
+    );
+    assert!(command
+        .execute("maintenance maybe")
+        .await
+        .starts_with("Usage"));
+    assert!(command.execute("stop").await.starts_with("Unknown command"));
+    Ok(())
+}
+
+#[test]
+async fn graceful_shutdown() -> anyhow::Result<()> {
+    use crate::network::{NetworkListener, Shutdown};
+    use crate::protocol::client::handshake::{HandshakeState, PacketHandshakeIn};
+    use crate::protocol::client::login::PacketLoginInStart;
+    use crate::protocol::server::login::OutLogin;
+    use crate::protocol::server::play::PacketPlayOut;
+    use std::time::Duration;
+
+    let config = SoulflameConfiguration {
+        online_mode: false,
+        compression_threshold: -1,
+        shutdown_message: "Closing for today".to_string(),
+        ..Default::default()
+    };
+
+    let listener = NetworkListener::bind("127.0.0.1".into(), 0, config.clone()).await?;
+    let shutdown = listener.shutdown_handle();
+    let running = tokio::spawn(listener.run());
+    shutdown.shutdown();
+    tokio::time::timeout(Duration::from_secs(1), running).await??;
+
+    // players are disconnected, and the shutdown waits for their connections to close
+    let (trigger, signal, mut closed) = Shutdown::new();
+    let (mut connection, mut client) = test_connection(config).await;
+    connection.set_shutdown(signal);
+    client
+        .send(&PacketHandshakeIn::new(
+            759,
+            "localhost".to_string(),
+            25565,
+            HandshakeState::Login,
+        ))
+        .await?;
+    client
+        .send(&PacketLoginInStart::new("Notch".to_string(), None))
+        .await?;
+    let task = tokio::spawn(connection.handle());
+    assert!(matches!(
+        client.read::<OutLogin>().await?,
+        OutLogin::PacketLoginOutSuccess(_)
+    ));
+    client.read_join().await?;
+
+    trigger.shutdown();
+    match client.read::<PacketPlayOut>().await? {
+        PacketPlayOut::PacketPlayOutDisconnect(disconnect) => {
+            assert_eq!(disconnect.reason().clone().flatten(), "Closing for today")
+        }
+        other => panic!("Expected disconnect, got {:?}", other),
+    }
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(1), closed.recv()).await?;
+    task.await??;
+    Ok(())
+}
+
+#[test]
+async fn registry_broadcast_and_kick() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::chat::Component;
+    use crate::network::player::PlayerHandle;
+    use crate::network::registry::PlayerRegistry;
+    use crate::protocol::client::play::Hand;
+    use crate::protocol::server::play::{PacketPlayOut, PacketPlayOutEntityAnimation};
+    use uuid::Uuid;
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (first_tx, first_rx) = flume::unbounded();
+    let first = PlayerHandle::new(addr, first_tx);
+    let (second_tx, second_rx) = flume::unbounded();
+    let second = PlayerHandle::new(addr, second_tx);
+
+    let registry = PlayerRegistry::new();
+    registry.register(Uuid::from_u128(1), first.clone(), DuplicateLogin::Deny)?;
+    registry.register(Uuid::from_u128(2), second.clone(), DuplicateLogin::Deny)?;
+
+    registry
+        .broadcast(PacketPlayOutEntityAnimation::swing(1, Hand::MainHand))
+        .await;
+    for rx in [&first_rx, &second_rx] {
+        assert!(matches!(
+            rx.try_recv()?,
+            PacketPlayOut::PacketPlayOutEntityAnimation(_)
+        ));
+    }
+
+    let reason = Component::text("Kicked".to_string());
+    assert!(registry.kick(&Uuid::from_u128(1), reason.clone()).await?);
+    assert!(matches!(
+        first_rx.try_recv()?,
+        PacketPlayOut::PacketPlayOutDisconnect(_)
+    ));
+    assert!(second_rx.is_empty());
+    assert!(!registry.kick(&Uuid::from_u128(3), reason).await?);
+
+    // the kicked connection unregisters itself once it closed
+    registry.unregister(&Uuid::from_u128(1), &first);
+    assert_eq!(registry.len(), 1);
+    Ok(())
+}
+
+#[test]
+async fn varint_five_byte_values() -> anyhow::Result<()> {
+    for (value, bytes) in [
+        (-1, vec![0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        (i32::MIN, vec![0x80, 0x80, 0x80, 0x80, 0x08]),
+        (i32::MAX, vec![0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+    ] {
+        let mut buffer = vec![];
+        VarInt(value).pack_write(&mut buffer, PROTO_VERSION).await?;
+        assert_eq!(buffer, bytes);
+        let read = VarInt::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+        assert_eq!(read, VarInt(value));
+    }
+
+    // bits above the 32nd and a sixth byte are both rejected
+    for bytes in [
+        [0xFF, 0xFF, 0xFF, 0xFF, 0x1F].as_slice(),
+        [0xFF, 0xFF, 0xFF, 0xFF, 0x8F, 0x01].as_slice(),
+    ] {
+        assert!(VarInt::pack_read(&mut Cursor::new(bytes), PROTO_VERSION)
+            .await
+            .is_err());
+    }
+    Ok(())
+}
+
+#[test]
+async fn packet_size_limits() -> anyhow::Result<()> {
+    use crate::network::encode::MAX_PACKET_SIZE;
+    use crate::protocol::server::play::PacketPlayOut;
+    use async_compression::tokio::bufread::ZlibEncoder;
+
+    // the declared length is refused before waiting for the body
+    let mut dec = PacketDecoder::new();
+    let mut buffer = vec![];
+    VarInt(i32::MAX)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    dec.digest(&buffer);
+    assert!(dec.read::<PacketPlayOut>().await.is_err());
+
+    let mut dec = PacketDecoder::new();
+    dec.set_max_packet_size(16);
+    let mut buffer = vec![];
+    VarInt(17).pack_write(&mut buffer, PROTO_VERSION).await?;
+    dec.digest(&buffer);
+    assert!(dec.read::<PacketPlayOut>().await.is_err());
+
+    // a length cut off after a few bytes waits for more, one longer than five bytes is refused
+    let mut dec = PacketDecoder::new();
+    dec.digest(&[0xFF, 0xFF]);
+    assert!(dec.read::<PacketPlayOut>().await?.is_none());
+    dec.digest(&[0xFF, 0xFF, 0xFF]);
+    assert!(dec.read::<PacketPlayOut>().await.is_err());
+
+    // a few kilobytes of zeroes expanding to twice the limit
+    let zeroes = vec![0u8; MAX_PACKET_SIZE * 2];
+    let mut compressed = vec![];
+    ZlibEncoder::new(&zeroes[..])
+        .read_to_end(&mut compressed)
+        .await?;
+    assert!(compressed.len() < MAX_PACKET_SIZE);
+
+    for data_len in [256, zeroes.len() as i32] {
+        let mut body = vec![];
+        VarInt(data_len)
+            .pack_write(&mut body, PROTO_VERSION)
+            .await?;
+        body.extend(&compressed);
+        let mut buffer = vec![];
+        VarInt(body.len() as i32)
+            .pack_write(&mut buffer, PROTO_VERSION)
+            .await?;
+        buffer.extend(body);
+
+        let mut dec = PacketDecoder::new();
+        dec.set_compression(64);
+        dec.digest(&buffer);
+        assert!(dec.read::<PacketPlayOut>().await.is_err());
+    }
+
+    let mut body = vec![];
+    VarInt(-1).pack_write(&mut body, PROTO_VERSION).await?;
+    body.push(0x00);
+    let mut buffer = vec![];
+    VarInt(body.len() as i32)
+        .pack_write(&mut buffer, PROTO_VERSION)
+        .await?;
+    buffer.extend(body);
+    let mut dec = PacketDecoder::new();
+    dec.set_compression(64);
+    dec.digest(&buffer);
+    assert!(dec.read::<PacketPlayOut>().await.is_err());
+    Ok(())
+}
+
+#[test]
+async fn configurable_read_timeout() -> anyhow::Result<()> {
+    use crate::network::client::InboundPacketChannel;
+    use crate::protocol::client::status::{InStatus, PacketStatusInRequest};
+    use std::time::Duration;
+
+    let addr = "127.0.0.1:25565".parse()?;
+    let (client, server) = tokio::io::duplex(1024);
+    let (tx, _rx) = flume::bounded(32);
+    let mut inbound =
+        InboundPacketChannel::new(Box::new(server), tx, addr, Some(Duration::from_millis(50)));
+    let err = inbound.read_packet::<InStatus>().await.unwrap_err();
+    assert!(err.to_string().contains("timed out"));
+    drop(client);
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (tx, _rx) = flume::bounded(32);
+    let mut inbound = InboundPacketChannel::new(Box::new(server), tx, addr, None);
+    let mut client = TestClient {
+        stream: client,
+        enc: PacketEncoder::new(),
+        dec: PacketDecoder::new(),
+    };
+    let mut read = tokio::spawn(async move { inbound.read_packet::<InStatus>().await });
+    assert!(tokio::time::timeout(Duration::from_millis(200), &mut read)
+        .await
+        .is_err());
+
+    client.send(&PacketStatusInRequest::new()).await?;
+    assert!(matches!(read.await??, InStatus::PacketStatusInRequest(_)));
+    Ok(())
+}
+
+#[test]
+async fn fixed_size_arrays() -> anyhow::Result<()> {
+    let mut buffer = vec![];
+    [1i32, -2, 3].pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, vec![0, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFE, 0, 0, 0, 3]);
+    let read = <[i32; 3]>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(read, [1, -2, 3]);
+
+    let secret = [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    let mut buffer = vec![];
+    secret.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer, secret.to_vec());
+    let mut cursor = Cursor::new(&buffer[..]);
+    assert_eq!(
+        <[u8; 16]>::pack_read(&mut cursor, PROTO_VERSION).await?,
+        secret
+    );
+    assert_eq!(cursor.position(), 16);
+
+    assert!(
+        <[u8; 16]>::pack_read(&mut Cursor::new(&buffer[..15]), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn tuple_round_trip() -> anyhow::Result<()> {
+    use crate::packet_struct;
+    use uuid::Uuid;
+
+    let pair = ("minecraft:overworld".to_string(), -7i32);
+    let mut buffer = vec![];
+    pair.pack_write(&mut buffer, PROTO_VERSION).await?;
+    let mut expected = vec![19];
+    expected.extend(b"minecraft:overworld");
+    expected.extend((-7i32).to_be_bytes());
+    assert_eq!(buffer, expected);
+    let read = <(String, i32)>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(read, pair);
+
+    let triple = (VarInt(300), true, Uuid::from_u128(0x1234_5678));
+    let mut buffer = vec![];
+    triple.pack_write(&mut buffer, PROTO_VERSION).await?;
+    assert_eq!(buffer.len(), 2 + 1 + 16);
+    let read = <(VarInt, bool, Uuid)>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(read, triple);
+
+    packet_struct! {
+        Dimensions {
+            dimensions: Vec<(String, VarInt)>
+        }
+    }
+    let dimensions = Dimensions::new(vec![("overworld".to_string(), VarInt(0))]);
+    let mut buffer = vec![];
+    dimensions.pack_write(&mut buffer, PROTO_VERSION).await?;
+    let read = Dimensions::pack_read(&mut Cursor::new(&buffer[..]), PROTO_VERSION).await?;
+    assert_eq!(read.dimensions(), dimensions.dimensions());
+    Ok(())
+}
+
+#[test]
+async fn map_round_trip() -> anyhow::Result<()> {
+    use std::collections::HashMap;
+
+    let map: HashMap<String, i32> = [("b", 2), ("a", 1), ("c", -3)]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+    let mut buffer = vec![];
+    map.pack_write(&mut buffer, PROTO_VERSION).await?;
+
+    // the count, then the entries sorted by key
+    let mut expected = vec![3];
+    for (key, value) in [("a", 1i32), ("b", 2), ("c", -3)] {
+        expected.push(1);
+        expected.extend(key.as_bytes());
+        expected.extend(value.to_be_bytes());
+    }
+    assert_eq!(buffer, expected);
+
+    let read = HashMap::<String, i32>::pack_read(&mut Cursor::new(&buffer), PROTO_VERSION).await?;
+    assert_eq!(read, map);
+
+    let mut oversized = vec![];
+    VarInt(i32::MAX)
+        .pack_write(&mut oversized, PROTO_VERSION)
+        .await?;
+    assert!(
+        HashMap::<String, i32>::pack_read(&mut Cursor::new(&oversized), PROTO_VERSION)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+async fn status_response_builder() -> anyhow::Result<()> {
+    use crate::chat::Component;
+    use crate::protocol::server::status::{ServerPlayers, ServerVersion, StatusResponse};
+
+    let builder = || {
+        StatusResponse::builder(
+            ServerVersion::new("1.19".to_string(), PROTO_VERSION as i32),
+            ServerPlayers::new(20, 0, vec![]),
+            Component::text("A server".to_string()),
+        )
+    };
+
+    let png = include_bytes!("../res/favicon.png");
+    let response = builder().favicon(png)?.build();
+    let json = serde_json::to_value(&response)?;
+    let favicon = json["favicon"].as_str().unwrap();
+    assert_eq!(
+        base64::decode(favicon.strip_prefix("data:image/png;base64,").unwrap())?,
+        png.to_vec()
+    );
+
+    let path = temp_path("builder_favicon.png");
+    tokio::fs::write(&path, png).await?;
+    assert!(
+        serde_json::to_value(&builder().favicon_file(&path).await?.build())?
+            .get("favicon")
+            .is_some()
+    );
+    tokio::fs::remove_file(&path).await?;
+
+    let mut ihdr = [0u8; 13];
+    ihdr[..8].copy_from_slice(&[0, 0, 0, 32, 0, 0, 0, 32]);
+    let small = png_with_chunks(&[(b"IHDR", &ihdr), (b"IEND", &[])]);
+    assert!(builder().favicon(&small).is_err());
+    assert!(builder().favicon(b"not a png").is_err());
+
+    // without a favicon the field is left out
+    let json = serde_json::to_value(&builder().build())?;
+    assert!(json.get("favicon").is_none());
+    Ok(())
+}
+
+#[test]
+async fn status_player_sample() -> anyhow::Result<()> {
+    use crate::cfg::DuplicateLogin;
+    use crate::network::player::PlayerHandle;
+    use crate::network::registry::PlayerRegistry;
+    use crate::network::status::{DefaultStatusProvider, StatusProvider};
+    use uuid::Uuid;
+
+    let config = SoulflameConfiguration {
+        sample_size: 2,
+        ..Default::default()
+    };
+    let mut players = PlayerCount::new(config.max_players);
+    let registry = PlayerRegistry::new();
+    let names = ["Alice", "Bob", "Carol"];
+    for (id, name) in names.into_iter().enumerate() {
+        let (tx, _rx) = flume::unbounded();
+        let player = PlayerHandle::new("127.0.0.1:25565".parse()?, tx);
+        player.set_name(name.to_string());
+        players.try_add()?;
+        registry.register(Uuid::from_u128(id as u128), player, DuplicateLogin::Deny)?;
+    }
+
+    let mut status = DefaultStatusProvider::new(
+        config,
+        RuntimeConfiguration {
+            favicon: None,
+            keys: None,
+            registry_codec: None,
+            read_timeout: None,
+        },
+    );
+    status.set_players(players, registry);
+    let json = serde_json::to_value(&status.status(PROTO_VERSION).await)?;
+    assert_eq!(json["players"]["online"], 3);
+    let sample = json["players"]["sample"].as_array().unwrap();
+    assert_eq!(sample.len(), 2);
+    for entry in sample {
+        assert!(names.contains(&entry["name"].as_str().unwrap()));
+    }
+    Ok(())
+}
+
+#[test]
+async fn bundled_registry_codec() -> anyhow::Result<()> {
+    use crate::DEFAULT_REGISTRY_CODEC;
+
+    let config = SoulflameConfiguration {
+        registry_codec: temp_path("registry_codec.nbt"),
+        online_mode: false,
+        ..Default::default()
+    };
+    tokio::fs::write(&config.registry_codec, DEFAULT_REGISTRY_CODEC).await?;
+    let runtime = RuntimeConfiguration::from_cfg(&config).await?;
+    let codec = runtime.registry_codec.expect("Bundled codec should load");
+    for registry in [
+        "minecraft:dimension_type",
+        "minecraft:worldgen/biome",
+        "minecraft:chat_type",
+    ] {
+        assert!(matches!(codec.get(registry), Some(nbt::Value::Compound(_))));
+    }
+    Ok(())
+}
+
+/// Property tests asserting that decoding an encoded value yields the same value.
+///
+/// Kept in their own module, since `proptest!` expects the standard `#[test]` attribute.
+mod round_trip_properties {
+    use super::PROTO_VERSION;
+    use crate::net_io::{PacketRead, PacketWrite, VarInt, VarLong};
+    use proptest::prelude::*;
+    use std::io::Cursor;
+
+    /// Encodes and decodes the value, checking that every written byte was read back.
+    fn round_trip<T: PacketRead + PacketWrite + Send + Sync>(value: &T) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mut buffer = vec![];
+                value.pack_write(&mut buffer, PROTO_VERSION).await.unwrap();
+                let mut cursor = Cursor::new(&buffer[..]);
+                let read = T::pack_read(&mut cursor, PROTO_VERSION).await.unwrap();
+                assert_eq!(cursor.position() as usize, buffer.len());
+                read
+            })
+    }
+
+    /// Strings of up to 1024 characters stay well below the 32767 byte limit.
+    fn string() -> impl Strategy<Value = String> {
+        ".{0,1024}"
+    }
+
+    proptest! {
+        #[test]
+        fn integers(
+            a in any::<u8>(),
+            b in any::<i8>(),
+            c in any::<u16>(),
+            d in any::<i16>(),
+            e in any::<u32>(),
+            f in any::<i32>(),
+            g in any::<u64>(),
+            h in any::<i64>(),
+            i in any::<u128>(),
+            j in any::<i128>(),
+            k in any::<bool>(),
+        ) {
+            prop_assert_eq!(round_trip(&a), a);
+            prop_assert_eq!(round_trip(&b), b);
+            prop_assert_eq!(round_trip(&c), c);
+            prop_assert_eq!(round_trip(&d), d);
+            prop_assert_eq!(round_trip(&e), e);
+            prop_assert_eq!(round_trip(&f), f);
+            prop_assert_eq!(round_trip(&g), g);
+            prop_assert_eq!(round_trip(&h), h);
+            prop_assert_eq!(round_trip(&i), i);
+            prop_assert_eq!(round_trip(&j), j);
+            prop_assert_eq!(round_trip(&k), k);
+        }
+
+        #[test]
+        fn floats(a in any::<f32>(), b in any::<f64>()) {
+            // compared bitwise, so NaN payloads have to survive as well
+            prop_assert_eq!(round_trip(&a).to_bits(), a.to_bits());
+            prop_assert_eq!(round_trip(&b).to_bits(), b.to_bits());
+        }
+
+        #[test]
+        fn var_numbers(a in any::<i32>(), b in any::<i64>()) {
+            prop_assert_eq!(round_trip(&VarInt(a)), VarInt(a));
+            prop_assert_eq!(round_trip(&VarLong(b)), VarLong(b));
+        }
+
+        #[test]
+        fn strings(value in string()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn options(
+            a in proptest::option::of(any::<i32>()),
+            b in proptest::option::of(string()),
+            c in proptest::option::of(proptest::option::of(any::<i64>().prop_map(VarLong))),
+        ) {
+            prop_assert_eq!(round_trip(&a), a);
+            prop_assert_eq!(round_trip(&b), b);
+            prop_assert_eq!(round_trip(&c), c);
+        }
+
+        #[test]
+        fn vecs(
+            a in proptest::collection::vec(any::<u8>(), 0..512),
+            b in proptest::collection::vec(any::<i32>().prop_map(VarInt), 0..128),
+            c in proptest::collection::vec(string(), 0..16),
+            d in proptest::collection::vec(proptest::option::of(any::<i64>()), 0..64),
+            e in proptest::collection::vec(proptest::collection::vec(any::<bool>(), 0..8), 0..8),
+        ) {
+            prop_assert_eq!(round_trip(&a), a);
+            prop_assert_eq!(round_trip(&b), b);
+            prop_assert_eq!(round_trip(&c), c);
+            prop_assert_eq!(round_trip(&d), d);
+            prop_assert_eq!(round_trip(&e), e);
+        }
+    }
+}
@@ -15,9 +15,9 @@ pub trait Identified {
 lazy_static::lazy_static! {
     pub static ref SOULFLAME_NAMESPACE: String = "soulflame".into();
     pub static ref MINECRAFT_NAMESPACE: String = "minecraft".into();
-    pub static ref NAMESPACE_RE: Regex = Regex::new(r"[a-z\d.-_]+").unwrap();
-    pub static ref PATH_RE: Regex = Regex::new(r"[a-z\d.-_/]+").unwrap();
-    pub static ref FULL_RE: Regex = Regex::new(r"([a-z\d.-_]+):([a-z\d.-_/]+)").unwrap();
+    pub static ref NAMESPACE_RE: Regex = Regex::new(r"^[a-z0-9._-]+$").unwrap();
+    pub static ref PATH_RE: Regex = Regex::new(r"^[a-z0-9._/-]+$").unwrap();
+    pub static ref FULL_RE: Regex = Regex::new(r"^([a-z0-9._-]+):([a-z0-9._/-]+)$").unwrap();
 }
 
 impl Identifier {
@@ -29,7 +29,7 @@ impl Identifier {
                 ns
             } else {
                 bail!(
-                    "Identifier namespace '{}' does not follow allowed pattern ([a-z\\d.-_]+)!",
+                    "Identifier namespace '{}' does not follow allowed pattern ([a-z0-9._-]+)!",
                     ns
                 )
             },
@@ -37,7 +37,7 @@ impl Identifier {
                 p
             } else {
                 bail!(
-                    "Identifier path '{}' does not follow allowed pattern ([a-z\\d.-_/]+)!",
+                    "Identifier path '{}' does not follow allowed pattern ([a-z0-9._/-]+)!",
                     p
                 )
             },
@@ -52,7 +52,7 @@ impl Identifier {
                 p
             } else {
                 bail!(
-                    "Identifier path '{}' does not follow allowed pattern ([a-z\\d.-_/]+)!",
+                    "Identifier path '{}' does not follow allowed pattern ([a-z0-9._/-]+)!",
                     p
                 )
             },
@@ -67,7 +67,7 @@ impl Identifier {
                 p
             } else {
                 bail!(
-                    "Identifier path '{}' does not follow allowed pattern ([a-z\\d.-_/]+)!",
+                    "Identifier path '{}' does not follow allowed pattern ([a-z0-9._/-]+)!",
                     p
                 )
             },
@@ -87,14 +87,14 @@ impl Identifier {
         let matches = FULL_RE.captures(&text);
         if let Some(captures) = matches {
             let namespace = captures
-                .get(0)
+                .get(1)
                 .ok_or_else(|| anyhow::Error::msg("Could not match identifier namespace!"))?;
             let path = captures
-                .get(1)
+                .get(2)
                 .ok_or_else(|| anyhow::Error::msg("Could not match identifier path!"))?;
             Identifier::new(namespace.as_str(), path.as_str())
         } else {
-            bail!("Invalid identifier provided in string '{}'! Should follow pattern '[a-z\\d.-_]+:[a-z\\d.-_/]+'!", text);
+            bail!("Invalid identifier provided in string '{}'! Should follow pattern '[a-z0-9._-]+:[a-z0-9._/-]+'!", text);
         }
     }
 }
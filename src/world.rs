@@ -1 +1,5 @@
 pub mod block;
+pub mod border;
+pub mod entity;
+pub mod item;
+pub mod team;
@@ -36,6 +36,12 @@ impl Location {
         }
     }
 
+    /// Squared distance between both positions, ignoring rotation.
+    pub fn distance_squared(&self, other: &Location) -> f32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        dx * dx + dy * dy + dz * dz
+    }
+
     pub fn x(&self) -> f32 {
         self.x.clone()
     }
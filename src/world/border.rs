@@ -0,0 +1,108 @@
+use crate::protocol::server::play::{
+    PacketPlayOutInitializeWorldBorder, PacketPlayOutSetBorderCenter,
+    PacketPlayOutSetBorderLerpSize, PacketPlayOutSetBorderSize, PacketPlayOutSetBorderWarningDelay,
+    PacketPlayOutSetBorderWarningDistance,
+};
+
+/// Diameter of the border in a new vanilla world.
+pub const DEFAULT_BORDER_DIAMETER: f64 = 59_999_968.0;
+
+/// Distance from the origin portals can teleport entities to, the vanilla default.
+pub const DEFAULT_PORTAL_TELEPORT_BOUNDARY: i32 = 29_999_984;
+
+/// Builder for the world border, producing the packets to show it on clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldBorder {
+    x: f64,
+    z: f64,
+    diameter: f64,
+    portal_teleport_boundary: i32,
+    warning_blocks: i32,
+    warning_time: i32,
+}
+
+impl Default for WorldBorder {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            z: 0.0,
+            diameter: DEFAULT_BORDER_DIAMETER,
+            portal_teleport_boundary: DEFAULT_PORTAL_TELEPORT_BOUNDARY,
+            warning_blocks: 5,
+            warning_time: 15,
+        }
+    }
+}
+
+impl WorldBorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn center(mut self, x: f64, z: f64) -> Self {
+        self.x = x;
+        self.z = z;
+        self
+    }
+
+    pub fn diameter(mut self, diameter: f64) -> Self {
+        self.diameter = diameter;
+        self
+    }
+
+    pub fn portal_teleport_boundary(mut self, boundary: i32) -> Self {
+        self.portal_teleport_boundary = boundary;
+        self
+    }
+
+    /// Distance to the border in blocks at which the screen starts tinting red.
+    pub fn warning_blocks(mut self, blocks: i32) -> Self {
+        self.warning_blocks = blocks;
+        self
+    }
+
+    /// Seconds before a shrinking border arrives at which the screen starts tinting red.
+    pub fn warning_time(mut self, seconds: i32) -> Self {
+        self.warning_time = seconds;
+        self
+    }
+
+    pub fn current_diameter(&self) -> f64 {
+        self.diameter
+    }
+
+    /// Sends the whole border state, e.g. when a player joins.
+    pub fn initialize(&self) -> PacketPlayOutInitializeWorldBorder {
+        PacketPlayOutInitializeWorldBorder::new(
+            self.x,
+            self.z,
+            self.diameter,
+            self.diameter,
+            0,
+            self.portal_teleport_boundary,
+            self.warning_blocks,
+            self.warning_time,
+        )
+    }
+
+    pub fn set_center(&self) -> PacketPlayOutSetBorderCenter {
+        PacketPlayOutSetBorderCenter::new(self.x, self.z)
+    }
+
+    pub fn set_size(&self) -> PacketPlayOutSetBorderSize {
+        PacketPlayOutSetBorderSize::new(self.diameter)
+    }
+
+    /// Moves the border from its diameter to the new one over the given milliseconds.
+    pub fn lerp_size(&self, new_diameter: f64, millis: i64) -> PacketPlayOutSetBorderLerpSize {
+        PacketPlayOutSetBorderLerpSize::new(self.diameter, new_diameter, millis)
+    }
+
+    pub fn set_warning_delay(&self) -> PacketPlayOutSetBorderWarningDelay {
+        PacketPlayOutSetBorderWarningDelay::new(self.warning_time)
+    }
+
+    pub fn set_warning_distance(&self) -> PacketPlayOutSetBorderWarningDistance {
+        PacketPlayOutSetBorderWarningDistance::new(self.warning_blocks)
+    }
+}
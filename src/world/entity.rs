@@ -0,0 +1,126 @@
+use crate::net_io::{Angle, VarInt};
+use crate::protocol::server::play::{PacketPlayOutEntityHeadLook, PacketPlayOutSetPassengers};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+/// Server-side record of which entities are riding which vehicles.
+#[derive(Debug, Clone, Default)]
+pub struct Passengers {
+    vehicles: HashMap<i32, Vec<i32>>,
+}
+
+impl Passengers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn passengers(&self, vehicle: i32) -> &[i32] {
+        self.vehicles
+            .get(&vehicle)
+            .map(|riders| riders.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn vehicle_of(&self, passenger: i32) -> Option<i32> {
+        self.vehicles
+            .iter()
+            .find(|(_, riders)| riders.contains(&passenger))
+            .map(|(vehicle, _)| *vehicle)
+    }
+
+    /// Mounts the passenger onto the vehicle, leaving any vehicle it was riding before.
+    ///
+    /// Returns the packets announcing the remaining passengers of the previous vehicle, if it was
+    /// riding another one, followed by the new passengers of the vehicle.
+    pub fn mount(&mut self, vehicle: i32, passenger: i32) -> Vec<PacketPlayOutSetPassengers> {
+        let mut packets = vec![];
+        if let Some(left) = self.dismount(passenger) {
+            if *left.vehicle_id() != vehicle {
+                packets.push(left);
+            }
+        }
+        self.vehicles.entry(vehicle).or_default().push(passenger);
+        packets.push(self.packet(vehicle));
+        packets
+    }
+
+    /// Dismounts the passenger from its vehicle.
+    ///
+    /// Returns the packet announcing the remaining passengers, if the passenger was riding anything.
+    pub fn dismount(&mut self, passenger: i32) -> Option<PacketPlayOutSetPassengers> {
+        let vehicle = self.vehicle_of(passenger)?;
+        if let Some(riders) = self.vehicles.get_mut(&vehicle) {
+            riders.retain(|rider| *rider != passenger);
+            if riders.is_empty() {
+                self.vehicles.remove(&vehicle);
+            }
+        }
+        Some(self.packet(vehicle))
+    }
+
+    fn packet(&self, vehicle: i32) -> PacketPlayOutSetPassengers {
+        PacketPlayOutSetPassengers::new(
+            vehicle,
+            self.passengers(vehicle)
+                .iter()
+                .map(|id| VarInt(*id))
+                .collect(),
+        )
+    }
+}
+
+/// Last head rotation sent for each entity, tracked separately from the body rotation.
+#[derive(Debug, Clone, Default)]
+pub struct HeadRotations {
+    heads: HashMap<i32, Angle>,
+}
+
+impl HeadRotations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn head_yaw(&self, entity: i32) -> Option<Angle> {
+        self.heads.get(&entity).copied()
+    }
+
+    /// Turns the head of the entity.
+    ///
+    /// Returns the head look packet if the rotation differs from the one last sent.
+    pub fn turn_head(&mut self, entity: i32, head_yaw: f32) -> Option<PacketPlayOutEntityHeadLook> {
+        let angle = Angle::from_degrees(head_yaw);
+        if self.heads.insert(entity, angle) == Some(angle) {
+            return None;
+        }
+        Some(PacketPlayOutEntityHeadLook::new(entity, angle))
+    }
+
+    pub fn forget(&mut self, entity: i32) {
+        self.heads.remove(&entity);
+    }
+}
+
+/// Hands out entity ids that are unique for the lifetime of the server.
+#[derive(Debug, Clone)]
+pub struct EntityIdAllocator {
+    next: Arc<AtomicI32>,
+}
+
+impl EntityIdAllocator {
+    pub fn new() -> Self {
+        Self {
+            next: Arc::new(AtomicI32::new(1)),
+        }
+    }
+
+    pub fn allocate(&self) -> i32 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Default for EntityIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::net_io::{PacketRead, PacketWrite, VarInt};
+use async_trait::async_trait;
+use nbt::Blob;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tokio::io::AsyncReadExt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemStack {
+    item_id: i32,
+    count: i8,
+    nbt: Option<Blob>,
+}
+
+impl ItemStack {
+    pub fn new(item_id: i32, count: i8, nbt: Option<Blob>) -> Self {
+        Self {
+            item_id,
+            count,
+            nbt,
+        }
+    }
+
+    pub fn item_id(&self) -> i32 {
+        self.item_id
+    }
+
+    pub fn count(&self) -> i8 {
+        self.count
+    }
+
+    pub fn nbt(&self) -> Option<&Blob> {
+        self.nbt.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Slot(pub Option<ItemStack>);
+
+impl Slot {
+    pub fn empty() -> Self {
+        Slot(None)
+    }
+
+    pub fn item(item_id: i32, count: i8) -> Self {
+        Slot(Some(ItemStack::new(item_id, count, None)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+#[async_trait]
+impl PacketWrite for Slot {
+    async fn pack_write(&self, buffer: &mut Vec<u8>, target_version: u32) -> anyhow::Result<()> {
+        match &self.0 {
+            Some(stack) => {
+                true.pack_write(buffer, target_version).await?;
+                VarInt(stack.item_id)
+                    .pack_write(buffer, target_version)
+                    .await?;
+                stack.count.pack_write(buffer, target_version).await?;
+                match &stack.nbt {
+                    Some(nbt) => nbt.to_writer(buffer)?,
+                    // TAG_End marks a missing tag
+                    None => buffer.push(0),
+                }
+            }
+            None => false.pack_write(buffer, target_version).await?,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PacketRead for Slot {
+    async fn pack_read(buffer: &mut Cursor<&[u8]>, target_version: u32) -> anyhow::Result<Self> {
+        if !bool::pack_read(buffer, target_version).await? {
+            return Ok(Slot(None));
+        }
+
+        let item_id = VarInt::pack_read(buffer, target_version).await?.0;
+        let count = i8::pack_read(buffer, target_version).await?;
+
+        let start = buffer.position();
+        let nbt = if buffer.read_u8().await? == 0 {
+            None
+        } else {
+            buffer.set_position(start);
+            Some(Blob::from_reader(buffer)?)
+        };
+
+        Ok(Slot(Some(ItemStack::new(item_id, count, nbt))))
+    }
+}
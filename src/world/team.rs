@@ -0,0 +1,118 @@
+use crate::chat::Component;
+use crate::net_io::Flags8;
+use crate::protocol::server::play::{
+    CollisionRule, NameTagVisibility, PacketPlayOutTeams, TeamAction, TeamColor, TeamFlags,
+    TeamInfo,
+};
+
+/// Builder for scoreboard teams, producing the packets to manage them on clients.
+#[derive(Debug, Clone)]
+pub struct Team {
+    name: String,
+    display_name: Component,
+    flags: TeamFlags,
+    name_tag_visibility: NameTagVisibility,
+    collision_rule: CollisionRule,
+    color: TeamColor,
+    prefix: Component,
+    suffix: Component,
+}
+
+impl Team {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        let name = name.into();
+        Self {
+            display_name: Component::text(name.clone()),
+            name,
+            flags: TeamFlags::empty(),
+            name_tag_visibility: NameTagVisibility::Always,
+            collision_rule: CollisionRule::Always,
+            color: TeamColor::Reset,
+            prefix: Component::text(String::new()),
+            suffix: Component::text(String::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn display_name(mut self, display_name: Component) -> Self {
+        self.display_name = display_name;
+        self
+    }
+
+    pub fn friendly_fire(mut self, allow: bool) -> Self {
+        self.flags.set(TeamFlags::ALLOW_FRIENDLY_FIRE, allow);
+        self
+    }
+
+    pub fn see_invisible_teammates(mut self, see: bool) -> Self {
+        self.flags.set(TeamFlags::SEE_INVISIBLE_TEAMMATES, see);
+        self
+    }
+
+    pub fn name_tag_visibility(mut self, visibility: NameTagVisibility) -> Self {
+        self.name_tag_visibility = visibility;
+        self
+    }
+
+    pub fn collision_rule(mut self, rule: CollisionRule) -> Self {
+        self.collision_rule = rule;
+        self
+    }
+
+    pub fn color(mut self, color: TeamColor) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn prefix(mut self, prefix: Component) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    pub fn suffix(mut self, suffix: Component) -> Self {
+        self.suffix = suffix;
+        self
+    }
+
+    fn info(&self) -> TeamInfo {
+        TeamInfo::new(
+            self.display_name.clone(),
+            Flags8(self.flags),
+            self.name_tag_visibility,
+            self.collision_rule,
+            self.color,
+            self.prefix.clone(),
+            self.suffix.clone(),
+        )
+    }
+
+    pub fn create(&self, entities: Vec<String>) -> PacketPlayOutTeams {
+        self.packet(TeamAction::Create {
+            info: self.info(),
+            entities,
+        })
+    }
+
+    pub fn remove(&self) -> PacketPlayOutTeams {
+        self.packet(TeamAction::Remove {})
+    }
+
+    pub fn update(&self) -> PacketPlayOutTeams {
+        self.packet(TeamAction::UpdateInfo { info: self.info() })
+    }
+
+    pub fn add_entities(&self, entities: Vec<String>) -> PacketPlayOutTeams {
+        self.packet(TeamAction::AddEntities { entities })
+    }
+
+    pub fn remove_entities(&self, entities: Vec<String>) -> PacketPlayOutTeams {
+        self.packet(TeamAction::RemoveEntities { entities })
+    }
+
+    fn packet(&self, action: TeamAction) -> PacketPlayOutTeams {
+        PacketPlayOutTeams::new(self.name.clone(), action)
+    }
+}